@@ -15,5 +15,10 @@ rustPlatform.buildRustPackage rec {
     gtk4.dev
   ];
 
+  postInstall = ''
+    install -Dm644 data/wireguard-gui.desktop $out/share/applications/wireguard-gui.desktop
+    install -Dm644 data/wireguard-gui.xml $out/share/mime/packages/wireguard-gui.xml
+  '';
+
   cargoSha256 = "sha256-rV+GAOd3BmbMZKDKRDFNzrSbi5IqptNoFo9wHRDBPT0=";
 }
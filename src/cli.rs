@@ -0,0 +1,178 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use clap::Parser;
+
+use crate::utils::RetryPolicy;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Configuration file or archive imported once existing tunnels are loaded.
+    #[arg(long, value_name = "PATH")]
+    pub import: Option<PathBuf>,
+    /// Same as `--import`, used when opening files from a file manager.
+    #[arg(value_name = "FILE", conflicts_with = "import")]
+    pub file: Option<PathBuf>,
+    /// Path of the unix socket accepting JSON control commands. Disabled if not set.
+    #[arg(long)]
+    pub control_socket: Option<PathBuf>,
+    /// User that should own configuration files. Ownership is left unchanged if not set.
+    #[arg(long)]
+    pub config_owner: Option<String>,
+    /// Group that should own configuration files. Ownership is left unchanged if not set.
+    #[arg(long)]
+    pub config_group: Option<String>,
+    /// Number of `wg show` attempts when checking whether tunnel interface is running.
+    #[arg(long, default_value_t = 1)]
+    pub state_check_attempts: u32,
+    /// Delay in milliseconds before the second state check attempt. Doubles with every retry.
+    #[arg(long, default_value_t = 100)]
+    pub state_check_interval_ms: u64,
+    /// Show public keys of peers instead of hiding them until revealed.
+    #[arg(long)]
+    pub reveal_public_keys: bool,
+    /// Maximal number of peers shown at once in the overview.
+    #[arg(long, default_value_t = 25)]
+    pub peers_per_page: usize,
+    /// Seconds after which error notifications are dismissed. 0 keeps them until dismissed.
+    #[arg(long, default_value_t = 0)]
+    pub error_toast_timeout: u64,
+    /// Seconds after which informational notifications are dismissed. 0 keeps them until
+    /// dismissed.
+    #[arg(long, default_value_t = 0)]
+    pub info_toast_timeout: u64,
+    /// Bulk actions affecting more items than this ask for confirmation first.
+    #[arg(long, default_value_t = 5)]
+    pub bulk_confirm_threshold: usize,
+    /// Configuration files larger than this number of bytes are not loaded.
+    #[arg(long, default_value_t = 1024 * 1024)]
+    pub max_config_size: u64,
+    /// Replace invalid UTF-8 in configuration files with U+FFFD instead of refusing them.
+    #[arg(long)]
+    pub lossy_utf8: bool,
+    /// Seconds between background checks, such as transfer samples. 0 disables them.
+    #[arg(long, value_name = "SECONDS", default_value_t = 5)]
+    pub poll_interval: u32,
+    /// Seconds between transfer samples of the active tunnel shown in the overview. Defaults
+    /// to `--poll-interval`.
+    #[arg(long)]
+    pub stats_interval_secs: Option<u32>,
+    /// Minimal width of the main window in pixels.
+    #[arg(long, default_value_t = 360)]
+    pub min_window_width: i32,
+    /// Minimal height of the main window in pixels.
+    #[arg(long, default_value_t = 300)]
+    pub min_window_height: i32,
+    /// Maximal number of tunnel configurations loaded at startup.
+    #[arg(long, default_value_t = 500)]
+    pub max_tunnels: usize,
+    /// PersistentKeepalive above this number of seconds is accepted with a warning.
+    #[arg(long, default_value_t = 3600)]
+    pub max_keepalive: u16,
+    /// Drop AllowedIPs entries contained in other entries of the same peer on save.
+    #[arg(long)]
+    pub collapse_allowed_ips: bool,
+    /// Reject imported configurations missing required fields or containing malformed values,
+    /// reporting all problems at once.
+    #[arg(long)]
+    pub strict_import: bool,
+    /// Interface keys required by `--strict-import`.
+    #[arg(long, value_delimiter = ',', default_value = "PrivateKey,Address")]
+    pub required_interface_fields: Vec<String>,
+    /// Peer keys required by `--strict-import`.
+    #[arg(long, value_delimiter = ',', default_value = "PublicKey,AllowedIPs")]
+    pub required_peer_fields: Vec<String>,
+    /// Keep values of interface fields cleared in the editor as commented out lines, e.g.
+    /// `# Address = 10.0.0.1/24`.
+    #[arg(long)]
+    pub comment_out_cleared: bool,
+    /// Default AllowedIPs of client peers added to generated host configs. `{ip}` is replaced
+    /// with the client address.
+    #[arg(long, default_value = "{ip}")]
+    pub generator_peer_allowed_ips: String,
+}
+
+impl Args {
+    /// Path of the configuration that should be imported on launch.
+    pub fn import_path(&self) -> Option<&PathBuf> {
+        self.import.as_ref().or(self.file.as_ref())
+    }
+
+    /// Seconds between background checks, `None` if they are disabled.
+    pub fn poll_interval(&self) -> Option<u32> {
+        (self.poll_interval > 0).then_some(self.poll_interval)
+    }
+
+    /// Seconds between transfer samples, `None` if background checks are disabled.
+    pub fn stats_interval(&self) -> Option<u32> {
+        self.poll_interval()
+            .map(|poll| self.stats_interval_secs.unwrap_or(poll).max(1))
+    }
+
+    pub fn state_check_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            attempts: self.state_check_attempts,
+            interval: Duration::from_millis(self.state_check_interval_ms),
+        }
+    }
+}
+
+static ARGS: OnceLock<Args> = OnceLock::new();
+
+/// Parses command line arguments of the process. Should be called once at startup.
+pub fn init() {
+    ARGS.get_or_init(Args::parse);
+}
+
+/// Returns parsed command line arguments, falling back to defaults if [`init`] wasn't called.
+pub fn get_args() -> &'static Args {
+    ARGS.get_or_init(|| Args::parse_from([env!("CARGO_PKG_NAME")]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launch_import() {
+        let parse = |args: &[&str]| {
+            Args::try_parse_from([env!("CARGO_PKG_NAME")].iter().chain(args))
+                .map(|a| a.import_path().cloned())
+        };
+
+        assert_eq!(parse(&[]).unwrap(), None);
+        assert_eq!(
+            parse(&["--import", "wg0.conf"]).unwrap(),
+            Some(PathBuf::from("wg0.conf"))
+        );
+        assert_eq!(
+            parse(&["/home/user/Downloads/wg1.conf"]).unwrap(),
+            Some(PathBuf::from("/home/user/Downloads/wg1.conf"))
+        );
+        assert!(parse(&["--import", "wg0.conf", "wg1.conf"]).is_err());
+        assert!(parse(&["wg0.conf", "wg1.conf"]).is_err());
+    }
+
+    #[test]
+    fn poll_interval() {
+        let parse = |args: &[&str]| {
+            let args = Args::try_parse_from([env!("CARGO_PKG_NAME")].iter().chain(args)).unwrap();
+            (args.poll_interval(), args.stats_interval())
+        };
+
+        assert_eq!(parse(&[]), (Some(5), Some(5)));
+        assert_eq!(parse(&["--poll-interval", "10"]), (Some(10), Some(10)));
+        assert_eq!(
+            parse(&["--poll-interval", "10", "--stats-interval-secs", "2"]),
+            (Some(10), Some(2))
+        );
+        assert_eq!(parse(&["--stats-interval-secs", "0"]), (Some(5), Some(1)));
+        assert_eq!(
+            parse(&["--poll-interval", "0", "--stats-interval-secs", "2"]),
+            (None, None)
+        );
+        assert!(Args::try_parse_from([env!("CARGO_PKG_NAME"), "--poll-interval", "-1"]).is_err());
+    }
+}
@@ -1,24 +1,109 @@
 use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::os::unix::fs::{fchown, MetadataExt};
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use tar::{Builder, EntryType, Header, HeaderMode};
+use cidr::{IpCidr, IpInet};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
+use tar::{Archive, Builder, EntryType, Header, HeaderMode};
+
+/// Name of the archive entry listing sizes and hashes of all configurations in the archive.
+pub const MANIFEST_NAME: &str = "MANIFEST";
 
 /// Defines the VPN settings for the local node.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
 pub struct Interface {
+    /// `#` comment lines preceding the first section, other than metadata.
+    pub leading_comments: Vec<String>,
     pub name: Option<String>,
+    /// Category the tunnel is grouped under in the tunnel list.
+    pub tag: Option<String>,
+    /// UTC time tunnel was last brought up, e.g. `2024-05-01T12:00:00Z`.
+    pub last_connected: Option<String>,
+    /// File name of the routing script whose hooks were last appended.
+    pub routing_script: Option<String>,
     pub address: Option<String>,
     pub listen_port: Option<String>,
     pub private_key: Option<String>,
+    /// Public key of the interface, kept as metadata. Has to be derived from `private_key`.
+    pub public_key: Option<String>,
     pub dns: Option<String>,
     pub table: Option<String>,
+    pub fwmark: Option<String>,
     pub mtu: Option<String>,
     pub pre_up: Option<String>,
     pub post_up: Option<String>,
     pub pre_down: Option<String>,
     pub post_down: Option<String>,
+    /// Keys and values of cleared fields, written as commented out lines like
+    /// `# Address = 10.0.0.1/24` so they can be restored by editing the file.
+    pub cleared: Vec<(String, String)>,
+    /// Keys in order of their appearance in the parsed file, used to preserve the
+    /// ordering on write. Keys not listed here are written in canonical order.
+    pub key_order: Vec<String>,
+}
+
+impl Interface {
+    /// Field of a key that is read by wg-quick, metadata keys aren't included.
+    fn field_mut(&mut self, key: &str) -> Option<&mut Option<String>> {
+        Some(match key {
+            "Address" => &mut self.address,
+            "ListenPort" => &mut self.listen_port,
+            "PrivateKey" => &mut self.private_key,
+            "DNS" => &mut self.dns,
+            "Table" => &mut self.table,
+            "FwMark" => &mut self.fwmark,
+            "MTU" => &mut self.mtu,
+            "PreUp" => &mut self.pre_up,
+            "PostUp" => &mut self.post_up,
+            "PreDown" => &mut self.pre_down,
+            "PostDown" => &mut self.post_down,
+            _ => return None,
+        })
+    }
+
+    /// Clears field, remembering its value in [`Interface::cleared`].
+    pub fn comment_out(&mut self, key: &str) {
+        let Some(value) = self.field_mut(key).and_then(Option::take) else {
+            return;
+        };
+        self.cleared.retain(|(k, _)| k != key);
+        if !value.trim().is_empty() {
+            self.cleared.push((key.to_string(), value));
+        }
+    }
+
+    /// Forgets commented out values of fields that have a value again.
+    pub fn prune_cleared(&mut self) {
+        let mut cleared = std::mem::take(&mut self.cleared);
+        cleared.retain(|(key, _)| self.field_mut(key).is_some_and(|v| v.is_none()));
+        self.cleared = cleared;
+    }
+
+    /// Keys and values of the section in canonical order.
+    pub fn kvs(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("# Name", self.name.clone()),
+            ("# Tag", self.tag.clone()),
+            ("# LastConnected", self.last_connected.clone()),
+            ("# RoutingScript", self.routing_script.clone()),
+            ("Address", self.address.clone()),
+            ("ListenPort", self.listen_port.clone()),
+            ("PrivateKey", self.private_key.clone()),
+            ("# PublicKey", self.public_key.clone()),
+            ("DNS", self.dns.clone()),
+            ("Table", self.table.clone()),
+            ("FwMark", self.fwmark.clone()),
+            ("MTU", self.mtu.clone()),
+            ("PreUp", self.pre_up.clone()),
+            ("PostUp", self.post_up.clone()),
+            ("PreDown", self.pre_down.clone()),
+            ("PostDown", self.post_down.clone()),
+        ]
+    }
 }
 
 /// Defines the VPN settings for a remote peer capable of routing
@@ -33,7 +118,39 @@ pub struct Peer {
     pub allowed_ips: Option<String>,
     pub endpoint: Option<String>,
     pub public_key: Option<String>,
+    /// Symmetric key mixed into the handshake, see [`is_base64_key`].
+    pub preshared_key: Option<String>,
     pub persistent_keepalive: Option<String>,
+    /// Disabled peers are kept in the file with their section commented out, see
+    /// [`DISABLED_PEER_SECTION`].
+    pub disabled: bool,
+    /// See [`Interface::key_order`].
+    pub key_order: Vec<String>,
+}
+
+impl Peer {
+    /// Whether name, public key or endpoint contains the query, ignoring case. Empty query
+    /// matches every peer.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [&self.name, &self.public_key, &self.endpoint]
+            .into_iter()
+            .flatten()
+            .any(|v| v.to_lowercase().contains(&query))
+            || query.is_empty()
+    }
+
+    /// Keys and values of the section in canonical order.
+    pub fn kvs(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("# Name", self.name.clone()),
+            ("AllowedIPs", self.allowed_ips.clone()),
+            ("Endpoint", self.endpoint.clone()),
+            ("PublicKey", self.public_key.clone()),
+            ("PresharedKey", self.preshared_key.clone()),
+            ("PersistentKeepalive", self.persistent_keepalive.clone()),
+        ]
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
@@ -42,168 +159,713 @@ pub struct WireguardConfig {
     pub peers: Vec<Peer>,
 }
 
+/// Header of a disabled peer section. Every line of the section is commented out, so
+/// wg-quick ignores the peer.
+pub const DISABLED_PEER_SECTION: &str = "#[Peer]";
+
+/// Whether value has the form of a key, 32 bytes encoded in base64.
+pub fn is_base64_key(v: &str) -> bool {
+    let v = v.trim();
+    v.len() == 44
+        && v.ends_with('=')
+        && v[..43]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+}
+
 pub fn parse_config(s: &str) -> Result<WireguardConfig, String> {
+    parse_config_with_errors(s).map_err(|errors| errors.into_iter().next().unwrap_or_default())
+}
+
+/// Parses configuration like [`parse_config`], but continues past invalid lines to report
+/// every problem found in it. Sections may come in any order, e.g. peers before the interface.
+pub fn parse_config_with_errors(s: &str) -> Result<WireguardConfig, Vec<String>> {
     enum LineType {
         Section(String),
+        DisabledPeer,
         Attribute(String, String),
+        Comment(usize, String),
     }
 
+    let metadata_keys: Vec<&str> = Interface::default()
+        .kvs()
+        .into_iter()
+        .chain(Peer::default().kvs())
+        .map(|(k, _)| k)
+        .filter(|k| k.starts_with('#'))
+        .collect();
+    let is_metadata = |l: &str| {
+        l.split_once('=').is_some_and(|(key, _)| {
+            let key = key.split_whitespace().collect::<Vec<_>>().join(" ");
+            metadata_keys.iter().any(|k| k.eq_ignore_ascii_case(&key))
+        })
+    };
+
     let lexed_lines = s // remove_comments(s)
         .split('\n')
         .map(str::trim)
         .enumerate()
         .filter(|(_, l)| !l.is_empty())
         .map(|(i, l)| {
-            if l.starts_with('[') && l.ends_with(']') {
+            if l.eq_ignore_ascii_case(DISABLED_PEER_SECTION) {
+                Ok(LineType::DisabledPeer)
+            } else if l.starts_with('#') && !is_metadata(l) {
+                Ok(LineType::Comment(i, l.into()))
+            } else if l.starts_with('[') && l.ends_with(']') {
                 Ok(LineType::Section(l[1..l.len() - 1].trim().into()))
-            } else if let Some(pos) = l.chars().position(|c| c == '=') {
+            } else if let Some((key, value)) = l.split_once('=') {
+                // Whitespace inside of keys (e.g. `#\tName`) is collapsed to single space.
                 Ok(LineType::Attribute(
-                    l[0..pos].trim().into(),
-                    l[pos + 1..l.len()].trim().into(),
+                    key.split_whitespace().collect::<Vec<_>>().join(" "),
+                    value.trim().into(),
                 ))
             } else {
                 Err(format!("Couldn't parse line {}: `{}`", i + 1, l.trim()))
             }
         })
-        .collect::<Result<Vec<LineType>, String>>()?;
+        .collect::<Vec<Result<LineType, String>>>();
 
+    let mut errors = vec![];
     let mut cfg = WireguardConfig::default();
 
-    let mut it = lexed_lines.into_iter().peekable();
-
     // We can be either in interface section or in peer section
     let mut is_in_interface = false;
     let mut is_in_peer = false;
+    // Attributes of unknown sections are skipped, their section was already reported.
+    let mut is_in_unknown = false;
+    let mut seen_section = false;
 
     let mut tmp_peer = Peer::default();
 
-    while let Some(l) = it.next() {
+    for l in lexed_lines {
+        let l = match l {
+            Ok(l) => l,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+
+        // Lines of disabled peers are parsed with their comment sign removed.
+        let l = match l {
+            LineType::Comment(i, c) if is_in_peer && tmp_peer.disabled => {
+                match c[1..].split_once('=') {
+                    Some((key, value)) => LineType::Attribute(
+                        key.split_whitespace().collect::<Vec<_>>().join(" "),
+                        value.trim().into(),
+                    ),
+                    None => LineType::Comment(i, c),
+                }
+            }
+            l => l,
+        };
+
         match l {
-            LineType::Section(s) => match s.as_str() {
-                "Interface" => {
-                    is_in_interface = true;
-                    is_in_peer = false;
+            LineType::Comment(_, c) if !seen_section => cfg.interface.leading_comments.push(c),
+            LineType::Comment(i, c) if is_in_interface => {
+                let field = c[1..].split_once('=').and_then(|(key, value)| {
+                    let key = canonical_key(key.trim().to_string(), Interface::default().kvs());
+                    let mut interface = Interface::default();
+                    interface
+                        .field_mut(&key)
+                        .is_some()
+                        .then(|| (key, value.trim().to_string()))
+                });
+                match field {
+                    Some(field) => cfg.interface.cleared.push(field),
+                    None => errors.push(format!("Couldn't parse line {}: `{}`", i + 1, c)),
                 }
-                "Peer" => {
-                    is_in_interface = false;
-                    is_in_peer = true;
+            }
+            LineType::Comment(i, c) => {
+                errors.push(format!("Couldn't parse line {}: `{}`", i + 1, c));
+            }
+            LineType::Section(_) | LineType::DisabledPeer => {
+                seen_section = true;
+                // Peer section ends at the beginning of any other section, even if it's empty.
+                if is_in_peer {
+                    cfg.peers.push(std::mem::take(&mut tmp_peer));
                 }
-                i => return Err(format!("Unexpected interface name {}.", i)),
-            },
+
+                is_in_interface = false;
+                is_in_peer = false;
+                is_in_unknown = false;
+                let s = match l {
+                    LineType::Section(s) => s,
+                    _ => {
+                        tmp_peer.disabled = true;
+                        String::from("peer")
+                    }
+                };
+                match s.to_ascii_lowercase().as_str() {
+                    "interface" => is_in_interface = true,
+                    "peer" => is_in_peer = true,
+                    _ => {
+                        errors.push(format!("Unexpected interface name {}.", s));
+                        is_in_unknown = true;
+                    }
+                }
+            }
             LineType::Attribute(key, value) => {
                 if is_in_interface {
+                    let key = canonical_key(key, Interface::default().kvs());
                     match key.as_str() {
                         "# Name" => cfg.interface.name = Some(value),
-                        "Address" => cfg.interface.address = Some(value),
+                        "# Tag" => cfg.interface.tag = Some(value),
+                        "# LastConnected" => cfg.interface.last_connected = Some(value),
+                        "# RoutingScript" => cfg.interface.routing_script = Some(value),
+                        "Address" => append_list(&mut cfg.interface.address, value),
                         "ListenPort" => cfg.interface.listen_port = Some(value),
                         "PrivateKey" => cfg.interface.private_key = Some(value),
-                        "DNS" => cfg.interface.dns = Some(value),
+                        "# PublicKey" => cfg.interface.public_key = Some(value),
+                        "DNS" => append_list(&mut cfg.interface.dns, value),
                         "Table" => cfg.interface.table = Some(value),
+                        "FwMark" => cfg.interface.fwmark = Some(value),
                         "MTU" => cfg.interface.mtu = Some(value),
                         "PreUp" => cfg.interface.pre_up = Some(value),
                         "PostUp" => cfg.interface.post_up = Some(value),
                         "PreDown" => cfg.interface.pre_down = Some(value),
                         "PostDown" => cfg.interface.post_down = Some(value),
-                        k => return Err(format!("Unexpected Interface configuration key {}.", k)),
+                        k => {
+                            errors.push(format!("Unexpected Interface configuration key {}.", k));
+                            continue;
+                        }
                     }
+                    remember_key(&mut cfg.interface.key_order, key);
                 } else if is_in_peer {
+                    let key = canonical_key(key, Peer::default().kvs());
                     match key.as_str() {
                         "# Name" => tmp_peer.name = Some(value),
                         "AllowedIPs" => tmp_peer.allowed_ips = Some(value),
                         "Endpoint" => tmp_peer.endpoint = Some(value),
                         "PublicKey" => tmp_peer.public_key = Some(value),
-                        "PersistentKeepalive" => tmp_peer.persistent_keepalive = Some(value),
-                        k => return Err(format!("Unexpected Peer configuration key {}.", k)),
-                    };
-
-                    match it.peek() {
-                        Some(LineType::Section(_)) => {
-                            cfg.peers.push(tmp_peer.clone());
-                            tmp_peer = Peer::default();
+                        "PresharedKey" if is_base64_key(&value) => {
+                            tmp_peer.preshared_key = Some(value)
                         }
-                        None => {
-                            cfg.peers.push(tmp_peer.clone());
+                        "PresharedKey" => {
+                            errors.push(String::from(
+                                "Invalid PresharedKey, expected 32 bytes encoded in base64.",
+                            ));
+                            continue;
                         }
-                        _ => (),
-                    }
-                } else {
-                    return Err(format!("Unexpected attribute {}.", key));
+                        "PersistentKeepalive" => tmp_peer.persistent_keepalive = Some(value),
+                        k => {
+                            errors.push(format!("Unexpected Peer configuration key {}.", k));
+                            continue;
+                        }
+                    };
+                    remember_key(&mut tmp_peer.key_order, key);
+                } else if !is_in_unknown {
+                    errors.push(format!("Unexpected attribute {}.", key));
                 }
             }
         }
     }
 
-    Ok(cfg)
+    if is_in_peer {
+        cfg.peers.push(tmp_peer);
+    }
+
+    if errors.is_empty() {
+        Ok(cfg)
+    } else {
+        Err(errors)
+    }
 }
 
-pub fn write_config(c: &WireguardConfig) -> String {
-    let mut res = String::from("[Interface]\n");
-
-    let kvs = [
-        c.interface.name.clone().map(|v| ("# Name", v)),
-        c.interface.address.clone().map(|v| ("Address", v)),
-        c.interface.listen_port.clone().map(|v| ("ListenPort", v)),
-        c.interface.private_key.clone().map(|v| ("PrivateKey", v)),
-        c.interface.dns.clone().map(|v| ("DNS", v)),
-        c.interface.table.clone().map(|v| ("Table", v)),
-        c.interface.mtu.clone().map(|v| ("MTU", v)),
-        c.interface.pre_up.clone().map(|v| ("PreUp", v)),
-        c.interface.post_up.clone().map(|v| ("PostUp", v)),
-        c.interface.pre_down.clone().map(|v| ("PreDown", v)),
-        c.interface.post_down.clone().map(|v| ("PostDown", v)),
-    ];
-
-    for (key, value) in kvs.into_iter().flatten() {
-        res.push_str(key);
-        res.push_str(" = ");
-        res.push_str(value.as_str());
-        res.push('\n');
+/// Parses fragment of configuration consisting of a single `[Peer]` section.
+pub fn parse_peer_fragment(s: &str) -> Result<Peer, String> {
+    let cfg = parse_config_with_errors(s).map_err(|errors| errors.join("\n"))?;
+
+    let leading_comments = cfg.interface.leading_comments.clone();
+    if cfg.interface
+        != (Interface {
+            leading_comments,
+            ..Default::default()
+        })
+    {
+        return Err(String::from(
+            "Peer fragment must not contain [Interface] section.",
+        ));
+    }
+    let [peer] = <[Peer; 1]>::try_from(cfg.peers).map_err(|peers| {
+        format!(
+            "Peer fragment must contain exactly one [Peer] section, found {}.",
+            peers.len()
+        )
+    })?;
+    if peer.public_key.is_none() {
+        return Err(String::from("Peer has no PublicKey."));
     }
-    res.push('\n');
 
-    for peer in c.peers.iter() {
-        res.push_str("[Peer]\n");
-
-        let kvs = [
-            peer.name.clone().map(|v| ("# Name", v)),
-            peer.allowed_ips.clone().map(|v| ("AllowedIPs", v)),
-            peer.endpoint.clone().map(|v| ("Endpoint", v)),
-            peer.public_key.clone().map(|v| ("PublicKey", v)),
-            peer.persistent_keepalive
-                .clone()
-                .map(|v| ("PersistentKeepalive", v)),
-        ];
+    Ok(peer)
+}
+
+/// Peers of another configuration that can be appended to `existing`. Peers without
+/// PublicKey and ones whose key is already present, or repeats within `source`, are left out.
+pub fn peers_to_merge(existing: &[Peer], source: Vec<Peer>) -> Vec<Peer> {
+    let mut keys: Vec<String> = existing
+        .iter()
+        .filter_map(|p| p.public_key.as_deref())
+        .map(|k| k.trim().to_string())
+        .collect();
 
-        for (key, value) in kvs.into_iter().flatten() {
+    source
+        .into_iter()
+        .filter(|peer| {
+            let Some(key) = peer.public_key.as_deref().map(str::trim) else {
+                return false;
+            };
+            if key.is_empty() || keys.iter().any(|k| k == key) {
+                return false;
+            }
+            keys.push(key.to_string());
+            true
+        })
+        .collect()
+}
+
+/// Returns canonical spelling of the key if it matches one of known keys ignoring case.
+fn canonical_key(key: String, kvs: Vec<(&'static str, Option<String>)>) -> String {
+    kvs.into_iter()
+        .map(|(k, _)| k)
+        .find(|k| k.eq_ignore_ascii_case(&key))
+        .map_or(key, String::from)
+}
+
+/// Joins values of a key repeated on several lines into one comma separated list, like
+/// wg-quick does.
+fn append_list(field: &mut Option<String>, value: String) {
+    match field {
+        Some(list) if !list.trim().is_empty() => {
+            list.push_str(", ");
+            list.push_str(&value);
+        }
+        _ => *field = Some(value),
+    }
+}
+
+fn remember_key(order: &mut Vec<String>, key: String) {
+    if !order.contains(&key) {
+        order.push(key);
+    }
+}
+
+fn write_section(
+    res: &mut String,
+    kvs: Vec<(&'static str, Option<String>)>,
+    order: &[String],
+    cleared: &[(String, String)],
+) {
+    let ordered = order
+        .iter()
+        .filter_map(|key| kvs.iter().find(|(k, _)| k == key))
+        .chain(
+            kvs.iter()
+                .filter(|(k, _)| !order.iter().any(|key| key == k)),
+        );
+
+    for (key, value) in ordered {
+        if let Some(value) = value {
             res.push_str(key);
             res.push_str(" = ");
             res.push_str(value.as_str());
             res.push('\n');
         }
+    }
+    for (key, value) in cleared {
+        res.push_str(&format!("# {key} = {value}\n"));
+    }
+    res.push('\n');
+}
+
+pub fn write_config(c: &WireguardConfig) -> String {
+    let mut res = String::new();
+    for comment in &c.interface.leading_comments {
+        res.push_str(comment);
         res.push('\n');
     }
+    res.push_str("[Interface]\n");
+    write_section(
+        &mut res,
+        c.interface.kvs(),
+        &c.interface.key_order,
+        &c.interface.cleared,
+    );
+
+    for peer in c.peers.iter() {
+        if peer.disabled {
+            let mut section = String::new();
+            write_section(&mut section, peer.kvs(), &peer.key_order, &[]);
+            res.push_str(DISABLED_PEER_SECTION);
+            res.push('\n');
+            for line in section.lines() {
+                if !line.is_empty() {
+                    res.push('#');
+                }
+                res.push_str(line);
+                res.push('\n');
+            }
+        } else {
+            res.push_str("[Peer]\n");
+            write_section(&mut res, peer.kvs(), &peer.key_order, &[]);
+        }
+    }
+
+    res
+}
+
+/// Placeholder replacing secret keys in configurations that are shared.
+pub const REDACTED: &str = "REDACTED";
+
+/// Copy of configuration with PrivateKey and PresharedKey values replaced by [`REDACTED`],
+/// including ones in comments.
+pub fn redact_secrets(cfg: &WireguardConfig) -> WireguardConfig {
+    fn is_secret(key: &str) -> bool {
+        let key = key.trim_start_matches('#').trim();
+        key.eq_ignore_ascii_case("PrivateKey") || key.eq_ignore_ascii_case("PresharedKey")
+    }
+
+    let mut cfg = cfg.clone();
+    if cfg.interface.private_key.is_some() {
+        cfg.interface.private_key = Some(REDACTED.into());
+    }
+    for (key, value) in &mut cfg.interface.cleared {
+        if is_secret(key) {
+            *value = REDACTED.into();
+        }
+    }
+    for peer in &mut cfg.peers {
+        if peer.preshared_key.is_some() {
+            peer.preshared_key = Some(REDACTED.into());
+        }
+    }
+    for comment in &mut cfg.interface.leading_comments {
+        if let Some((key, _)) = comment.split_once('=').filter(|(k, _)| is_secret(k)) {
+            *comment = format!("{} = {REDACTED}", key.trim_end());
+        }
+    }
+    cfg
+}
+
+/// Lists differences between configurations field by field, e.g.
+/// `Interface ListenPort: 51820 -> 51821`. Peers are matched by their public keys.
+pub fn diff_configs(old: &WireguardConfig, new: &WireguardConfig) -> Vec<String> {
+    fn diff_section(
+        res: &mut Vec<String>,
+        section: &str,
+        old: Vec<(&str, Option<String>)>,
+        new: Vec<(&str, Option<String>)>,
+    ) {
+        let show = |v: &Option<String>| v.clone().unwrap_or_else(|| String::from("(unset)"));
+        for ((key, old), (_, new)) in old.iter().zip(&new) {
+            if old != new {
+                let key = key.trim_start_matches("# ");
+                res.push(format!("{section} {key}: {} -> {}", show(old), show(new)));
+            }
+        }
+    }
+    let peer_label = |peer: &Peer| {
+        let id = peer.name.as_deref().or(peer.public_key.as_deref());
+        format!("Peer {}", id.unwrap_or("without PublicKey"))
+    };
+
+    let mut res = vec![];
+    if old.interface.leading_comments != new.interface.leading_comments {
+        res.push(String::from("Leading comments changed"));
+    }
+    diff_section(
+        &mut res,
+        "Interface",
+        old.interface.kvs(),
+        new.interface.kvs(),
+    );
+
+    let mut matched = vec![false; new.peers.len()];
+    for old_peer in &old.peers {
+        let found = new
+            .peers
+            .iter()
+            .enumerate()
+            .position(|(i, p)| !matched[i] && p.public_key == old_peer.public_key);
+        match found {
+            Some(i) => {
+                matched[i] = true;
+                let label = peer_label(old_peer);
+                if old_peer.disabled != new.peers[i].disabled {
+                    let state = if new.peers[i].disabled {
+                        "disabled"
+                    } else {
+                        "enabled"
+                    };
+                    res.push(format!("{label} {state}"));
+                }
+                diff_section(&mut res, &label, old_peer.kvs(), new.peers[i].kvs());
+            }
+            None => res.push(format!("{} removed", peer_label(old_peer))),
+        }
+    }
+    for (peer, _) in new.peers.iter().zip(matched).filter(|(_, m)| !m) {
+        res.push(format!("{} added", peer_label(peer)));
+    }
 
     res
 }
 
+/// Builds configuration of the client behind `peer` of the `server` interface. Client's
+/// address is taken from host entries of peer's AllowedIPs and server becomes its only peer,
+/// routing server's networks.
+pub fn derive_client_config(
+    server: &Interface,
+    server_public_key: &str,
+    peer: &Peer,
+    client_private_key: &str,
+    endpoint_host: Option<&str>,
+) -> Result<WireguardConfig, String> {
+    let address = peer
+        .allowed_ips
+        .iter()
+        .flat_map(|ips| ips.split(','))
+        .map(str::trim)
+        .filter(|ip| ip.parse::<IpCidr>().is_ok_and(|ip| ip.is_host_address()))
+        .collect::<Vec<_>>();
+    if address.is_empty() {
+        return Err(String::from(
+            "Peer AllowedIPs don't contain a single address that could be used by the client",
+        ));
+    }
+
+    let allowed_ips = server
+        .address
+        .iter()
+        .flat_map(|ips| ips.split(','))
+        .map(|ip| {
+            ip.trim()
+                .parse::<IpInet>()
+                .map(|ip| ip.network().to_string())
+                .map_err(|_| format!("Could not parse interface Address `{}`", ip.trim()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let endpoint = match (endpoint_host, &server.listen_port) {
+        (Some(host), Some(port)) => Some(crate::utils::format_endpoint(
+            host,
+            crate::utils::parse_port(port)?,
+        )),
+        (Some(_), None) => {
+            return Err(String::from(
+                "Interface has no ListenPort to build the endpoint from",
+            ))
+        }
+        (None, _) => None,
+    };
+
+    Ok(WireguardConfig {
+        interface: Interface {
+            name: peer.name.clone(),
+            address: Some(address.join(", ")),
+            private_key: Some(client_private_key.to_string()),
+            dns: server.dns.clone(),
+            ..Default::default()
+        },
+        peers: vec![Peer {
+            name: server.name.clone(),
+            allowed_ips: (!allowed_ips.is_empty()).then(|| allowed_ips.join(", ")),
+            endpoint,
+            public_key: Some(server_public_key.to_string()),
+            preshared_key: peer.preshared_key.clone(),
+            ..Default::default()
+        }],
+    })
+}
+
+/// Whether AllowedIPs contain a default route, `0.0.0.0/0` or `::/0`, sending all traffic
+/// through the peer.
+pub fn routes_all_traffic(allowed_ips: &str) -> bool {
+    allowed_ips
+        .split(',')
+        .filter_map(|ip| ip.trim().parse::<IpCidr>().ok())
+        .any(|ip| ip.network_length() == 0)
+}
+
+fn is_full_tunnel(cfg: &WireguardConfig) -> bool {
+    cfg.peers
+        .iter()
+        .filter(|p| !p.disabled)
+        .any(|p| p.allowed_ips.as_deref().is_some_and(routes_all_traffic))
+}
+
+fn has_dns(interface: &Interface) -> bool {
+    interface
+        .dns
+        .as_deref()
+        .is_some_and(|d| !d.trim().is_empty())
+}
+
+/// Whether configuration routes all traffic through the tunnel and sets DNS, so wg-quick
+/// replaces the system resolver while it's up.
+pub fn overrides_system_dns(cfg: &WireguardConfig) -> bool {
+    is_full_tunnel(cfg) && has_dns(&cfg.interface)
+}
+
+/// Advisory warnings about configurations that are valid, but likely not what was intended.
+pub fn lint_config(cfg: &WireguardConfig) -> Vec<String> {
+    let mut warnings = vec![];
+
+    if is_full_tunnel(cfg) && !has_dns(&cfg.interface) {
+        warnings.push(String::from(
+            "All traffic is routed through the tunnel, but DNS isn't set. DNS queries may leak outside of the tunnel or stop resolving, consider setting DNS to a server reachable through it.",
+        ));
+    }
+
+    warnings
+}
+
+fn interface_addresses(interface: &Interface) -> Vec<IpInet> {
+    interface
+        .address
+        .iter()
+        .flat_map(|a| a.split(','))
+        .filter_map(|a| a.trim().parse().ok())
+        .collect()
+}
+
+/// Finds the first address in the subnet of the interface's first Address that isn't taken
+/// by the interface or by a single address entry in AllowedIPs of any of the peers.
+pub fn next_free_address(interface: &Interface, peers: &[Peer]) -> Result<IpCidr, String> {
+    let addresses = interface_addresses(interface);
+    let subnet = addresses
+        .first()
+        .ok_or("Interface has no Address to assign addresses from")?
+        .network();
+
+    let mut used: Vec<IpAddr> = addresses.iter().map(|a| a.address()).collect();
+    used.extend(
+        peers
+            .iter()
+            .flat_map(|p| p.allowed_ips.iter())
+            .flat_map(|ips| ips.split(','))
+            .filter_map(|ip| ip.trim().parse::<IpCidr>().ok())
+            .filter(|ip| ip.is_host_address())
+            .map(|ip| ip.first_address()),
+    );
+    // Network and broadcast addresses can't be assigned to hosts.
+    let reserved = subnet.is_ipv4() && subnet.network_length() < 31;
+
+    subnet
+        .iter()
+        .map(|a| a.address())
+        .filter(|a| !reserved || (*a != subnet.first_address() && *a != subnet.last_address()))
+        .find(|a| !used.contains(a))
+        .map(IpCidr::new_host)
+        .ok_or_else(|| format!("No free address left in {subnet}"))
+}
+
+/// Puts `address` into AllowedIPs in place of the single address entry from the interface's
+/// subnet, or appends it if there's none. Other entries are kept.
+pub fn with_assigned_address(
+    interface: &Interface,
+    allowed_ips: Option<&str>,
+    address: IpCidr,
+) -> String {
+    let subnet = interface_addresses(interface).first().map(|a| a.network());
+    let mut entries: Vec<String> = allowed_ips
+        .iter()
+        .flat_map(|ips| ips.split(','))
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .map(String::from)
+        .collect();
+    let assigned = entries.iter().position(|ip| {
+        ip.parse::<IpCidr>().is_ok_and(|ip| {
+            ip.is_host_address() && subnet.is_some_and(|s| s.contains(&ip.first_address()))
+        })
+    });
+    let address = format!("{address:#}");
+    match assigned {
+        Some(i) => entries[i] = address,
+        None => entries.push(address),
+    }
+    entries.join(", ")
+}
 
+/// Writes configurations into tar archive together with manifest. Archive is compressed
+/// with gzip if path ends with `.gz` or `.tgz`.
 pub fn write_configs_to_path(cfgs: Vec<WireguardConfig>, path: PathBuf) -> io::Result<()> {
+    write_file_atomically(&path, |file| {
+        if is_gzip_path(&path) {
+            write_archive(&cfgs, GzEncoder::new(file, Compression::default()))?.finish()?;
+        } else {
+            write_archive(&cfgs, file)?;
+        }
+        Ok(())
+    })
+}
+
+/// Writes file through a temporary one in the same directory which is then renamed over the
+/// target, so a crash mid-write never leaves it truncated. An existing target keeps its
+/// permissions and ownership, new files are only accessible by the owner.
+pub fn write_file_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut fs::File) -> io::Result<()>,
+) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+
+    write(tmp.as_file_mut())?;
+    if let Ok(metadata) = fs::metadata(path) {
+        let file = tmp.as_file();
+        file.set_permissions(metadata.permissions())?;
+        // Only root may change the owner, other users already own the file they replace.
+        if let Err(err) = fchown(file, Some(metadata.uid()), Some(metadata.gid())) {
+            if err.kind() != io::ErrorKind::PermissionDenied {
+                return Err(err);
+            }
+        }
+    }
+    tmp.as_file().sync_all()?;
+
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+fn is_gzip_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("gz" | "tgz")
+    )
+}
+
+fn write_archive<W: Write>(cfgs: &[WireguardConfig], w: W) -> io::Result<W> {
     let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
 
-    let file = fs::File::create(path)?;
-    let mut ar = Builder::new(file);
+    let mut ar = Builder::new(w);
     ar.mode(HeaderMode::Complete);
     let mut header = Header::new_gnu();
 
-    for (i, cfg) in cfgs.iter().enumerate() {
-        let mut name = cfg
-            .interface
-            .name
-            .clone()
-            .unwrap_or_else(|| format!("configuration-{i}"));
-        name.push_str(".conf");
-        let content = crate::config::write_config(cfg);
+    let entries: Vec<(String, String)> = cfgs
+        .iter()
+        .enumerate()
+        .map(|(i, cfg)| {
+            let mut name = cfg
+                .interface
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("configuration-{i}"));
+            name.push_str(".conf");
+            (name, crate::config::write_config(cfg))
+        })
+        .collect();
+    let manifest = write_manifest(entries.iter().map(|(n, c)| (n.as_str(), c.as_bytes())));
+
+    let all_entries = entries
+        .iter()
+        .map(|(n, c)| (n.as_str(), c.as_str()))
+        .chain(std::iter::once((MANIFEST_NAME, manifest.as_str())));
 
+    for (name, content) in all_entries {
         header.set_size(content.as_bytes().len().try_into().unwrap());
         header.set_entry_type(EntryType::Regular);
         header.set_mtime(time.as_secs());
@@ -212,7 +874,120 @@ pub fn write_configs_to_path(cfgs: Vec<WireguardConfig>, path: PathBuf) -> io::R
         ar.append_data(&mut header, name, content.as_bytes())?;
     }
 
-    ar.finish()
+    ar.into_inner()
+}
+
+/// Reads configurations from (optionally gzip compressed) tar archive, refusing archives
+/// whose content doesn't match the manifest.
+pub fn read_configs_from_path(path: &Path) -> io::Result<Vec<WireguardConfig>> {
+    let data = fs::read(path)?;
+
+    let reader: Box<dyn Read> = if data.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(data.as_slice()))
+    } else {
+        Box::new(data.as_slice())
+    };
+
+    let mut manifest = None;
+    let mut entries = vec![];
+
+    for entry in Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type() != EntryType::Regular {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut content = vec![];
+        entry.read_to_end(&mut content)?;
+
+        if name == MANIFEST_NAME {
+            manifest = Some(String::from_utf8_lossy(&content).into_owned());
+        } else {
+            entries.push((name, content));
+        }
+    }
+
+    let manifest =
+        manifest.ok_or_else(|| io::Error::other("Archive doesn't contain a manifest."))?;
+    verify_manifest(
+        &manifest,
+        entries.iter().map(|(n, c)| (n.as_str(), c.as_slice())),
+    )
+    .map_err(io::Error::other)?;
+
+    entries
+        .into_iter()
+        .map(|(name, content)| {
+            let content = String::from_utf8(content)
+                .map_err(|_| io::Error::other(format!("{name} is not a valid utf-8 file.")))?;
+            let mut cfg = parse_config_with_errors(&content)
+                .map_err(|e| io::Error::other(format!("{name}: {}", e.join("\n"))))?;
+            if cfg.interface.name.is_none() {
+                cfg.interface.name = Path::new(&name)
+                    .file_stem()
+                    .and_then(|n| n.to_str())
+                    .map(|n| n.to_owned());
+            }
+            Ok(cfg)
+        })
+        .collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Builds manifest with one `<sha256> <size> <name>` line per entry.
+pub fn write_manifest<'a>(entries: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> String {
+    entries
+        .into_iter()
+        .map(|(name, content)| format!("{} {} {}\n", sha256_hex(content), content.len(), name))
+        .collect()
+}
+
+/// Checks that entries are exactly the ones listed in the manifest, with matching sizes and
+/// hashes.
+pub fn verify_manifest<'a>(
+    manifest: &str,
+    entries: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+) -> Result<(), String> {
+    let mut expected = manifest
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let mut parts = l.splitn(3, ' ');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(hash), Some(size), Some(name)) => size
+                    .parse::<usize>()
+                    .map(|size| (name.to_owned(), (hash.to_owned(), size)))
+                    .map_err(|_| format!("Invalid manifest line `{l}`")),
+                _ => Err(format!("Invalid manifest line `{l}`")),
+            }
+        })
+        .collect::<Result<std::collections::HashMap<_, _>, _>>()?;
+
+    for (name, content) in entries {
+        let (hash, size) = expected
+            .remove(name)
+            .ok_or_else(|| format!("{name} is not listed in the manifest."))?;
+        if content.len() != size {
+            return Err(format!(
+                "{name} has size {} but manifest expects {size}.",
+                content.len()
+            ));
+        }
+        if sha256_hex(content) != hash {
+            return Err(format!("{name} doesn't match its hash in the manifest."));
+        }
+    }
+
+    match expected.keys().next() {
+        Some(name) => Err(format!("{name} is missing from the archive.")),
+        None => Ok(()),
+    }
 }
 
 pub fn get_value(f: &Option<String>) -> &str {
@@ -260,4 +1035,895 @@ PersistentKeepalive = 26
         let s = write_config(&cfg);
         assert_eq!(s, CONFIG);
     }
+
+    #[test]
+    fn preserve_order() {
+        const CONFIG: &str = "[Interface]
+PrivateKey = localPrivateKeyAbcAbcAbc=
+Address = 192.0.2.3/32
+# Name = node1.example.tld
+ListenPort = 51820
+
+[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+Endpoint = node1.example.tld:51820
+AllowedIPs = 192.0.2.1/24
+# Name = node2-node.example.tld
+
+";
+        let cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(write_config(&cfg), CONFIG);
+
+        // Keys added after parsing are written after the ones from the file.
+        let mut cfg = cfg;
+        cfg.interface.mtu = Some("1420".into());
+        cfg.peers[0].persistent_keepalive = Some("25".into());
+        assert_eq!(
+            write_config(&cfg),
+            CONFIG
+                .replace("ListenPort = 51820\n", "ListenPort = 51820\nMTU = 1420\n")
+                .replace(
+                    "# Name = node2-node.example.tld\n",
+                    "# Name = node2-node.example.tld\nPersistentKeepalive = 25\n"
+                )
+        );
+
+        // New configs use canonical order.
+        let new = WireguardConfig {
+            interface: Interface {
+                private_key: Some("localPrivateKeyAbcAbcAbc=".into()),
+                address: Some("192.0.2.3/32".into()),
+                ..Default::default()
+            },
+            peers: vec![],
+        };
+        assert_eq!(
+            write_config(&new),
+            "[Interface]\nAddress = 192.0.2.3/32\nPrivateKey = localPrivateKeyAbcAbcAbc=\n\n"
+        );
+    }
+
+    #[test]
+    fn write_is_stable() {
+        const CONFIG: &str = "
+[Interface]
+Address=10.0.0.1/24
+  PrivateKey   =   localPrivateKeyAbcAbcAbc=
+[Peer]
+[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+AllowedIPs = 10.0.0.2/32";
+        let cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(cfg.peers.len(), 2);
+        assert_eq!(cfg.peers[0], Peer::default());
+
+        let written = write_config(&cfg);
+        assert_eq!(
+            written,
+            "[Interface]
+Address = 10.0.0.1/24
+PrivateKey = localPrivateKeyAbcAbcAbc=
+
+[Peer]
+
+[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+AllowedIPs = 10.0.0.2/32
+
+"
+        );
+        assert_eq!(write_config(&parse_config(&written).unwrap()), written);
+    }
+
+    #[test]
+    fn odd_whitespace() {
+        const CONFIG: &str = "[Interface]
+Address = 10.0.0.1/24
+PrivateKey = localPrivateKeyAbcAbcAbc=
+PostUp = iptables -A FORWARD -i %i -j ACCEPT
+# Name = server
+
+[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+AllowedIPs = 10.0.0.2/32
+
+";
+        let expected = parse_config(CONFIG).unwrap();
+
+        let tabs = "\t[Interface]\t
+Address\t=\t10.0.0.1/24
+\tPrivateKey\t=\tlocalPrivateKeyAbcAbcAbc=
+PostUp\t= iptables -A FORWARD -i %i -j ACCEPT\t
+#\tName\t=\tserver
+
+[\tPeer\t]
+\t\tPublicKey\t= remotePublicKeyAbcAbcAbc=
+AllowedIPs\t\t=\t10.0.0.2/32
+";
+        assert_eq!(parse_config(tabs).unwrap(), expected);
+
+        let spaces = "[Interface]\r
+Address   =    10.0.0.1/24\r
+    PrivateKey=localPrivateKeyAbcAbcAbc=\r
+PostUp =   iptables -A FORWARD -i %i -j ACCEPT   \r
+#   Name   = server\r
+\r
+   [Peer]\r
+PublicKey      = remotePublicKeyAbcAbcAbc=\r
+  AllowedIPs =10.0.0.2/32\r
+";
+        let cfg = parse_config(spaces).unwrap();
+        assert_eq!(cfg, expected);
+        assert_eq!(write_config(&cfg), CONFIG);
+    }
+
+    #[test]
+    fn case_insensitive() {
+        const CONFIG: &str = "[interface]
+address = 10.0.0.1/24
+listenport = 51820
+PRIVATEKEY = localPrivateKeyAbcAbcAbc=
+# name = server
+
+[PEER]
+publicKey = remotePublicKeyAbcAbcAbc=
+AllowedIps = 10.0.0.2/32
+persistentKeepAlive = 25
+";
+        let cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(cfg.interface.listen_port.as_deref(), Some("51820"));
+        assert_eq!(cfg.interface.name.as_deref(), Some("server"));
+        assert_eq!(cfg.peers[0].allowed_ips.as_deref(), Some("10.0.0.2/32"));
+        assert_eq!(cfg.peers[0].persistent_keepalive.as_deref(), Some("25"));
+
+        assert_eq!(
+            write_config(&cfg),
+            "[Interface]
+Address = 10.0.0.1/24
+ListenPort = 51820
+PrivateKey = localPrivateKeyAbcAbcAbc=
+# Name = server
+
+[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+AllowedIPs = 10.0.0.2/32
+PersistentKeepalive = 25
+
+"
+        );
+
+        assert!(parse_config("[Interface]\nListenPorts = 1").is_err());
+    }
+
+    #[test]
+    fn client_config() {
+        let server = parse_config(
+            "[Interface]
+# Name = office
+Address = 10.0.0.1/24, fd00::1/64
+ListenPort = 51820
+PrivateKey = serverPrivateKey=
+DNS = 10.0.0.1
+
+[Peer]
+# Name = laptop
+PublicKey = oldClientPublicKey=
+AllowedIPs = 10.0.0.2/32, fd00::2/128, 192.168.7.0/24
+",
+        )
+        .unwrap();
+
+        let client = derive_client_config(
+            &server.interface,
+            "serverPublicKey=",
+            &server.peers[0],
+            "clientPrivateKey=",
+            Some("vpn.example.com"),
+        )
+        .unwrap();
+        assert_eq!(
+            write_config(&client),
+            "[Interface]
+# Name = laptop
+Address = 10.0.0.2/32, fd00::2/128
+PrivateKey = clientPrivateKey=
+DNS = 10.0.0.1
+
+[Peer]
+# Name = office
+AllowedIPs = 10.0.0.0/24, fd00::/64
+Endpoint = vpn.example.com:51820
+PublicKey = serverPublicKey=
+
+"
+        );
+
+        let client = derive_client_config(
+            &server.interface,
+            "serverPublicKey=",
+            &server.peers[0],
+            "clientPrivateKey=",
+            None,
+        )
+        .unwrap();
+        assert_eq!(client.peers[0].endpoint, None);
+
+        let routed_only = Peer {
+            allowed_ips: Some("192.168.7.0/24".into()),
+            ..Default::default()
+        };
+        assert!(derive_client_config(
+            &server.interface,
+            "serverPublicKey=",
+            &routed_only,
+            "clientPrivateKey=",
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn tag_roundtrip() {
+        const CONFIG: &str = "[Interface]
+# Name = office
+# Tag = Work
+Address = 10.0.0.2/24
+PrivateKey = localPrivateKeyAbcAbcAbc=
+
+";
+        let cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(cfg.interface.tag.as_deref(), Some("Work"));
+        assert_eq!(write_config(&cfg), CONFIG);
+
+        let cfg = parse_config("[Interface]\n# tag = Personal\n").unwrap();
+        assert_eq!(write_config(&cfg), "[Interface]\n# Tag = Personal\n\n");
+    }
+
+    #[test]
+    fn last_connected_roundtrip() {
+        const CONFIG: &str = "[Interface]
+# Name = office
+# LastConnected = 2024-05-01T12:00:00Z
+PrivateKey = localPrivateKeyAbcAbcAbc=
+
+";
+        let mut cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(
+            cfg.interface.last_connected.as_deref(),
+            Some("2024-05-01T12:00:00Z")
+        );
+        assert_eq!(write_config(&cfg), CONFIG);
+
+        cfg.interface.last_connected = Some("2024-06-02T08:30:00Z".into());
+        assert_eq!(
+            write_config(&cfg),
+            CONFIG.replace("2024-05-01T12:00:00Z", "2024-06-02T08:30:00Z")
+        );
+
+        let cfg = parse_config("[Interface]\nPrivateKey = a=\n").unwrap();
+        let mut with_time = cfg.clone();
+        with_time.interface.last_connected = Some("2024-05-01T12:00:00Z".into());
+        assert_eq!(
+            write_config(&with_time),
+            "[Interface]\nPrivateKey = a=\n# LastConnected = 2024-05-01T12:00:00Z\n\n"
+        );
+    }
+
+    #[test]
+    fn commented_out_fields() {
+        let mut cfg = parse_config(
+            "[Interface]
+Address = 10.0.0.1/24
+DNS = 10.0.0.53
+",
+        )
+        .unwrap();
+        cfg.interface.comment_out("Address");
+        cfg.interface.comment_out("DNS");
+        cfg.interface.comment_out("MTU");
+        cfg.interface.comment_out("# Name");
+        assert_eq!(cfg.interface.address, None);
+
+        let written = write_config(&cfg);
+        assert_eq!(
+            written,
+            "[Interface]
+# Address = 10.0.0.1/24
+# DNS = 10.0.0.53
+
+"
+        );
+        assert_eq!(
+            parse_config(&written).unwrap().interface.cleared,
+            cfg.interface.cleared
+        );
+
+        // Commenting out again keeps only the latest value.
+        cfg.interface.address = Some("10.0.0.2/24".into());
+        cfg.interface.comment_out("Address");
+        assert_eq!(
+            cfg.interface.cleared,
+            [
+                ("DNS".to_string(), "10.0.0.53".to_string()),
+                ("Address".to_string(), "10.0.0.2/24".to_string())
+            ]
+        );
+
+        // Setting a value again drops the commented out one.
+        cfg.interface.dns = Some("10.0.0.54".into());
+        cfg.interface.prune_cleared();
+        assert_eq!(
+            cfg.interface.cleared,
+            [("Address".to_string(), "10.0.0.2/24".to_string())]
+        );
+
+        let cfg = parse_config("[Interface]\n#  mtu=1420\n").unwrap();
+        assert_eq!(
+            cfg.interface.cleared,
+            [("MTU".to_string(), "1420".to_string())]
+        );
+        assert!(parse_config("[Interface]\n# Unknown = 1\n").is_err());
+        assert!(parse_config("[Interface]\n# Just a comment\n").is_err());
+    }
+
+    #[test]
+    fn secrets_redaction() {
+        const PRIVATE: &str = "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=";
+        const OLD_PRIVATE: &str = "oLdPrIvAtEkEy00000000000000000000000000000=";
+        const PRESHARED: &str = "pReShArEdKeY0000000000000000000000000000000=";
+        const PEER_PRESHARED: &str = "FpCyhws9cxwWoV4xELtfJvjJN+zQVRPISllRWgeopVE=";
+        let mut cfg = parse_config(&format!(
+            "# PresharedKey = {PRESHARED}
+# Generated for laptop
+[Interface]
+Address = 10.0.0.1/24
+PrivateKey = {PRIVATE}
+# PublicKey = hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=
+[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+PresharedKey = {PEER_PRESHARED}
+AllowedIPs = 10.0.0.2/32
+"
+        ))
+        .unwrap();
+        cfg.interface.cleared = vec![("PrivateKey".into(), OLD_PRIVATE.into())];
+
+        let written = write_config(&redact_secrets(&cfg));
+        for secret in [PRIVATE, OLD_PRIVATE, PRESHARED, PEER_PRESHARED] {
+            assert!(!written.contains(secret), "{secret} leaked:\n{written}");
+        }
+        assert!(written.contains("PrivateKey = REDACTED\n"));
+        assert!(written.contains("# PrivateKey = REDACTED\n"));
+        assert!(written.contains("# PresharedKey = REDACTED\n"));
+        assert!(written.contains("\nPresharedKey = REDACTED\n"));
+        assert!(written.contains("# Generated for laptop\n"));
+        assert!(written.contains("hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo="));
+        assert!(written.contains("remotePublicKeyAbcAbcAbc="));
+
+        let keyless = parse_config("[Interface]\nAddress = 10.0.0.1/24\n").unwrap();
+        assert_eq!(redact_secrets(&keyless), keyless);
+    }
+
+    #[test]
+    fn preshared_key() {
+        const CONFIG: &str = "[Interface]
+PrivateKey = dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=
+
+[Peer]
+PublicKey = hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=
+PresharedKey = FpCyhws9cxwWoV4xELtfJvjJN+zQVRPISllRWgeopVE=
+AllowedIPs = 10.0.0.2/32
+
+";
+        let cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(
+            cfg.peers[0].preshared_key.as_deref(),
+            Some("FpCyhws9cxwWoV4xELtfJvjJN+zQVRPISllRWgeopVE=")
+        );
+        assert_eq!(write_config(&cfg), CONFIG);
+
+        assert_eq!(
+            parse_config("[Peer]\nPublicKey = a\nPresharedKey = secret\n"),
+            Err(String::from(
+                "Invalid PresharedKey, expected 32 bytes encoded in base64."
+            ))
+        );
+        assert!(!is_base64_key(
+            "FpCyhws9cxwWoV4xELtfJvjJN+zQVRPISllRWgeopV=="
+        ));
+        assert!(!is_base64_key(
+            "FpCyhws9cxwWoV4xELtfJvjJN+zQVRPISllRWgeopVE"
+        ));
+    }
+
+    #[test]
+    fn routing_script_roundtrip() {
+        const CONFIG: &str = "[Interface]
+# Name = office
+# RoutingScript = office-routes.sh
+PostUp = ip route add 10.1.0.0/16 dev %i
+
+";
+        let cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(
+            cfg.interface.routing_script.as_deref(),
+            Some("office-routes.sh")
+        );
+        assert_eq!(write_config(&cfg), CONFIG);
+    }
+
+    #[test]
+    fn leading_comments() {
+        const CONFIG: &str = "# Office tunnel, maintained by IT.
+#
+#   Call +1 555 0100 before changing keys.
+[Interface]
+# Name = office
+PrivateKey = localPrivateKeyAbcAbcAbc=
+
+[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+
+";
+        let cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(
+            cfg.interface.leading_comments,
+            [
+                "# Office tunnel, maintained by IT.",
+                "#",
+                "#   Call +1 555 0100 before changing keys.",
+            ]
+        );
+        assert_eq!(cfg.interface.name.as_deref(), Some("office"));
+        assert_eq!(write_config(&cfg), CONFIG);
+
+        // Metadata before the first section is still rejected.
+        assert!(parse_config("# Name = office\n[Interface]\n").is_err());
+        // Comments elsewhere aren't preserved, so they are still rejected too.
+        assert!(parse_config("[Interface]\n# note\nPrivateKey = a=\n").is_err());
+        assert!(parse_peer_fragment("# From Bob\n[Peer]\nPublicKey = a=\n").is_ok());
+    }
+
+    #[test]
+    fn peer_search() {
+        let peer = Peer {
+            name: Some("Laptop".into()),
+            public_key: Some("remotePublicKeyAbcAbcAbc=".into()),
+            endpoint: Some("vpn.example.com:51820".into()),
+            ..Default::default()
+        };
+        assert!(peer.matches(""));
+        assert!(peer.matches("lap"));
+        assert!(peer.matches("KEYABC"));
+        assert!(peer.matches("example.com"));
+        assert!(!peer.matches("desktop"));
+        assert!(Peer::default().matches(""));
+        assert!(!Peer::default().matches("lap"));
+    }
+
+    #[test]
+    fn peer_fragment() {
+        let peer = parse_peer_fragment(
+            "
+[Peer]
+# Name = Laptop
+PublicKey = remotePublicKeyAbcAbcAbc=
+AllowedIPs = 10.0.0.2/32
+",
+        )
+        .unwrap();
+        assert_eq!(peer.name.as_deref(), Some("Laptop"));
+        assert_eq!(
+            peer.public_key.as_deref(),
+            Some("remotePublicKeyAbcAbcAbc=")
+        );
+        assert_eq!(peer.allowed_ips.as_deref(), Some("10.0.0.2/32"));
+
+        assert_eq!(
+            parse_peer_fragment("[Interface]\nListenPort = 51820\n[Peer]\nPublicKey = a="),
+            Err(String::from(
+                "Peer fragment must not contain [Interface] section."
+            ))
+        );
+        assert_eq!(
+            parse_peer_fragment("[Peer]\nPublicKey = a=\n[Peer]\nPublicKey = b="),
+            Err(String::from(
+                "Peer fragment must contain exactly one [Peer] section, found 2."
+            ))
+        );
+        assert_eq!(
+            parse_peer_fragment(""),
+            Err(String::from(
+                "Peer fragment must contain exactly one [Peer] section, found 0."
+            ))
+        );
+        assert_eq!(
+            parse_peer_fragment("[Peer]\nAllowedIPs = 10.0.0.2/32"),
+            Err(String::from("Peer has no PublicKey."))
+        );
+        assert!(parse_peer_fragment("PublicKey = a=").is_err());
+    }
+
+    #[test]
+    fn repeated_list_keys() {
+        let cfg = parse_config(
+            "[Interface]
+Address = 10.0.0.1/24
+DNS = 10.0.0.53
+Address = fd00::1/64
+DNS = 1.1.1.1, example.tld
+",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.interface.address.as_deref(),
+            Some("10.0.0.1/24, fd00::1/64")
+        );
+        assert_eq!(
+            cfg.interface.dns.as_deref(),
+            Some("10.0.0.53, 1.1.1.1, example.tld")
+        );
+
+        let written = write_config(&cfg);
+        assert_eq!(
+            written,
+            "[Interface]
+Address = 10.0.0.1/24, fd00::1/64
+DNS = 10.0.0.53, 1.1.1.1, example.tld
+
+"
+        );
+        assert_eq!(parse_config(&written).unwrap(), cfg);
+    }
+
+    #[test]
+    fn peer_merge() {
+        let target = parse_config(
+            "[Interface]
+[Peer]
+# Name = Laptop
+PublicKey = laptopKey=
+AllowedIPs = 10.0.0.2/32
+",
+        )
+        .unwrap();
+        let source = parse_config(
+            "[Interface]
+[Peer]
+# Name = Laptop, other config
+PublicKey =  laptopKey=
+AllowedIPs = 10.1.0.2/32
+[Peer]
+# Name = Phone
+PublicKey = phoneKey=
+AllowedIPs = 10.0.0.3/32
+[Peer]
+# Name = Phone again
+PublicKey = phoneKey=
+[Peer]
+# Name = Keyless
+AllowedIPs = 10.0.0.4/32
+",
+        )
+        .unwrap();
+
+        let merged = peers_to_merge(&target.peers, source.peers.clone());
+        assert_eq!(merged, [source.peers[1].clone()]);
+        assert_eq!(peers_to_merge(&source.peers, target.peers), []);
+    }
+
+    #[test]
+    fn peers_before_interface() {
+        let cfg = parse_config(
+            "[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+AllowedIPs = 10.0.0.2/32
+[Peer]
+PublicKey = otherPublicKeyAbcAbcAbc=
+[Interface]
+Address = 10.0.0.1/24
+PrivateKey = localPrivateKeyAbcAbcAbc=
+",
+        )
+        .unwrap();
+        assert_eq!(cfg.interface.address.as_deref(), Some("10.0.0.1/24"));
+        assert_eq!(
+            cfg.peers
+                .iter()
+                .map(|p| p.public_key.as_deref())
+                .collect::<Vec<_>>(),
+            [
+                Some("remotePublicKeyAbcAbcAbc="),
+                Some("otherPublicKeyAbcAbcAbc=")
+            ]
+        );
+        assert_eq!(cfg.peers[0].allowed_ips.as_deref(), Some("10.0.0.2/32"));
+        assert_eq!(
+            parse_config(&write_config(&cfg)),
+            Ok(cfg),
+            "written back with interface first"
+        );
+    }
+
+    #[test]
+    fn config_diff() {
+        let old = parse_config(
+            "[Interface]
+ListenPort = 51820
+MTU = 1420
+[Peer]
+# Name = laptop
+PublicKey = laptopPublicKeyAbcAbcAbc=
+AllowedIPs = 10.0.0.2/32
+[Peer]
+PublicKey = phonePublicKeyAbcAbcAbc=
+",
+        )
+        .unwrap();
+        assert_eq!(diff_configs(&old, &old), Vec::<String>::new());
+
+        let new = parse_config(
+            "# Synced by cron
+[Interface]
+ListenPort = 51821
+[Peer]
+PublicKey = tabletPublicKeyAbcAbcAbc=
+[Peer]
+# Name = laptop
+PublicKey = laptopPublicKeyAbcAbcAbc=
+AllowedIPs = 10.0.0.2/32, 10.0.1.0/24
+",
+        )
+        .unwrap();
+        assert_eq!(
+            diff_configs(&old, &new),
+            [
+                "Leading comments changed",
+                "Interface ListenPort: 51820 -> 51821",
+                "Interface MTU: 1420 -> (unset)",
+                "Peer laptop AllowedIPs: 10.0.0.2/32 -> 10.0.0.2/32, 10.0.1.0/24",
+                "Peer phonePublicKeyAbcAbcAbc= removed",
+                "Peer tabletPublicKeyAbcAbcAbc= added",
+            ]
+        );
+    }
+
+    #[test]
+    fn full_tunnel_without_dns() {
+        let mut cfg = parse_config(
+            "[Interface]
+Address = 10.0.0.2/32
+[Peer]
+AllowedIPs = 10.0.0.0/24
+[Peer]
+AllowedIPs = 10.1.0.0/16, ::/0
+",
+        )
+        .unwrap();
+        assert!(routes_all_traffic(" ::/0"));
+        assert!(routes_all_traffic("10.0.0.0/8, 0.0.0.0/0"));
+        assert!(!routes_all_traffic("0.0.0.0/1, 128.0.0.0/1"));
+
+        assert_eq!(lint_config(&cfg).len(), 1);
+        assert!(!overrides_system_dns(&cfg));
+
+        cfg.interface.dns = Some(String::from("10.0.0.1"));
+        assert_eq!(lint_config(&cfg), Vec::<String>::new());
+        assert!(overrides_system_dns(&cfg));
+
+        cfg.interface.dns = None;
+        cfg.peers[1].disabled = true;
+        assert_eq!(lint_config(&cfg), Vec::<String>::new());
+    }
+
+    #[test]
+    fn free_address() {
+        let interface = Interface {
+            address: Some(String::from("10.0.0.1/29, fd00::1/64")),
+            ..Default::default()
+        };
+        let peer = |allowed_ips: &str| Peer {
+            allowed_ips: Some(allowed_ips.to_string()),
+            ..Default::default()
+        };
+        let next = |peers: &[Peer]| next_free_address(&interface, peers).map(|a| a.to_string());
+
+        assert_eq!(next(&[]), Ok(String::from("10.0.0.2")));
+        // Only single address entries take addresses.
+        assert_eq!(
+            next(&[peer("10.0.0.2/32, 192.168.0.0/16"), peer("10.0.0.0/29")]),
+            Ok(String::from("10.0.0.3"))
+        );
+        assert_eq!(
+            next(&[peer("10.0.0.2/32, 10.0.0.3"), peer("10.0.0.5/32")]),
+            Ok(String::from("10.0.0.4"))
+        );
+        assert_eq!(
+            next(&[peer("10.0.0.2, 10.0.0.3, 10.0.0.4, 10.0.0.5, 10.0.0.6")]),
+            Err(String::from("No free address left in 10.0.0.0/29"))
+        );
+
+        let interface = Interface {
+            address: Some(String::from("fd00::1/127")),
+            ..Default::default()
+        };
+        assert_eq!(
+            next_free_address(&interface, &[]).map(|a| a.to_string()),
+            Ok(String::from("fd00::"))
+        );
+        assert!(next_free_address(&Interface::default(), &[]).is_err());
+    }
+
+    #[test]
+    fn assigned_address() {
+        let interface = Interface {
+            address: Some(String::from("10.0.0.1/24")),
+            ..Default::default()
+        };
+        let address = IpCidr::new_host("10.0.0.3".parse().unwrap());
+        let assign = |ips| with_assigned_address(&interface, ips, address);
+
+        assert_eq!(assign(None), "10.0.0.3/32");
+        assert_eq!(
+            assign(Some("10.0.0.2/32, 192.168.1.0/24")),
+            "10.0.0.3/32, 192.168.1.0/24"
+        );
+        assert_eq!(
+            assign(Some("192.168.1.7/32, fd00::2/128")),
+            "192.168.1.7/32, fd00::2/128, 10.0.0.3/32"
+        );
+        assert_eq!(assign(Some("10.0.0.0/24")), "10.0.0.0/24, 10.0.0.3/32");
+    }
+
+    #[test]
+    fn disabled_peers() {
+        const CONFIG: &str = "[Interface]
+PrivateKey = localPrivateKeyAbcAbcAbc=
+
+#[Peer]
+## Name = laptop
+#PublicKey = laptopPublicKeyAbcAbcAbc=
+#AllowedIPs = 10.0.0.2/32
+
+[Peer]
+PublicKey = phonePublicKeyAbcAbcAbc=
+
+#[Peer]
+#PublicKey = tabletPublicKeyAbcAbcAbc=
+
+";
+        let cfg = parse_config(CONFIG).unwrap();
+        assert_eq!(
+            cfg.peers.iter().map(|p| p.disabled).collect::<Vec<_>>(),
+            [true, false, true]
+        );
+        assert_eq!(cfg.peers[0].name.as_deref(), Some("laptop"));
+        assert_eq!(cfg.peers[0].allowed_ips.as_deref(), Some("10.0.0.2/32"));
+        assert_eq!(write_config(&cfg), CONFIG);
+
+        let mut enabled = cfg.clone();
+        enabled.peers[0].disabled = false;
+        assert!(write_config(&enabled).contains("[Peer]\n# Name = laptop\n"));
+        assert_eq!(diff_configs(&cfg, &enabled), ["Peer laptop enabled"]);
+
+        // Comments inside of disabled peers still have to be attributes.
+        assert_eq!(
+            parse_config("[Interface]\n#[Peer]\n#PublicKey = x=\n# note").unwrap_err(),
+            "Couldn't parse line 4: `# note`"
+        );
+    }
+
+    #[test]
+    fn all_errors() {
+        const CONFIG: &str = "Address = 10.0.0.1/24
+[Interface]
+PrivateKey = localPrivateKeyAbcAbcAbc=
+ListenPorts = 51820
+garbage line
+[Peers]
+PublicKey = ignoredPublicKey=
+[Peer]
+PublicKey = remotePublicKeyAbcAbcAbc=
+Keepalive = 25
+";
+        assert_eq!(
+            parse_config_with_errors(CONFIG).unwrap_err(),
+            [
+                "Unexpected attribute Address.",
+                "Unexpected Interface configuration key ListenPorts.",
+                "Couldn't parse line 5: `garbage line`",
+                "Unexpected interface name Peers.",
+                "Unexpected Peer configuration key Keepalive.",
+            ]
+        );
+        assert_eq!(
+            parse_config(CONFIG).unwrap_err(),
+            "Unexpected attribute Address."
+        );
+
+        assert!(parse_config_with_errors("[Interface]\nAddress = 10.0.0.1/24").is_ok());
+    }
+
+    #[test]
+    fn manifest() {
+        let entries = [
+            ("wg0.conf", "[Interface]\n".as_bytes()),
+            ("wg1.conf", "[Interface]\nMTU = 1420\n".as_bytes()),
+        ];
+        let manifest = write_manifest(entries);
+        assert_eq!(manifest.lines().count(), 2);
+        assert!(manifest.ends_with(" 23 wg1.conf\n"));
+        assert_eq!(verify_manifest(&manifest, entries), Ok(()));
+
+        let tampered = [
+            ("wg0.conf", "[Interface]\n".as_bytes()),
+            ("wg1.conf", "[Interface]\nMTU = 1280\n".as_bytes()),
+        ];
+        assert!(verify_manifest(&manifest, tampered).is_err());
+
+        let truncated = [("wg1.conf", "[Interface]\nMTU = 14".as_bytes())];
+        assert!(verify_manifest(&manifest, truncated).is_err());
+
+        let missing = [("wg0.conf", "[Interface]\n".as_bytes())];
+        assert!(verify_manifest(&manifest, missing).is_err());
+
+        let unlisted = [
+            ("wg0.conf", "[Interface]\n".as_bytes()),
+            ("wg1.conf", "[Interface]\nMTU = 1420\n".as_bytes()),
+            ("wg2.conf", "[Interface]\n".as_bytes()),
+        ];
+        assert!(verify_manifest(&manifest, unlisted).is_err());
+    }
+
+    #[test]
+    fn archive_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfgs = vec![
+            parse_config("[Interface]\n# Name = wg0\nAddress = 10.0.0.1/24\n").unwrap(),
+            parse_config("[Interface]\n# Name = wg1\nAddress = 10.0.0.2/24\n").unwrap(),
+        ];
+
+        for name in ["configs.tar", "configs.tar.gz"] {
+            let path = dir.path().join(name);
+            write_configs_to_path(cfgs.clone(), path.clone()).unwrap();
+            assert_eq!(read_configs_from_path(&path).unwrap(), cfgs);
+        }
+
+        let path = dir.path().join("configs.tar");
+        let mut data = fs::read(&path).unwrap();
+        let pos = data
+            .windows(b"10.0.0.2".len())
+            .position(|w| w == b"10.0.0.2")
+            .unwrap();
+        data[pos + 7] = b'3';
+        fs::write(&path, &data).unwrap();
+        assert!(read_configs_from_path(&path).is_err());
+    }
+
+    #[test]
+    fn atomic_write() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wg0.conf");
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+
+        write_file_atomically(&path, |f| f.write_all(b"first")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+        assert_eq!(mode(&path), 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_file_atomically(&path, |f| f.write_all(b"second")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(mode(&path), 0o640);
+
+        // Failed write leaves the target untouched and no temporary file behind.
+        let res = write_file_atomically(&path, |f| {
+            f.write_all(b"partial")?;
+            Err(io::Error::other("interrupted"))
+        });
+        assert!(res.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 }
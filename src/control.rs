@@ -0,0 +1,244 @@
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::oneshot;
+
+/// Command accepted on the control socket, one JSON object per line.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum ControlRequest {
+    List,
+    Up { name: String },
+    Down { name: String },
+    Status { name: String },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct TunnelStatus {
+    pub name: String,
+    pub active: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub struct ControlResponse {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tunnels: Vec<TunnelStatus>,
+}
+
+impl ControlResponse {
+    pub fn tunnels(tunnels: Vec<TunnelStatus>) -> Self {
+        Self {
+            ok: true,
+            error: None,
+            tunnels,
+        }
+    }
+
+    pub fn error(msg: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(msg.into()),
+            tunnels: vec![],
+        }
+    }
+}
+
+/// Request forwarded to the application together with the channel to answer it.
+#[derive(Debug)]
+pub struct ControlCall {
+    pub request: ControlRequest,
+    pub responder: oneshot::Sender<ControlResponse>,
+}
+
+pub fn parse_request(line: &str) -> Result<ControlRequest, String> {
+    serde_json::from_str(line.trim()).map_err(|e| format!("Invalid request: {e}"))
+}
+
+pub fn serialize_response(response: &ControlResponse) -> String {
+    // Serialization of plain structs with string keys can't fail.
+    serde_json::to_string(response).unwrap()
+}
+
+/// Forwards request to the application and returns future resolving to its response.
+pub fn dispatch<F>(forward: &F, request: ControlRequest) -> impl Future<Output = ControlResponse>
+where
+    F: Fn(ControlCall) + ?Sized,
+{
+    let (responder, receiver) = oneshot::channel();
+    forward(ControlCall { request, responder });
+    async move {
+        receiver
+            .await
+            .unwrap_or_else(|_| ControlResponse::error("Application is not responding"))
+    }
+}
+
+/// Listens on the unix socket at `path` and forwards every request to `forward`. The socket
+/// is accessible only by its owner. It's bound in a private directory and moved to `path`
+/// once its permissions are set, so it's never reachable by others in between.
+pub async fn serve<F>(path: PathBuf, forward: F) -> io::Result<()>
+where
+    F: Fn(ControlCall) + Clone + Send + 'static,
+{
+    if let Ok(meta) = fs::symlink_metadata(&path) {
+        if !meta.file_type().is_socket() {
+            return Err(io::Error::other(format!(
+                "{} exists and is not a socket",
+                path.display()
+            )));
+        }
+    }
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = tempfile::Builder::new()
+        .prefix(".wireguard-gui-")
+        .permissions(fs::Permissions::from_mode(0o700))
+        .tempdir_in(parent)?;
+    let bound = dir.path().join("control.sock");
+    let listener = UnixListener::bind(&bound)?;
+    fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+    fs::rename(&bound, &path)?;
+    drop(dir);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let forward = forward.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, forward).await {
+                eprintln!("Control socket connection failed: {:#?}", err);
+            }
+        });
+    }
+}
+
+async fn handle_connection<F>(stream: UnixStream, forward: F) -> io::Result<()>
+where
+    F: Fn(ControlCall),
+{
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match parse_request(&line) {
+            Ok(request) => dispatch(&forward, request).await,
+            Err(e) => ControlResponse::error(e),
+        };
+
+        let mut out = serialize_response(&response);
+        out.push('\n');
+        writer.write_all(out.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        assert_eq!(
+            parse_request(r#"{"command": "list"}"#),
+            Ok(ControlRequest::List)
+        );
+        assert_eq!(
+            parse_request(r#"{"command": "up", "name": "wg0"}"#),
+            Ok(ControlRequest::Up { name: "wg0".into() })
+        );
+        assert_eq!(
+            parse_request(r#"{"command": "down", "name": "wg0"}"#),
+            Ok(ControlRequest::Down { name: "wg0".into() })
+        );
+        assert_eq!(
+            parse_request(r#"{"command": "status", "name": "wg1"}"#),
+            Ok(ControlRequest::Status { name: "wg1".into() })
+        );
+        assert!(parse_request(r#"{"command": "up"}"#).is_err());
+        assert!(parse_request(r#"{"command": "restart", "name": "wg0"}"#).is_err());
+        assert!(parse_request("list").is_err());
+    }
+
+    #[tokio::test]
+    async fn dispatch_waits_for_response() {
+        let forward = |call: ControlCall| {
+            let response = match call.request {
+                ControlRequest::Status { name } => {
+                    ControlResponse::tunnels(vec![TunnelStatus { name, active: true }])
+                }
+                _ => ControlResponse::error("unsupported"),
+            };
+            call.responder.send(response).unwrap();
+        };
+        let response = dispatch(&forward, ControlRequest::Status { name: "wg0".into() }).await;
+        assert!(response.ok);
+        assert_eq!(response.tunnels[0].name, "wg0");
+
+        let dropped = |_: ControlCall| ();
+        let response = dispatch(&dropped, ControlRequest::List).await;
+        assert!(!response.ok);
+    }
+
+    #[tokio::test]
+    async fn socket_only_for_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        tokio::spawn(serve(path.clone(), |_: ControlCall| ()));
+
+        let entries = || fs::read_dir(dir.path()).unwrap().count();
+        for _ in 0..100 {
+            if path.exists() && entries() == 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let meta = fs::symlink_metadata(&path).unwrap();
+        assert!(meta.file_type().is_socket());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        // Directory the socket was bound in is gone.
+        assert_eq!(entries(), 1);
+        assert!(UnixStream::connect(&path).await.is_ok());
+    }
+
+    #[test]
+    fn responses() {
+        let response = ControlResponse::tunnels(vec![
+            TunnelStatus {
+                name: "wg0".into(),
+                active: true,
+            },
+            TunnelStatus {
+                name: "wg1".into(),
+                active: false,
+            },
+        ]);
+        assert_eq!(
+            serialize_response(&response),
+            r#"{"ok":true,"tunnels":[{"name":"wg0","active":true},{"name":"wg1","active":false}]}"#
+        );
+        assert_eq!(
+            serialize_response(&ControlResponse::tunnels(vec![])),
+            r#"{"ok":true}"#
+        );
+        assert_eq!(
+            serialize_response(&ControlResponse::error("No tunnel named wg2")),
+            r#"{"ok":false,"error":"No tunnel named wg2"}"#
+        );
+    }
+}
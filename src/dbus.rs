@@ -0,0 +1,99 @@
+//! Exposes tunnel control on the session bus as `ghaf.WireguardGui` at `/ghaf/WireguardGui`
+//! with interface `ghaf.WireguardGui.Tunnels1`:
+//!
+//! * `List() -> a(sb)` - names of the tunnels and whether they are active;
+//! * `Activate(s name)` - brings the tunnel up;
+//! * `Deactivate(s name)` - brings the tunnel down.
+//!
+//! Calls are dispatched to the application in the same way as control socket requests.
+
+use zbus::{fdo, interface};
+
+use crate::control::*;
+
+pub const BUS_NAME: &str = "ghaf.WireguardGui";
+pub const OBJECT_PATH: &str = "/ghaf/WireguardGui";
+
+type Forward = Box<dyn Fn(ControlCall) + Send + Sync>;
+
+pub struct TunnelsInterface {
+    forward: Forward,
+}
+
+impl TunnelsInterface {
+    pub fn new(forward: impl Fn(ControlCall) + Send + Sync + 'static) -> Self {
+        Self {
+            forward: Box::new(forward),
+        }
+    }
+
+    async fn call(&self, request: ControlRequest) -> fdo::Result<Vec<TunnelStatus>> {
+        let response = dispatch(&*self.forward, request).await;
+        match response.error {
+            None if response.ok => Ok(response.tunnels),
+            error => Err(fdo::Error::Failed(
+                error.unwrap_or_else(|| String::from("Unknown error")),
+            )),
+        }
+    }
+}
+
+#[interface(name = "ghaf.WireguardGui.Tunnels1")]
+impl TunnelsInterface {
+    async fn list(&self) -> fdo::Result<Vec<(String, bool)>> {
+        let tunnels = self.call(ControlRequest::List).await?;
+        Ok(tunnels.into_iter().map(|t| (t.name, t.active)).collect())
+    }
+
+    async fn activate(&self, name: String) -> fdo::Result<()> {
+        self.call(ControlRequest::Up { name }).await.map(|_| ())
+    }
+
+    async fn deactivate(&self, name: String) -> fdo::Result<()> {
+        self.call(ControlRequest::Down { name }).await.map(|_| ())
+    }
+}
+
+/// Registers the service on the session bus. The service stays available as long as the
+/// returned connection is alive.
+pub async fn serve(
+    forward: impl Fn(ControlCall) + Send + Sync + 'static,
+) -> zbus::Result<zbus::Connection> {
+    zbus::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, TunnelsInterface::new(forward))?
+        .build()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface() -> TunnelsInterface {
+        TunnelsInterface::new(|call: ControlCall| {
+            let response = match call.request {
+                ControlRequest::List => ControlResponse::tunnels(vec![TunnelStatus {
+                    name: "wg0".into(),
+                    active: false,
+                }]),
+                ControlRequest::Up { name } if name == "wg0" => {
+                    ControlResponse::tunnels(vec![TunnelStatus { name, active: true }])
+                }
+                _ => ControlResponse::error("No tunnel named wg1"),
+            };
+            call.responder.send(response).unwrap();
+        })
+    }
+
+    #[tokio::test]
+    async fn handlers() {
+        let iface = interface();
+        assert_eq!(iface.list().await.unwrap(), vec![("wg0".into(), false)]);
+        assert!(iface.activate("wg0".into()).await.is_ok());
+        assert_eq!(
+            iface.deactivate("wg1".into()).await,
+            Err(fdo::Error::Failed("No tunnel named wg1".into()))
+        );
+    }
+}
@@ -1,6 +1,6 @@
-use std::{collections::HashMap, convert::TryFrom};
+use std::{collections::HashMap, convert::TryFrom, net::IpAddr};
 
-use cidr::IpCidr;
+use cidr::{IpCidr, IpInet};
 
 use crate::{config::*, utils};
 
@@ -10,11 +10,15 @@ pub struct GenerationSettings {
     number_of_clients: u8,
     cidr: IpCidr,
     client_allowed_ips: Vec<IpCidr>,
+    /// AllowedIPs pattern of client peers in the host config, see [`expand_allowed_ips`].
+    peer_allowed_ips: String,
     // Endpoint is represented by domain name and port, but keep just as String for simplicity.
     endpoint: Option<String>,
     // dns: Option<String>,
     post_up_rule: Option<String>,
     post_down_rule: Option<String>,
+    /// Whether every client shares a generated PresharedKey with the host.
+    preshared_keys: bool,
 }
 
 impl TryFrom<HashMap<String, Option<String>>> for GenerationSettings {
@@ -52,22 +56,100 @@ impl TryFrom<HashMap<String, Option<String>>> for GenerationSettings {
                     .collect::<Result<Vec<_>, _>>()
                     .map_err(|_| "Could not parse one of the Allowed IP addresses")
             })?;
+        let peer_allowed_ips = map
+            .get("Peer Allowed IPs")
+            .cloned()
+            .flatten()
+            .unwrap_or_else(|| String::from(ADDRESS_PLACEHOLDER));
+        // Host takes the first address, the rest go to clients.
+        for client in assignable_addresses(&cidr)
+            .skip(1)
+            .take(number_of_clients.into())
+        {
+            expand_allowed_ips(&peer_allowed_ips, client.address())?;
+        }
+        // Host takes one address too.
+        if usable_hosts(&cidr) < u128::from(number_of_clients) + 1 {
+            return Err(
+                "CIDR doesn't have enough usable addresses for the host and the requested Number of Clients",
+            );
+        }
         let endpoint: Option<String> = map.get("Endpoint (Optional)").cloned().flatten();
         // let dns: Option<String> = map.get("DNS (Optional)").cloned().flatten();
         let post_up_rule: Option<String> = map.get("Post-Up rule (Optional)").cloned().flatten();
         let post_down_rule: Option<String> =
             map.get("Post-Down rule (Optional)").cloned().flatten();
+        let preshared_keys = map
+            .get(PRESHARED_KEYS_FIELD)
+            .cloned()
+            .flatten()
+            .is_some_and(|v| v == "true");
 
         Ok(Self {
             listen_port,
             number_of_clients,
             cidr,
             client_allowed_ips,
+            peer_allowed_ips,
             endpoint,
             // dns,
             post_up_rule,
             post_down_rule,
+            preshared_keys,
+        })
+    }
+}
+
+/// Key of the settings map enabling [`GenerationSettings::preshared_keys`] when set to `true`.
+pub const PRESHARED_KEYS_FIELD: &str = "Preshared Keys";
+
+/// Placeholder replaced with the client address in [`expand_allowed_ips`].
+pub const ADDRESS_PLACEHOLDER: &str = "{ip}";
+
+/// Builds AllowedIPs from comma separated pattern, replacing [`ADDRESS_PLACEHOLDER`] with `ip`.
+/// Entries without prefix length cover a single address, e.g. `{ip}, 192.168.1.0/24` becomes
+/// `10.0.0.2/32, 192.168.1.0/24` for `10.0.0.2`.
+pub fn expand_allowed_ips(pattern: &str, ip: IpAddr) -> Result<String, &'static str> {
+    let ip = ip.to_string();
+    let entries = pattern
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .replace(ADDRESS_PLACEHOLDER, &ip)
+                .parse::<IpCidr>()
+                .map(|cidr| format!("{cidr:#}"))
+                .map_err(|_| "Could not parse one of the Peer Allowed IPs")
         })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if entries.is_empty() {
+        return Err("No Peer Allowed IPs specified");
+    }
+    Ok(entries.join(", "))
+}
+
+/// Addresses of the network that can be assigned to hosts, in order.
+fn assignable_addresses(cidr: &IpCidr) -> impl Iterator<Item = IpInet> {
+    let mut iter = cidr.iter();
+    // Network address can't be assigned to any host.
+    if cidr.is_ipv4() && cidr.network_length() < 31 {
+        iter.next();
+    }
+    iter
+}
+
+/// Number of addresses in the network that can be assigned to hosts. Network and broadcast
+/// addresses of IPv4 networks are excluded unless prefix is /31 or /32.
+pub fn usable_hosts(cidr: &IpCidr) -> u128 {
+    let host_bits = u32::from(cidr.family().len() - cidr.network_length());
+    let addresses = 1u128.checked_shl(host_bits).unwrap_or(u128::MAX);
+
+    if cidr.is_ipv4() && host_bits > 1 {
+        addresses - 2
+    } else {
+        addresses
     }
 }
 
@@ -76,7 +158,7 @@ impl GenerationSettings {
     pub fn generate(&self) -> Vec<WireguardConfig> {
         let mut cfgs = Vec::with_capacity(usize::from(self.number_of_clients) + 1);
 
-        let mut cidr_iter = self.cidr.iter();
+        let mut cidr_iter = assignable_addresses(&self.cidr);
 
         let listen_port = self.listen_port.to_string();
 
@@ -96,9 +178,13 @@ impl GenerationSettings {
         };
 
         for client_cidr in cidr_iter.take(self.number_of_clients.into()) {
+            let client_ip = client_cidr.address();
             let client_cidr: String = client_cidr.to_string();
             let client_private_key = utils::generate_private_key().unwrap();
             let client_public_key = utils::generate_public_key(client_private_key.clone()).unwrap();
+            let preshared_key = self
+                .preshared_keys
+                .then(|| utils::generate_preshared_key().unwrap());
 
             cfgs.push(WireguardConfig {
                 interface: Interface {
@@ -117,13 +203,16 @@ impl GenerationSettings {
                     ),
                     endpoint: self.endpoint.clone(),
                     public_key: Some(host_public_key.clone()),
+                    preshared_key: preshared_key.clone(),
                     ..Default::default()
                 }],
             });
 
             host_cfg.peers.push(Peer {
-                allowed_ips: Some(client_cidr.clone()),
+                // Pattern was validated when settings were created.
+                allowed_ips: expand_allowed_ips(&self.peer_allowed_ips, client_ip).ok(),
                 public_key: Some(client_public_key),
+                preshared_key,
                 ..Default::default()
             });
         }
@@ -133,3 +222,129 @@ impl GenerationSettings {
         cfgs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(cidr: &str, clients: &str) -> Result<GenerationSettings, &'static str> {
+        settings_with(&[
+            ("Listen Port", "51820"),
+            ("Number of Clients", clients),
+            ("CIDR", cidr),
+            ("Client Allowed IPs", "0.0.0.0/0"),
+        ])
+    }
+
+    fn settings_with(fields: &[(&str, &str)]) -> Result<GenerationSettings, &'static str> {
+        let map = fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), Some(v.to_string())))
+            .collect::<HashMap<_, _>>();
+
+        GenerationSettings::try_from(map)
+    }
+
+    #[test]
+    fn usable_host_count() {
+        let hosts = |cidr: &str| usable_hosts(&cidr.parse().unwrap());
+        assert_eq!(hosts("10.0.0.0/24"), 254);
+        assert_eq!(hosts("10.0.0.0/30"), 2);
+        assert_eq!(hosts("10.0.0.0/31"), 2);
+        assert_eq!(hosts("10.0.0.1/32"), 1);
+        assert_eq!(hosts("fd00::/120"), 256);
+        assert_eq!(hosts("::/0"), u128::MAX);
+    }
+
+    #[test]
+    fn clients_fit_subnet() {
+        assert!(settings("10.0.0.0/30", "3").is_err());
+        assert!(settings("10.0.0.0/30", "2").is_err());
+        assert!(settings("10.0.0.0/30", "1").is_ok());
+        assert!(settings("10.0.0.0/29", "5").is_ok());
+        assert!(settings("10.0.0.0/29", "6").is_err());
+        assert!(settings("10.0.0.0/24", "200").is_ok());
+        assert!(settings("fd00::/64", "255").is_ok());
+    }
+
+    #[test]
+    fn allowed_ips_pattern() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            expand_allowed_ips("{ip}", ip("10.0.0.2")).as_deref(),
+            Ok("10.0.0.2/32")
+        );
+        assert_eq!(
+            expand_allowed_ips(" {ip}/32 , 192.168.1.0/24,", ip("10.0.0.2")).as_deref(),
+            Ok("10.0.0.2/32, 192.168.1.0/24")
+        );
+        assert_eq!(
+            expand_allowed_ips("{ip}", ip("fd00::2")).as_deref(),
+            Ok("fd00::2/128")
+        );
+        assert!(expand_allowed_ips("{ip}/24", ip("10.0.0.2")).is_err());
+        assert!(expand_allowed_ips("{ip}/33", ip("10.0.0.2")).is_err());
+        assert!(expand_allowed_ips(" , ", ip("10.0.0.2")).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "native-keys")]
+    fn peer_allowed_ips_in_host_config() {
+        let fields = |pattern| {
+            settings_with(&[
+                ("Listen Port", "51820"),
+                ("Number of Clients", "2"),
+                ("CIDR", "10.0.0.0/24"),
+                ("Client Allowed IPs", "0.0.0.0/0"),
+                ("Peer Allowed IPs", pattern),
+            ])
+        };
+        assert!(fields("{ip}/16").is_err());
+
+        let settings = fields("{ip}, 192.168.0.0/16").unwrap();
+        let host = &settings.generate()[0];
+        let allowed_ips: Vec<_> = host
+            .peers
+            .iter()
+            .map(|p| p.allowed_ips.as_deref())
+            .collect();
+        assert_eq!(
+            allowed_ips,
+            [
+                Some("10.0.0.2/32, 192.168.0.0/16"),
+                Some("10.0.0.3/32, 192.168.0.0/16")
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "native-keys")]
+    fn preshared_keys() {
+        let fields = |preshared_keys| {
+            settings_with(&[
+                ("Listen Port", "51820"),
+                ("Number of Clients", "2"),
+                ("CIDR", "10.0.0.0/24"),
+                ("Client Allowed IPs", "0.0.0.0/0"),
+                (PRESHARED_KEYS_FIELD, preshared_keys),
+            ])
+            .unwrap()
+            .generate()
+        };
+
+        let cfgs = fields("true");
+        let (host, clients) = cfgs.split_first().unwrap();
+        for (peer, client) in host.peers.iter().zip(clients) {
+            let key = peer.preshared_key.as_deref().unwrap();
+            assert!(is_base64_key(key));
+            assert_eq!(client.peers[0].preshared_key.as_deref(), Some(key));
+        }
+        assert_ne!(host.peers[0].preshared_key, host.peers[1].preshared_key);
+
+        let cfgs = fields("false");
+        assert!(cfgs
+            .iter()
+            .flat_map(|c| &c.peers)
+            .all(|p| p.preshared_key.is_none()));
+    }
+}
@@ -5,6 +5,7 @@ use relm4::{gtk::prelude::*, prelude::*};
 use relm4_components::{alert::*, save_dialog::*};
 
 use crate::{
+    cli,
     config::{write_configs_to_path, WireguardConfig},
     fields::*,
     generation_settings::*,
@@ -18,6 +19,8 @@ pub struct GeneratorModel {
     // XXX: I haven't found simpler way to store state required to save generated configs.
     latest_generated_configs: Option<Vec<WireguardConfig>>,
     alert_dialog: Controller<Alert>,
+    /// Whether generated clients share a PresharedKey with the host.
+    preshared_keys: bool,
 }
 
 #[derive(Debug)]
@@ -28,6 +31,8 @@ pub enum GeneratorInput {
     #[doc(hidden)]
     AskForFieldsMap,
     #[doc(hidden)]
+    SetPresharedKeys(bool),
+    #[doc(hidden)]
     Generate(HashMap<String, Option<String>>),
     #[doc(hidden)]
     SaveGeneratedInPath(PathBuf),
@@ -58,6 +63,15 @@ impl SimpleComponent for GeneratorModel {
 
                 append: model.fields.widget(),
 
+                gtk::CheckButton {
+                    set_label: Some("Use preshared keys"),
+                    set_tooltip_text: Some("Generate a PresharedKey shared by the host and each client"),
+                    set_margin_all: 5,
+                    connect_toggled[sender] => move |b| {
+                        sender.input(Self::Input::SetPresharedKeys(b.is_active()));
+                    },
+                },
+
                 gtk::Box {
                     gtk::Button {
                         set_label: "Cancel",
@@ -82,6 +96,10 @@ impl SimpleComponent for GeneratorModel {
             ("Number of Clients".into(), Some("3".into())),
             ("CIDR".into(), Some("10.0.0.0/24".into())),
             ("Client Allowed IPs".into(), Some("0.0.0.0/0, ::/0".into())),
+            (
+                "Peer Allowed IPs".into(),
+                Some(cli::get_args().generator_peer_allowed_ips.clone()),
+            ),
             ("Endpoint (Optional)".into(), Some("myserver.dyndns.org:51820".into())),
             // ("DNS (Optional)".into(), Some("DNS (Optional)".into())),
             ("Post-Up rule (Optional)".into(), Some("iptables -A FORWARD -i %i -j ACCEPT; iptables -t nat -A POSTROUTING -o eth0 -j MASQUERADE".into())),
@@ -105,6 +123,7 @@ impl SimpleComponent for GeneratorModel {
                 filters: vec![{
                     let filter = gtk::FileFilter::new();
                     filter.add_mime_type("application/x-tar");
+                    filter.add_mime_type("application/x-compressed-tar");
                     // filter.add_pattern("*.tar");
                     filter
                 }],
@@ -133,6 +152,7 @@ impl SimpleComponent for GeneratorModel {
             save_dialog,
             latest_generated_configs: None,
             alert_dialog,
+            preshared_keys: false,
         };
 
         let widgets = view_output!();
@@ -147,23 +167,30 @@ impl SimpleComponent for GeneratorModel {
             Self::Input::AskForFieldsMap => {
                 self.fields.emit(FieldsInput::Collect);
             }
+            Self::Input::SetPresharedKeys(enabled) => self.preshared_keys = enabled,
             // FIXME: On the first run allows to save with all fields being empty.
-            Self::Input::Generate(fields) => match GenerationSettings::try_from(fields) {
-                Ok(settings) => {
-                    self.latest_generated_configs = Some(settings.generate());
-                    self.save_dialog
-                        .emit(SaveDialogMsg::SaveAs(format!("clients.tar")))
-                }
-                Err(e) => {
-                    self.alert_dialog
-                        .state()
-                        .get_mut()
-                        .model
-                        .settings
-                        .secondary_text = Some(e.into());
-                    self.alert_dialog.emit(AlertMsg::Show);
+            Self::Input::Generate(mut fields) => {
+                fields.insert(
+                    PRESHARED_KEYS_FIELD.into(),
+                    Some(self.preshared_keys.to_string()),
+                );
+                match GenerationSettings::try_from(fields) {
+                    Ok(settings) => {
+                        self.latest_generated_configs = Some(settings.generate());
+                        self.save_dialog
+                            .emit(SaveDialogMsg::SaveAs(format!("clients.tar")))
+                    }
+                    Err(e) => {
+                        self.alert_dialog
+                            .state()
+                            .get_mut()
+                            .model
+                            .settings
+                            .secondary_text = Some(e.into());
+                        self.alert_dialog.emit(AlertMsg::Show);
+                    }
                 }
-            },
+            }
             Self::Input::SaveGeneratedInPath(path) => {
                 let cfgs = self.latest_generated_configs.take().unwrap();
                 let (host_cfg, clients_cfgs) = cfgs.split_first().unwrap();
@@ -1,3 +1,10 @@
+/// Command line arguments.
+pub mod cli;
+/// Unix socket accepting JSON commands for external automation.
+pub mod control;
+/// DBus service for tunnel control.
+#[cfg(feature = "dbus")]
+pub mod dbus;
 /// Parser and structure that defines accepted Wireguard configuration
 /// file format.
 pub mod config;
@@ -5,6 +12,13 @@ pub mod config;
 pub mod overview;
 /// Peers factory.
 pub mod peer;
+/// Reachability checks of peers through active tunnel.
+pub mod ping;
+/// Dialog showing configurations as QR codes.
+pub mod qr;
+/// Tray icon with quick toggle menu.
+#[cfg(feature = "tray")]
+pub mod tray;
 /// Tunnel - list item.
 pub mod tunnel;
 /// Component that helps display and update structure fields.
@@ -13,5 +27,9 @@ pub mod fields;
 pub mod generation_settings;
 /// Generator component. Provides functionality similar to https://www.wireguardconfig.com/
 pub mod generator;
+/// Routing scripts and static routes applied through interface hooks.
+pub mod routing;
+/// Transfer statistics of active tunnels.
+pub mod stats;
 /// Various utility functions
 pub mod utils;
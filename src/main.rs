@@ -1,34 +1,135 @@
-use std::path::PathBuf;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
 
 use gtk::prelude::*;
 use relm4::factory::{DynamicIndex, FactoryVecDeque};
 use relm4::prelude::*;
+use relm4::MessageBroker;
+use relm4_components::alert::*;
 use relm4_components::open_button::{OpenButton, OpenButtonSettings};
 use relm4_components::open_dialog::OpenDialogSettings;
-use relm4_components::alert::*;
 
-use wireguard_gui::{config::*, generator::*, overview::*, tunnel::*};
+use wireguard_gui::{cli, config::*, control::*, generator::*, overview::*, routing::*, tunnel::*};
+
+/// Window width in pixels below which the tunnel list and the overview are stacked
+/// vertically.
+const NARROW_WIDTH: i32 = 480;
+
+/// Lets files opened from outside reach the running app.
+static APP_BROKER: MessageBroker<AppMsg> = MessageBroker::new();
 
 struct App {
     tunnels: FactoryVecDeque<Tunnel>,
     selected_tunnel_idx: Option<usize>,
+    /// Warning about missing external tools, shown above the tunnel list.
+    tools_warning: Option<String>,
+    /// Number of loaded and all existing configurations while they are being loaded.
+    loading: Option<(usize, usize)>,
+    /// Outcome of the last action with the time it happened, shown at the bottom of the window.
+    status: Option<String>,
+    loading_errors: Vec<String>,
+    collapsed_groups: HashSet<Option<String>>,
+    /// Whether only active tunnels are listed.
+    active_only: bool,
+    /// Whether the window is narrower than [`NARROW_WIDTH`], stacking the overview below the
+    /// tunnel list.
+    narrow: bool,
+    /// Tunnels hidden by the list box filter because their group is collapsed.
+    hidden_tunnels: Rc<RefCell<Vec<bool>>>,
     overview: Controller<OverviewModel>,
     generator: Controller<GeneratorModel>,
     import_button: Controller<OpenButton>,
+    import_folder_button: Controller<OpenButton>,
+    merge_button: Controller<OpenButton>,
+    script_all_button: Controller<OpenButton>,
     alert_dialog: Controller<Alert>,
+    /// Incremented with every shown alert so stale dismiss timers are ignored.
+    alert_generation: u64,
+    permissions_dialog: Controller<Alert>,
+    removal_dialog: Controller<Alert>,
+    /// Tunnel waiting for removal confirmation.
+    pending_removal: Option<DynamicIndex>,
+    bulk_dialog: Controller<Alert>,
+    /// Bulk action waiting for confirmation.
+    pending_bulk: Option<BulkAction>,
+    reload_dialog: Controller<Alert>,
+    /// Version of the selected tunnel's configuration found on disk, waiting for the user to
+    /// choose between it and the one in the list.
+    pending_reload: Option<(usize, Box<WireguardConfig>, Option<SystemTime>)>,
+    #[cfg(feature = "tray")]
+    tray_tunnels: tokio::sync::watch::Sender<Vec<TunnelStatus>>,
+}
+
+/// Action applied to many items at once. Asks for confirmation when it affects more of them
+/// than `--bulk-confirm-threshold`.
+#[derive(Debug)]
+enum BulkAction {
+    DisconnectAll,
+    /// Rotates keys of all inactive tunnels.
+    RotateAllKeys,
+    /// Appends hooks of routing script with given file name to all tunnels.
+    ApplyRoutingScript(RoutingHooks, String),
+    ImportFolder(Vec<PathBuf>),
 }
 
 #[derive(Debug)]
 enum AppMsg {
+    /// Existing configuration loaded at startup, with number of loaded and all configurations.
+    ConfigLoaded(
+        Result<Box<(WireguardConfig, TunnelSource)>, String>,
+        usize,
+        usize,
+    ),
+    /// Loading of existing configurations finished, with number of configurations skipped over
+    /// the limit of loaded tunnels.
+    LoadingFinished(usize),
     ShowOverview(usize),
     AddTunnel(Box<WireguardConfig>),
     RemoveTunnel(DynamicIndex),
+    /// Removes tunnel waiting for confirmation and deletes its configuration file.
+    DeleteTunnel,
+    /// Removes tunnel waiting for confirmation from the list, keeping its configuration file.
+    ForgetTunnel,
     ImportTunnel(PathBuf),
+    ImportFolder(PathBuf),
+    DisconnectAll,
+    RotateAllKeys,
+    ApplyRoutingScriptToAll(PathBuf),
+    /// Shows problems found in all loaded tunnels.
+    ValidateAll,
+    /// Runs bulk action waiting for confirmation.
+    BulkConfirmed,
+    /// Replaces tunnel with its version found on disk.
+    ReloadConfirmed,
+    /// Keeps the version of the tunnel in the list until its file changes again.
+    ReloadDismissed,
     SaveConfigInitiate,
     SaveConfigFinish(Box<WireguardConfig>),
     AddPeer,
+    PastePeer,
+    /// Appends peers of configuration file to the selected tunnel.
+    MergePeers(PathBuf),
+    CopySanitizedConfig,
     ShowGenerator,
+    FixPermissions,
+    WrongOwnership(Vec<PathBuf>),
+    Control(ControlCall),
+    #[cfg(feature = "tray")]
+    SetTunnelActive(String, bool),
+    TunnelToggled(String),
+    SetGroupCollapsed(DynamicIndex, bool),
+    SetActiveOnly(bool),
+    /// Width of the main window changed.
+    WindowResized(i32),
+    /// Raises the main window, e.g. when the app is launched again.
+    ShowWindow,
+    Info(String),
     Error(String),
+    /// Hides alert if it's still the one with given generation.
+    DismissAlert(u64),
     Ignore,
 }
 
@@ -42,63 +143,174 @@ impl SimpleComponent for App {
         gtk::Window {
             set_title: Some("Wireguard"),
             set_default_size: (480, 340),
+            set_size_request: (
+                cli::get_args().min_window_width,
+                cli::get_args().min_window_height,
+            ),
+            connect_default_width_notify[sender] => move |w| {
+                sender.input(Self::Input::WindowResized(w.default_width()));
+            },
 
-            gtk::Paned {
-                set_shrink_start_child: false,
-                set_shrink_end_child: false,
-
-                #[wrap(Some)]
-                set_start_child = &gtk::Box {
-                    set_orientation: gtk::Orientation::Vertical,
-                    gtk::ScrolledWindow {
-                        set_vexpand: true,
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
 
-                        #[local_ref]
-                        tunnels_list_box -> gtk::ListBox {}
+                gtk::Paned {
+                    set_vexpand: true,
+                    #[watch]
+                    set_orientation: if model.narrow {
+                        gtk::Orientation::Vertical
+                    } else {
+                        gtk::Orientation::Horizontal
                     },
+                    set_shrink_start_child: false,
+                    set_shrink_end_child: false,
+
+                    #[wrap(Some)]
+                    set_start_child = &gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+
+                        gtk::Label {
+                            add_css_class: "warning",
+                            set_wrap: true,
+                            set_margin_all: 5,
+                            set_visible: model.tools_warning.is_some(),
+                            set_label: model.tools_warning.as_deref().unwrap_or_default(),
+                        },
 
-                    gtk::Box {
-                        gtk::Button {
-                            set_label: "Add Tunnel",
-                            connect_clicked => Self::Input::AddTunnel(Box::default()),
+                        gtk::CheckButton {
+                            set_label: Some("Show active only"),
+                            set_margin_all: 5,
+                            connect_toggled[sender] => move |b| {
+                                sender.input(Self::Input::SetActiveOnly(b.is_active()));
+                            },
                         },
 
-                        append: model.import_button.widget(),
+                        gtk::ScrolledWindow {
+                            set_vexpand: true,
 
-                        gtk::Button {
-                            set_label: "Generate Configs",
-                            connect_clicked => Self::Input::ShowGenerator,
-                        }
-                    },
-                },
-                #[wrap(Some)]
-                set_end_child = &gtk::Box {
-                    set_orientation: gtk::Orientation::Vertical,
-                    #[name = "config_overview"]
-                    gtk::Box {
-                        set_vexpand: true,
-                        set_hexpand: true,
-
-                        // TODO: Just set property
-                        match () {
-                            () => model.overview.widget().clone(),
+                            #[local_ref]
+                            tunnels_list_box -> gtk::ListBox {}
                         },
-                    },
 
-                    gtk::CenterBox {
-                        #[wrap(Some)]
-                        set_end_widget = &gtk::Box {
+                        gtk::Box {
+                            set_spacing: 5,
+                            set_margin_all: 5,
+                            #[watch]
+                            set_visible: model.loading.is_some(),
+
+                            gtk::Spinner {
+                                #[watch]
+                                set_spinning: model.loading.is_some(),
+                            },
+
+                            gtk::Label {
+                                #[watch]
+                                set_label: &match model.loading {
+                                    Some((loaded, total)) if total > 0 => {
+                                        format!("Loading {loaded} of {total} tunnels...")
+                                    }
+                                    _ => String::from("Loading tunnels..."),
+                                },
+                            },
+                        },
+
+                        gtk::Box {
+                            gtk::Button {
+                                set_label: "Add Tunnel",
+                                connect_clicked => Self::Input::AddTunnel(Box::default()),
+                            },
+
+                            append: model.import_button.widget(),
+
+                            append: model.import_folder_button.widget(),
+
                             gtk::Button {
-                                set_label: "Save",
-                                connect_clicked => Self::Input::SaveConfigInitiate,
+                                set_label: "Generate Configs",
+                                connect_clicked => Self::Input::ShowGenerator,
                             },
 
                             gtk::Button {
-                                set_label: "Add Peer",
-                                connect_clicked => Self::Input::AddPeer,
+                                set_label: "Fix Permissions",
+                                set_tooltip_text: Some("Restrict access to configuration files and apply configured ownership"),
+                                connect_clicked => Self::Input::FixPermissions,
                             },
+
+                            gtk::Button {
+                                set_label: "Disconnect All",
+                                connect_clicked => Self::Input::DisconnectAll,
+                            },
+
+                            gtk::Button {
+                                set_label: "Rotate All Keys",
+                                set_tooltip_text: Some("Generate new keys for every inactive tunnel, backing up the old ones"),
+                                connect_clicked => Self::Input::RotateAllKeys,
+                            },
+
+                            append: model.script_all_button.widget(),
+
+                            gtk::Button {
+                                set_label: "Validate All",
+                                set_tooltip_text: Some("Check all tunnels for errors and likely mistakes"),
+                                connect_clicked => Self::Input::ValidateAll,
+                            },
+                        },
+                    },
+                    #[wrap(Some)]
+                    set_end_child = &gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        // Overview scrolls instead of pushing the buttons below out of small windows.
+                        gtk::ScrolledWindow {
+                            set_vexpand: true,
+                            set_hexpand: true,
+
+                            #[name = "config_overview"]
+                            gtk::Box {
+                                // TODO: Just set property
+                                match () {
+                                    () => model.overview.widget().clone(),
+                                },
+                            },
+                        },
+
+                        gtk::CenterBox {
+                            #[wrap(Some)]
+                            set_end_widget = &gtk::Box {
+                                gtk::Button {
+                                    set_label: "Save",
+                                    connect_clicked => Self::Input::SaveConfigInitiate,
+                                },
+
+                                gtk::Button {
+                                    set_label: "Add Peer",
+                                    connect_clicked => Self::Input::AddPeer,
+                                },
+
+                                gtk::Button {
+                                    set_label: "Paste Peer",
+                                    set_tooltip_text: Some("Add peer from [Peer] section in the clipboard"),
+                                    connect_clicked => Self::Input::PastePeer,
+                                },
+
+                                append: model.merge_button.widget(),
+
+                                gtk::Button {
+                                    set_label: "Copy Sanitized",
+                                    set_tooltip_text: Some("Copy configuration with private keys redacted, e.g. to ask for help"),
+                                    connect_clicked => Self::Input::CopySanitizedConfig,
+                                },
+                            }
                         }
-                    }
+                    },
+                },
+
+                gtk::Label {
+                    add_css_class: "dim-label",
+                    set_halign: gtk::Align::Start,
+                    set_margin_all: 5,
+                    #[watch]
+                    set_visible: model.status.is_some(),
+                    #[watch]
+                    set_label: model.status.as_deref().unwrap_or_default(),
                 },
             },
         }
@@ -109,26 +321,61 @@ impl SimpleComponent for App {
         root: Self::Root,
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
-        let mut tunnels = FactoryVecDeque::builder()
+        let tunnels: FactoryVecDeque<Tunnel> = FactoryVecDeque::builder()
             .launch(gtk::ListBox::default())
             .forward(sender.input_sender(), |output| match output {
                 TunnelOutput::Remove(idx) => Self::Input::RemoveTunnel(idx),
+                TunnelOutput::SetGroupCollapsed(idx, collapsed) => {
+                    Self::Input::SetGroupCollapsed(idx, collapsed)
+                }
+                TunnelOutput::Toggled(name) => Self::Input::TunnelToggled(name),
+                // Only the list, the overview and the tray have to be synced.
+                TunnelOutput::StateChecked => Self::Input::Ignore,
 
                 TunnelOutput::Error(msg) => Self::Input::Error(msg),
             });
 
-        match wireguard_gui::utils::load_existing_configurations() {
-            Ok(cfgs) => {
-                let mut g = tunnels.guard();
+        let hidden_tunnels = Rc::new(RefCell::new(Vec::<bool>::new()));
+        let hidden = Rc::clone(&hidden_tunnels);
+        tunnels.widget().set_filter_func(move |row| {
+            let hidden = hidden.borrow();
+            !usize::try_from(row.index()).is_ok_and(|i| hidden.get(i).copied().unwrap_or(false))
+        });
 
-                for cfg in cfgs {
-                    g.push_back(cfg);
+        if let Err(err) = wireguard_gui::utils::ensure_tunnels_dir(std::path::Path::new(
+            wireguard_gui::utils::TUNNELS_PATH,
+        )) {
+            eprintln!("{err}");
+            sender.input(AppMsg::Error(err));
+        }
+
+        // Configurations are loaded in background so the window shows up right away.
+        let loading_sender = sender.clone();
+        std::thread::spawn(move || {
+            let paths = match wireguard_gui::utils::existing_configuration_paths() {
+                Ok(paths) => paths,
+                Err(err) => {
+                    eprintln!("Could not load existing configurations: {:#?}", err);
+                    vec![]
                 }
+            };
+            let (paths, skipped) =
+                wireguard_gui::utils::limit_configuration_paths(paths, cli::get_args().max_tunnels);
+            let total = paths.len();
+            let mut names = HashMap::new();
+            for (i, path) in paths.iter().enumerate() {
+                // Modification time is taken first, so changes made while loading aren't missed.
+                let source = TunnelSource::new(path.clone());
+                let config = wireguard_gui::utils::load_configuration(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|config| {
+                        wireguard_gui::utils::claim_tunnel_name(&config, path, &mut names)?;
+                        Ok(Box::new((config, source)))
+                    });
+                loading_sender.input(AppMsg::ConfigLoaded(config, i + 1, total));
             }
-            Err(err) => {
-                eprintln!("Could not load existing configurations: {:#?}", err);
-            }
-        };
+            loading_sender.input(AppMsg::LoadingFinished(skipped));
+        });
 
         let import_button = OpenButton::builder()
             .launch(OpenButtonSettings {
@@ -141,6 +388,9 @@ impl SimpleComponent for App {
                     filters: vec![{
                         let filter = gtk::FileFilter::new();
                         filter.add_pattern("*.conf");
+                        filter.add_pattern("*.tar");
+                        filter.add_pattern("*.tar.gz");
+                        filter.add_pattern("*.tgz");
                         filter
                     }],
                 },
@@ -150,10 +400,64 @@ impl SimpleComponent for App {
             })
             .forward(sender.input_sender(), Self::Input::ImportTunnel);
 
+        let import_folder_button = OpenButton::builder()
+            .launch(OpenButtonSettings {
+                dialog_settings: OpenDialogSettings {
+                    folder_mode: true,
+                    accept_label: String::from("Import"),
+                    cancel_label: String::from("Cancel"),
+                    create_folders: false,
+                    is_modal: true,
+                    filters: vec![],
+                },
+                text: "Import Folder",
+                recently_opened_files: None,
+                max_recent_files: 0,
+            })
+            .forward(sender.input_sender(), Self::Input::ImportFolder);
+
+        let merge_button = OpenButton::builder()
+            .launch(OpenButtonSettings {
+                dialog_settings: OpenDialogSettings {
+                    folder_mode: false,
+                    accept_label: String::from("Merge"),
+                    cancel_label: String::from("Cancel"),
+                    create_folders: false,
+                    is_modal: true,
+                    filters: vec![{
+                        let filter = gtk::FileFilter::new();
+                        filter.add_pattern("*.conf");
+                        filter
+                    }],
+                },
+                text: "Merge Peers",
+                recently_opened_files: None,
+                max_recent_files: 0,
+            })
+            .forward(sender.input_sender(), Self::Input::MergePeers);
+
+        let script_all_button = OpenButton::builder()
+            .launch(OpenButtonSettings {
+                dialog_settings: OpenDialogSettings {
+                    folder_mode: false,
+                    accept_label: String::from("Apply"),
+                    cancel_label: String::from("Cancel"),
+                    create_folders: false,
+                    is_modal: true,
+                    filters: vec![],
+                },
+                text: "Apply Routing Script to All",
+                recently_opened_files: None,
+                max_recent_files: 0,
+            })
+            .forward(sender.input_sender(), Self::Input::ApplyRoutingScriptToAll);
+
         let overview = OverviewModel::builder()
             .launch(WireguardConfig::default())
             .forward(sender.input_sender(), |msg| match msg {
                 OverviewOutput::SaveConfig(config) => Self::Input::SaveConfigFinish(config),
+                OverviewOutput::Error(msg) => Self::Input::Error(msg),
+                OverviewOutput::Warning(msg) => Self::Input::Info(msg),
             });
 
         let generator =
@@ -178,15 +482,161 @@ impl SimpleComponent for App {
             })
             .forward(sender.input_sender(), |_| Self::Input::Ignore);
 
+        let permissions_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::from("Configuration files have wrong ownership"),
+                secondary_text: None,
+                confirm_label: Some(String::from("Fix Permissions")),
+                cancel_label: Some(String::from("Ignore")),
+                option_label: None,
+                is_modal: true,
+                destructive_accept: false,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::FixPermissions,
+                _ => Self::Input::Ignore,
+            });
+
+        let removal_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::from("Remove tunnel?"),
+                secondary_text: None,
+                confirm_label: Some(String::from("Delete File")),
+                cancel_label: Some(String::from("Cancel")),
+                option_label: Some(String::from("Remove from List Only")),
+                is_modal: true,
+                destructive_accept: true,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::DeleteTunnel,
+                AlertResponse::Option => Self::Input::ForgetTunnel,
+                AlertResponse::Cancel => Self::Input::Ignore,
+            });
+
+        let bulk_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::new(),
+                secondary_text: None,
+                confirm_label: Some(String::from("Continue")),
+                cancel_label: Some(String::from("Cancel")),
+                option_label: None,
+                is_modal: true,
+                destructive_accept: false,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::BulkConfirmed,
+                _ => Self::Input::Ignore,
+            });
+
+        let reload_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::new(),
+                secondary_text: None,
+                confirm_label: Some(String::from("Load Disk Version")),
+                cancel_label: Some(String::from("Keep Mine")),
+                option_label: None,
+                is_modal: true,
+                destructive_accept: true,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::ReloadConfirmed,
+                _ => Self::Input::ReloadDismissed,
+            });
+
+        let args = cli::get_args();
+        let wrong_ownership = wireguard_gui::utils::resolve_ownership(
+            args.config_owner.as_deref(),
+            args.config_group.as_deref(),
+        )
+        .and_then(|(uid, gid)| {
+            wireguard_gui::utils::find_wrong_ownership(
+                std::path::Path::new(wireguard_gui::utils::TUNNELS_PATH),
+                uid,
+                gid,
+            )
+        });
+        match wrong_ownership {
+            Ok(paths) if !paths.is_empty() => sender.input(AppMsg::WrongOwnership(paths)),
+            Ok(_) => (),
+            Err(err) => eprintln!("Could not check ownership of configurations: {:#?}", err),
+        }
+
         let model = App {
             tunnels,
             selected_tunnel_idx: None,
+            tools_warning: tools_warning(),
+            status: None,
+            loading: Some((0, 0)),
+            loading_errors: vec![],
+            collapsed_groups: HashSet::new(),
+            active_only: false,
+            narrow: false,
+            hidden_tunnels,
             import_button,
+            merge_button,
+            script_all_button,
+            import_folder_button,
             overview,
             generator,
-            alert_dialog
+            alert_dialog,
+            alert_generation: 0,
+            permissions_dialog,
+            removal_dialog,
+            pending_removal: None,
+            bulk_dialog,
+            pending_bulk: None,
+            reload_dialog,
+            pending_reload: None,
+            #[cfg(feature = "tray")]
+            tray_tunnels: tokio::sync::watch::Sender::new(vec![]),
         };
 
+        if let Some(path) = cli::get_args().control_socket.clone() {
+            let control_sender = sender.clone();
+            let error_sender = sender.clone();
+            relm4::spawn(async move {
+                let forward = move |call| control_sender.input(AppMsg::Control(call));
+                if let Err(err) = serve(path, forward).await {
+                    error_sender.input(AppMsg::Error(format!("Control socket failed: {err}")));
+                }
+            });
+        }
+
+        #[cfg(feature = "dbus")]
+        {
+            let dbus_sender = sender.clone();
+            relm4::spawn(async move {
+                let forward = move |call| dbus_sender.input(AppMsg::Control(call));
+                match wireguard_gui::dbus::serve(forward).await {
+                    // Keep connection alive for the lifetime of the application.
+                    Ok(_connection) => std::future::pending::<()>().await,
+                    Err(err) => eprintln!("Could not register DBus service: {:#?}", err),
+                }
+            });
+        }
+
+        model.sync_groups();
+
+        #[cfg(feature = "tray")]
+        {
+            model.sync_tray();
+            let tunnels = model.tray_tunnels.subscribe();
+            let toggle_sender = sender.clone();
+            let show_sender = sender.clone();
+            relm4::spawn(async move {
+                let on_toggle =
+                    move |name, up| toggle_sender.input(AppMsg::SetTunnelActive(name, up));
+                let on_show = move || show_sender.input(AppMsg::ShowWindow);
+                if let Err(err) = wireguard_gui::tray::run(tunnels, on_toggle, on_show).await {
+                    eprintln!("Tray icon is not available: {:#?}", err);
+                }
+            });
+        }
+
         let tunnels_list_box = model.tunnels.widget();
 
         tunnels_list_box.connect_row_selected(gtk::glib::clone!(@strong sender => move |_, row| {
@@ -202,75 +652,897 @@ impl SimpleComponent for App {
 
     fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>) {
         match msg {
+            Self::Input::ConfigLoaded(config, loaded, total) => {
+                self.loading = Some((loaded, total));
+                match config {
+                    Ok(loaded) => {
+                        let (config, source) = *loaded;
+                        self.insert_tunnel(config, Some(source));
+                    }
+                    Err(err) => self.loading_errors.push(err),
+                }
+            }
+            Self::Input::LoadingFinished(skipped) => {
+                self.loading = None;
+                if let Some(path) = cli::get_args().import_path().cloned() {
+                    sender.input(Self::Input::ImportTunnel(path));
+                }
+                let limit_warning = (skipped > 0).then(|| {
+                    format!(
+                        "Only {} tunnels were loaded, {skipped} more configurations were skipped. \
+                         The limit can be changed with --max-tunnels.",
+                        cli::get_args().max_tunnels
+                    )
+                });
+                if !self.loading_errors.is_empty() {
+                    let errors = std::mem::take(&mut self.loading_errors);
+                    eprintln!("Could not load existing configurations: {:#?}", errors);
+                    let mut msg = format!(
+                        "Could not load existing configurations:\n{}",
+                        errors.join("\n")
+                    );
+                    if let Some(warning) = limit_warning {
+                        msg.push_str(&format!("\n\n{warning}"));
+                    }
+                    sender.input(Self::Input::Error(msg));
+                } else if let Some(warning) = limit_warning {
+                    eprintln!("{warning}");
+                    sender.input(Self::Input::Info(warning));
+                }
+            }
             Self::Input::ShowOverview(idx) => {
                 self.selected_tunnel_idx = Some(idx);
                 let tunnel = self.tunnels.get(idx).unwrap();
                 self.overview
                     .emit(OverviewInput::ShowConfig(Box::new(tunnel.config.clone())));
+                self.check_disk_version(idx);
             }
-            Self::Input::AddTunnel(config) => {
-                let mut tunnels = self.tunnels.guard();
-                tunnels.push_back(*config);
+            Self::Input::ReloadDismissed => {
+                if let Some((idx, _, modified)) = self.pending_reload.take() {
+                    self.remember_disk_version(idx, modified);
+                }
             }
+            Self::Input::ReloadConfirmed => {
+                let Some((idx, config, modified)) = self.pending_reload.take() else {
+                    return;
+                };
+                self.remember_disk_version(idx, modified);
+                if self.selected_tunnel_idx == Some(idx) {
+                    self.overview
+                        .emit(OverviewInput::ShowConfig(Box::new((*config).clone())));
+                    let renamed = self.tunnels.guard().get_mut(idx).is_some_and(|tunnel| {
+                        let renamed = tunnel.config.interface.name != config.interface.name;
+                        tunnel.reload(*config);
+                        renamed
+                    });
+                    if renamed {
+                        self.tunnels.send(idx, TunnelMsg::CheckState);
+                    }
+                }
+            }
+            Self::Input::AddTunnel(config) => self.insert_tunnel(*config, None),
             Self::Input::RemoveTunnel(idx) => {
+                let Some(tunnel) = self.tunnels.get(idx.current_index()) else {
+                    return;
+                };
+                let has_file = tunnel.source.is_some();
+                let settings = &mut self.removal_dialog.state().get_mut().model.settings;
+                settings.text = format!("Remove tunnel {}?", tunnel.name);
+                settings.secondary_text = Some(tunnel.removal_confirmation());
+                // Only tunnels loaded from a file have one to delete.
+                settings.confirm_label = has_file.then(|| String::from("Delete File"));
+                settings.option_label = Some(String::from(if has_file {
+                    "Remove from List Only"
+                } else {
+                    "Remove from List"
+                }));
+                self.pending_removal = Some(idx);
+                self.removal_dialog.emit(AlertMsg::Show);
+            }
+            Self::Input::DeleteTunnel => {
+                let Some(idx) = self.pending_removal.take() else {
+                    return;
+                };
                 let mut tunnels = self.tunnels.guard();
-                // self.tunnels.widget.selection
+                let Some(tunnel) = tunnels.get(idx.current_index()) else {
+                    return;
+                };
+
+                if let Some(source) = &tunnel.source {
+                    if let Err(err) = std::fs::remove_file(&source.path) {
+                        sender.input(Self::Input::Error(format!(
+                            "Could not delete {}: {err}",
+                            source.path.display()
+                        )));
+                        return;
+                    }
+                }
+
                 tunnels.remove(idx.current_index());
             }
-            Self::Input::ImportTunnel(path) => {
-                let file_content = std::fs::read_to_string(&path);
-                let res = file_content.map(|c| parse_config(&c));
-
-                let Ok(Ok(mut config)) = res else {
-                    sender.input(Self::Input::Error(format!("{:#?}", res)));
+            Self::Input::ForgetTunnel => {
+                let Some(idx) = self.pending_removal.take() else {
+                    return;
+                };
+                let mut tunnels = self.tunnels.guard();
+                let Some(tunnel) = tunnels.get(idx.current_index()) else {
                     return;
                 };
 
-                if config.interface.name.is_none() {
-                    config.interface.name = path
-                        .file_stem()
-                        .unwrap_or_else(|| todo!())
-                        .to_str()
-                        .map(|s| s.to_owned());
+                // Tunnels that weren't loaded from a file won't show up again anyway.
+                if let Some(source) = &tunnel.source {
+                    if let Err(err) = wireguard_gui::utils::add_ignored_config(
+                        &wireguard_gui::utils::ignored_configs_path(),
+                        &source.path,
+                    ) {
+                        sender.input(Self::Input::Error(format!(
+                            "Could not remember that {} is hidden: {err}",
+                            tunnel.name
+                        )));
+                        return;
+                    }
                 }
 
-                sender.input(Self::Input::AddTunnel(Box::new(config)));
+                tunnels.remove(idx.current_index());
+            }
+            Self::Input::ImportTunnel(path) if path.extension().is_some_and(|e| e != "conf") => {
+                match read_configs_from_path(&path) {
+                    Ok(cfgs) => {
+                        let mut errors = vec![];
+                        for cfg in cfgs {
+                            match check_strict_import(&cfg) {
+                                Ok(()) => self.insert_tunnel(cfg, None),
+                                Err(e) => errors.push(format!(
+                                    "Skipped {} from {}:\n{e}",
+                                    get_value(&cfg.interface.name),
+                                    path.display()
+                                )),
+                            }
+                        }
+                        if !errors.is_empty() {
+                            sender.input(Self::Input::Error(errors.join("\n\n")));
+                        }
+                    }
+                    Err(err) => sender.input(Self::Input::Error(format!(
+                        "Could not import {}: {err}",
+                        path.display()
+                    ))),
+                }
+            }
+            Self::Input::ImportTunnel(path) => match read_tunnel(&path) {
+                Ok(config) => {
+                    self.set_status(&format!("Imported {}", path.display()));
+                    sender.input(Self::Input::AddTunnel(Box::new(config)));
+                }
+                Err(err) => sender.input(Self::Input::Error(err)),
+            },
+            Self::Input::ImportFolder(dir) => match wireguard_gui::utils::list_configs(&dir) {
+                Ok(paths) => self.confirm_bulk(BulkAction::ImportFolder(paths), &sender),
+                Err(err) => sender.input(Self::Input::Error(format!(
+                    "Could not read {}: {err}",
+                    dir.display()
+                ))),
+            },
+            Self::Input::DisconnectAll => self.confirm_bulk(BulkAction::DisconnectAll, &sender),
+            Self::Input::RotateAllKeys => self.confirm_bulk(BulkAction::RotateAllKeys, &sender),
+            Self::Input::ApplyRoutingScriptToAll(path) => match read_routing_script(&path) {
+                Ok((hooks, name)) => {
+                    self.confirm_bulk(BulkAction::ApplyRoutingScript(hooks, name), &sender)
+                }
+                Err(err) => sender.input(Self::Input::Error(format!(
+                    "Could not apply routing script {}: {err}",
+                    path.display()
+                ))),
+            },
+            Self::Input::BulkConfirmed => {
+                if let Some(action) = self.pending_bulk.take() {
+                    self.run_bulk(action, &sender);
+                }
             }
             Self::Input::SaveConfigInitiate => self.overview.emit(OverviewInput::CollectTunnel),
             Self::Input::SaveConfigFinish(tunnel) => {
                 let Some(idx) = self.selected_tunnel_idx else {
                     return;
                 };
-                if let Some(selected_tunnel) = self.tunnels.guard().get_mut(idx) {
-                    *selected_tunnel = Tunnel::new(*tunnel);
+                match wireguard_gui::utils::check_private_key(&tunnel.interface) {
+                    Ok(warning) => {
+                        if let Some(warning) = warning {
+                            sender.input(Self::Input::Info(warning));
+                        }
+                    }
+                    Err(err) => {
+                        sender.input(Self::Input::Error(err));
+                        return;
+                    }
+                }
+                if let Err(err) = wireguard_gui::utils::check_peers_allowed_ips(&tunnel.peers) {
+                    sender.input(Self::Input::Error(err));
+                    return;
+                }
+                if let Some(warning) = wireguard_gui::utils::saved_keys_warning(&tunnel.interface) {
+                    sender.input(Self::Input::Info(warning));
+                }
+                self.set_status(&format!(
+                    "Saved {}",
+                    tunnel.interface.name.as_deref().unwrap_or("unknown")
+                ));
+                let tag_changed = self
+                    .tunnels
+                    .get(idx)
+                    .is_some_and(|t| t.config.interface.tag != tunnel.interface.tag);
+                if tag_changed {
+                    // Moves tunnel into its new group.
+                    let source = self.tunnels.guard().remove(idx).and_then(|t| t.source);
+                    self.selected_tunnel_idx =
+                        Some(insert_grouped(&mut self.tunnels, *tunnel, source));
+                } else {
+                    let renamed = self.tunnels.guard().get_mut(idx).is_some_and(|selected| {
+                        let renamed = selected.config.interface.name != tunnel.interface.name;
+                        selected.set_config(*tunnel);
+                        renamed
+                    });
+                    if renamed {
+                        self.tunnels.send(idx, TunnelMsg::CheckState);
+                    }
                 }
             }
             Self::Input::AddPeer => {
                 self.overview.emit(OverviewInput::AddPeer);
             }
+            Self::Input::PastePeer => self.overview.emit(OverviewInput::PastePeer),
+            Self::Input::CopySanitizedConfig => {
+                self.overview.emit(OverviewInput::CopySanitizedConfig)
+            }
+            Self::Input::MergePeers(path) => {
+                self.overview.emit(OverviewInput::MergePeersFrom(path))
+            }
+            Self::Input::ValidateAll => {
+                let report =
+                    wireguard_gui::utils::validation_report(self.tunnels.iter().map(|t| &t.config));
+                self.set_status(&format!(
+                    "Validated {} tunnels, {} with problems",
+                    self.tunnels.len(),
+                    report.len()
+                ));
+                show_validation_report(&report, self.tunnels.len());
+            }
             Self::Input::ShowGenerator => {
                 self.generator.emit(GeneratorInput::Show);
             }
+            Self::Input::FixPermissions => {
+                let args = cli::get_args();
+                let results = wireguard_gui::utils::resolve_ownership(
+                    args.config_owner.as_deref(),
+                    args.config_group.as_deref(),
+                )
+                .and_then(|(uid, gid)| {
+                    wireguard_gui::utils::fix_configs_permissions(
+                        std::path::Path::new(wireguard_gui::utils::TUNNELS_PATH),
+                        uid,
+                        gid,
+                    )
+                });
+                match results {
+                    Ok(results) => {
+                        let failed: Vec<String> = results
+                            .iter()
+                            .filter_map(|(path, res)| {
+                                res.as_ref()
+                                    .err()
+                                    .map(|e| format!("{}: {e}", path.display()))
+                            })
+                            .collect();
+                        let msg = format!(
+                            "Fixed permissions of {} of {} configuration files.",
+                            results.len() - failed.len(),
+                            results.len()
+                        );
+                        if failed.is_empty() {
+                            sender.input(Self::Input::Info(msg));
+                        } else {
+                            sender
+                                .input(Self::Input::Error(format!("{msg}\n{}", failed.join("\n"))));
+                        }
+                    }
+                    Err(err) => sender.input(Self::Input::Error(format!(
+                        "Could not fix permissions: {err}"
+                    ))),
+                }
+            }
+            Self::Input::WrongOwnership(paths) => {
+                let files: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                self.permissions_dialog
+                    .state()
+                    .get_mut()
+                    .model
+                    .settings
+                    .secondary_text = Some(format!(
+                    "The following files are not owned by the configured owner:\n{}",
+                    files.join("\n")
+                ));
+                self.permissions_dialog.emit(AlertMsg::Show);
+            }
+            Self::Input::Control(ControlCall { request, responder }) => {
+                let _ = responder.send(self.handle_control_request(request));
+            }
+            #[cfg(feature = "tray")]
+            Self::Input::SetTunnelActive(name, up) => {
+                let request = if up {
+                    ControlRequest::Up { name }
+                } else {
+                    ControlRequest::Down { name }
+                };
+                if let Some(err) = self.handle_control_request(request).error {
+                    sender.input(Self::Input::Error(err));
+                }
+            }
+            Self::Input::TunnelToggled(name) => {
+                let active = self
+                    .tunnels
+                    .iter()
+                    .find(|t| t.name == name)
+                    .map(|t| t.active);
+                if let Some(active) = active {
+                    let state = if active { "up" } else { "down" };
+                    self.set_status(&format!("{name} is {state}"));
+                }
+                if let Some(warning) = self.allowed_ips_warning(&name) {
+                    sender.input(Self::Input::Info(warning));
+                }
+            }
+            Self::Input::SetActiveOnly(active_only) => self.active_only = active_only,
+            Self::Input::WindowResized(width) => self.narrow = width < NARROW_WIDTH,
+            Self::Input::SetGroupCollapsed(idx, collapsed) => {
+                if let Some(tunnel) = self.tunnels.get(idx.current_index()) {
+                    let group = group_of(&tunnel.config.interface.tag).map(String::from);
+                    if collapsed {
+                        self.collapsed_groups.insert(group);
+                    } else {
+                        self.collapsed_groups.remove(&group);
+                    }
+                }
+            }
+            Self::Input::ShowWindow => {
+                if let Some(window) = relm4::main_application().active_window() {
+                    window.present();
+                } else if let Some(window) = relm4::main_application().windows().first() {
+                    window.present();
+                }
+            }
+            Self::Input::Info(msg) => self.show_alert(
+                "Information",
+                msg,
+                cli::get_args().info_toast_timeout,
+                &sender,
+            ),
             Self::Input::Error(msg) => {
-                self.alert_dialog
-                        .state()
-                        .get_mut()
-                        .model
-                        .settings
-                        .secondary_text = Some(msg);
-                self.alert_dialog.emit(AlertMsg::Show);
+                self.set_status(&format!(
+                    "Error: {}",
+                    msg.lines().next().unwrap_or_default()
+                ));
+                self.show_alert("Error", msg, cli::get_args().error_toast_timeout, &sender)
+            }
+            Self::Input::DismissAlert(generation) => {
+                if generation == self.alert_generation {
+                    self.alert_dialog.emit(AlertMsg::Hide);
+                }
             }
             Self::Input::Ignore => (),
         }
+
+        if let Some(tunnel) = self.selected_tunnel_idx.and_then(|i| self.tunnels.get(i)) {
+            self.overview.emit(OverviewInput::SetActive(tunnel.active));
+            self.overview.emit(OverviewInput::SetLastConnected(
+                tunnel.config.interface.last_connected.clone(),
+            ));
+        }
+
+        self.sync_groups();
+
+        #[cfg(feature = "tray")]
+        self.sync_tray();
     }
 }
 
-fn main() {
+/// Shows problems of tunnels in a scrollable window.
+fn show_validation_report(report: &[(String, Vec<String>)], total: usize) {
+    let text = if report.is_empty() {
+        format!("No problems found in {total} tunnels.")
+    } else {
+        report
+            .iter()
+            .map(|(name, issues)| {
+                let issues: Vec<String> = issues.iter().map(|i| format!("  • {i}")).collect();
+                format!("{name}:\n{}", issues.join("\n"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+
+    let label = gtk::Label::builder()
+        .label(text)
+        .wrap(true)
+        .selectable(true)
+        .xalign(0.0)
+        .yalign(0.0)
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .build();
+    let window = gtk::Window::builder()
+        .title("Validation Report")
+        .default_width(480)
+        .default_height(340)
+        .modal(true)
+        .child(
+            &gtk::ScrolledWindow::builder()
+                .hscrollbar_policy(gtk::PolicyType::Never)
+                .child(&label)
+                .build(),
+        )
+        .build();
+    window.set_transient_for(relm4::main_application().active_window().as_ref());
+    window.present();
+}
+
+/// Describes missing external tools and features disabled because of them.
+fn tools_warning() -> Option<String> {
+    let names: Vec<&str> = wireguard_gui::utils::REQUIRED_TOOLS
+        .iter()
+        .map(|(name, _)| *name)
+        .collect();
+    let missing = wireguard_gui::utils::missing_binaries(
+        &names,
+        &std::env::var_os("PATH").unwrap_or_default(),
+    );
+    if missing.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<String> = wireguard_gui::utils::REQUIRED_TOOLS
+        .iter()
+        .filter(|(name, _)| missing.contains(name))
+        .map(|(name, feature)| format!("{name} was not found, {feature} won't work."))
+        .collect();
+    Some(lines.join("\n"))
+}
+
+/// Checks imported configuration against the required fields if `--strict-import` is set.
+fn check_strict_import(config: &WireguardConfig) -> Result<(), String> {
+    let args = cli::get_args();
+    if !args.strict_import {
+        return Ok(());
+    }
+    wireguard_gui::utils::strict_check(
+        config,
+        &args.required_interface_fields,
+        &args.required_peer_fields,
+    )
+    .map_err(|errors| errors.join("\n"))
+}
+
+/// Reads tunnel configuration file, naming the tunnel after the file if it has no name.
+fn read_tunnel(path: &Path) -> Result<WireguardConfig, String> {
+    let mut config = wireguard_gui::utils::read_config_file(path, cli::get_args().max_config_size)
+        .map_err(|e| e.to_string())
+        .and_then(|c| parse_config(&c))
+        .map_err(|e| format!("Could not import {}: {e}", path.display()))?;
+
+    check_strict_import(&config)
+        .map_err(|e| format!("Could not import {}:\n{e}", path.display()))?;
+
+    if config.interface.name.is_none() {
+        config.interface.name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_owned());
+    }
+
+    Ok(config)
+}
+
+/// Inserts tunnel after the last tunnel of its group, returning its index.
+fn insert_grouped(
+    tunnels: &mut FactoryVecDeque<Tunnel>,
+    config: WireguardConfig,
+    source: Option<TunnelSource>,
+) -> usize {
+    let tags: Vec<Option<String>> = tunnels
+        .iter()
+        .map(|t| t.config.interface.tag.clone())
+        .collect();
+    let pos = group_insert_position(&tags, &config.interface.tag);
+    tunnels.guard().insert(pos, (config, source));
+    pos
+}
+
+impl App {
+    /// Shows message in the status line, prefixed with the current time.
+    fn set_status(&mut self, msg: &str) {
+        let time = gtk::glib::DateTime::now_local()
+            .and_then(|t| t.format("%H:%M"))
+            .map(String::from)
+            .unwrap_or_default();
+        self.status = Some(format!("{time} {msg}"));
+    }
+
+    /// Shows alert, dismissing it after `timeout` seconds unless it is 0.
+    fn show_alert(
+        &mut self,
+        title: &str,
+        msg: String,
+        timeout: u64,
+        sender: &ComponentSender<Self>,
+    ) {
+        let settings = &mut self.alert_dialog.state().get_mut().model.settings;
+        settings.text = String::from(title);
+        settings.secondary_text = Some(msg);
+        self.alert_dialog.emit(AlertMsg::Show);
+
+        self.alert_generation += 1;
+        if timeout > 0 {
+            let generation = self.alert_generation;
+            let sender = sender.clone();
+            gtk::glib::timeout_add_local_once(std::time::Duration::from_secs(timeout), move || {
+                sender.input(AppMsg::DismissAlert(generation));
+            });
+        }
+    }
+
+    /// Runs bulk action right away if it affects few enough items, otherwise asks to confirm it.
+    fn confirm_bulk(&mut self, action: BulkAction, sender: &ComponentSender<Self>) {
+        let (count, text) = match &action {
+            BulkAction::DisconnectAll => {
+                let count = self.tunnels.iter().filter(|t| t.active).count();
+                (count, format!("Disconnect {count} tunnels?"))
+            }
+            BulkAction::RotateAllKeys => {
+                let count = self.tunnels.iter().filter(|t| !t.active).count();
+                (count, format!("Rotate keys of {count} tunnels?"))
+            }
+            BulkAction::ApplyRoutingScript(_, name) => {
+                let count = self.tunnels.len();
+                (
+                    count,
+                    format!("Apply routing script {name} to {count} tunnels?"),
+                )
+            }
+            BulkAction::ImportFolder(paths) => {
+                (paths.len(), format!("Import {} tunnels?", paths.len()))
+            }
+        };
+
+        if count <= cli::get_args().bulk_confirm_threshold {
+            self.run_bulk(action, sender);
+            return;
+        }
+
+        self.bulk_dialog.state().get_mut().model.settings.text = text;
+        self.pending_bulk = Some(action);
+        self.bulk_dialog.emit(AlertMsg::Show);
+    }
+
+    fn run_bulk(&mut self, action: BulkAction, sender: &ComponentSender<Self>) {
+        let mut errors = vec![];
+        match action {
+            BulkAction::DisconnectAll => {
+                let mut tunnels = self.tunnels.guard();
+                for i in 0..tunnels.len() {
+                    let tunnel = tunnels.get_mut(i).unwrap();
+                    if let Err(err) = tunnel.set_active(false) {
+                        errors.push(err.to_string());
+                    }
+                }
+            }
+            BulkAction::RotateAllKeys => {
+                let backups = wireguard_gui::utils::key_backups_dir();
+                let edited = self.edited_tunnel_idx();
+                let mut tunnels = self.tunnels.guard();
+                let before: Vec<WireguardConfig> =
+                    tunnels.iter().map(|t| t.config.clone()).collect();
+                let (mut report, rotated) = wireguard_gui::utils::rotate_inactive_keys(
+                    tunnels
+                        .iter_mut()
+                        .enumerate()
+                        .filter(|&(i, _)| Some(i) != edited)
+                        .map(|(_, t)| {
+                            let active = t.active;
+                            (&mut t.config.interface, active)
+                        }),
+                    |interface| wireguard_gui::utils::rotate_keys(interface, &backups),
+                );
+                for (tunnel, before) in tunnels.iter_mut().zip(before) {
+                    tunnel.saved &= tunnel.config == before;
+                }
+                drop(tunnels);
+
+                if let Some(tunnel) = edited.and_then(|i| self.tunnels.get(i)) {
+                    report.push(format!(
+                        "{}: skipped, it has unsaved edits",
+                        tunnel.config.interface.name.as_deref().unwrap_or("unknown")
+                    ));
+                } else if rotated {
+                    self.show_selected_config();
+                }
+                sender.input(AppMsg::Info(format!(
+                    "New public keys to distribute to peers:\n{}",
+                    report.join("\n")
+                )));
+            }
+            BulkAction::ApplyRoutingScript(hooks, name) => {
+                let edited = self.edited_tunnel_idx();
+                let mut tunnels = self.tunnels.guard();
+                let before: Vec<WireguardConfig> =
+                    tunnels.iter().map(|t| t.config.clone()).collect();
+                let mut skipped = apply_to_all(
+                    &hooks,
+                    &name,
+                    tunnels
+                        .iter_mut()
+                        .enumerate()
+                        .filter(|&(i, _)| Some(i) != edited)
+                        .map(|(_, t)| &mut t.config.interface),
+                );
+                for (tunnel, before) in tunnels.iter_mut().zip(before) {
+                    tunnel.saved &= tunnel.config == before;
+                }
+                drop(tunnels);
+
+                if let Some(tunnel) = edited.and_then(|i| self.tunnels.get(i)) {
+                    skipped.push(format!(
+                        "{}: it has unsaved edits",
+                        tunnel.config.interface.name.as_deref().unwrap_or("unknown")
+                    ));
+                } else {
+                    self.show_selected_config();
+                }
+                errors.extend(
+                    skipped
+                        .into_iter()
+                        .map(|s| format!("Routing script {name} wasn't applied to {s}")),
+                );
+            }
+            BulkAction::ImportFolder(paths) => {
+                for path in paths {
+                    match read_tunnel(&path) {
+                        Ok(config) => self.insert_tunnel(config, None),
+                        Err(err) => errors.push(err),
+                    }
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            sender.input(AppMsg::Error(errors.join("\n")));
+        }
+    }
+
+    /// Selected tunnel if it has edits in the overview that aren't saved yet.
+    fn edited_tunnel_idx(&self) -> Option<usize> {
+        let idx = self.selected_tunnel_idx?;
+        let tunnel = self.tunnels.get(idx)?;
+        let edited = self.overview.state().get_mut().model.current_config();
+        (edited != tunnel.config).then_some(idx)
+    }
+
+    fn show_selected_config(&self) {
+        if let Some(tunnel) = self.selected_tunnel_idx.and_then(|i| self.tunnels.get(i)) {
+            self.overview
+                .emit(OverviewInput::ShowConfig(Box::new(tunnel.config.clone())));
+        }
+    }
+
+    fn insert_tunnel(&mut self, config: WireguardConfig, source: Option<TunnelSource>) {
+        let pos = insert_grouped(&mut self.tunnels, config, source);
+        if let Some(selected) = self.selected_tunnel_idx.as_mut() {
+            if pos <= *selected {
+                *selected += 1;
+            }
+        }
+    }
+
+    /// Offers to load the file tunnel was loaded from if it changed on disk since, showing
+    /// what differs from the version in the list.
+    fn check_disk_version(&mut self, idx: usize) {
+        let Some(source) = self.tunnels.get(idx).and_then(|t| t.source.as_ref()) else {
+            return;
+        };
+        if !source.changed() {
+            return;
+        }
+        let path = source.path.clone();
+        let modified = source.current_modified();
+        let on_disk = match wireguard_gui::utils::load_configuration(&path) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!(
+                    "Could not compare {} with the tunnel: {:#?}",
+                    path.display(),
+                    err
+                );
+                self.remember_disk_version(idx, modified);
+                return;
+            }
+        };
+        let diff = diff_configs(&self.tunnels[idx].config, &on_disk);
+        if diff.is_empty() {
+            self.remember_disk_version(idx, modified);
+            return;
+        }
+
+        let settings = &mut self.reload_dialog.state().get_mut().model.settings;
+        settings.text = format!("{} changed on disk", path.display());
+        settings.secondary_text = Some(format!(
+            "Differences from the version in the list:\n{}",
+            diff.join("\n")
+        ));
+        self.pending_reload = Some((idx, Box::new(on_disk), modified));
+        self.reload_dialog.emit(AlertMsg::Show);
+    }
+
+    /// Marks version of tunnel's file modified at `modified` as seen, so it's offered again
+    /// only after the file changes once more.
+    fn remember_disk_version(&mut self, idx: usize, modified: Option<SystemTime>) {
+        if let Some(source) = self
+            .tunnels
+            .guard()
+            .get_mut(idx)
+            .and_then(|t| t.source.as_mut())
+        {
+            source.modified = modified;
+        }
+    }
+
+    /// Updates group headers of tunnels and hides tunnels of collapsed groups, as well as
+    /// inactive ones if only active tunnels are listed.
+    fn sync_groups(&self) {
+        let tags: Vec<Option<String>> = self
+            .tunnels
+            .iter()
+            .map(|t| t.config.interface.tag.clone())
+            .collect();
+        let shown: Vec<bool> = self
+            .tunnels
+            .iter()
+            .map(|t| !self.active_only || t.active)
+            .collect();
+        let headers = shown_group_headers(&tags, &shown);
+
+        let mut hidden = self.hidden_tunnels.borrow_mut();
+        hidden.clear();
+        for (i, (tag, header)) in tags.iter().zip(headers).enumerate() {
+            let collapsed = self
+                .collapsed_groups
+                .contains(&group_of(tag).map(String::from));
+            hidden.push(!shown[i] || (collapsed && header.is_none()));
+
+            let tunnel = &self.tunnels[i];
+            if tunnel.header != header || tunnel.collapsed != collapsed {
+                self.tunnels
+                    .send(i, TunnelMsg::SetGroup { header, collapsed });
+            }
+        }
+        drop(hidden);
+
+        self.tunnels.widget().invalidate_filter();
+    }
+
+    #[cfg(feature = "tray")]
+    fn sync_tray(&self) {
+        let statuses = self
+            .tunnels
+            .iter()
+            .map(|t| TunnelStatus {
+                name: t.name.clone(),
+                active: t.active,
+            })
+            .collect();
+        self.tray_tunnels.send_replace(statuses);
+    }
+
+    /// Warns about AllowedIPs of tunnel overlapping with ones of other active tunnels, if
+    /// the tunnel is active.
+    fn allowed_ips_warning(&self, name: &str) -> Option<String> {
+        let tunnel = self.tunnels.iter().find(|t| t.name == name && t.active)?;
+        let others = self
+            .tunnels
+            .iter()
+            .filter(|t| t.active && t.name != name)
+            .map(|t| (t.name.as_str(), &t.config));
+        let conflicts = wireguard_gui::utils::allowed_ips_conflicts(&tunnel.config, others);
+        (!conflicts.is_empty()).then(|| {
+            format!(
+                "Routes of {name} conflict with other active tunnels:\n{}",
+                conflicts.join("\n")
+            )
+        })
+    }
+
+    fn handle_control_request(&mut self, request: ControlRequest) -> ControlResponse {
+        let status = |t: &Tunnel| TunnelStatus {
+            name: t.name.clone(),
+            active: t.active,
+        };
+
+        let (name, up) = match request {
+            ControlRequest::List => {
+                return ControlResponse::tunnels(self.tunnels.iter().map(status).collect())
+            }
+            ControlRequest::Status { name } => {
+                return match self.tunnels.iter().find(|t| t.name == name) {
+                    Some(t) => ControlResponse::tunnels(vec![status(t)]),
+                    None => ControlResponse::error(format!("No tunnel named {name}")),
+                };
+            }
+            ControlRequest::Up { name } => (name, true),
+            ControlRequest::Down { name } => (name, false),
+        };
+
+        let Some(idx) = self.tunnels.iter().position(|t| t.name == name) else {
+            return ControlResponse::error(format!("No tunnel named {name}"));
+        };
+        let mut tunnels = self.tunnels.guard();
+        let tunnel = tunnels.get_mut(idx).unwrap();
+        let res = tunnel.set_active(up).map(|()| status(tunnel));
+        drop(tunnels);
+        self.tunnels.send(idx, TunnelMsg::PollStats);
+        match res {
+            Ok(status) => {
+                if let Some(warning) = self.allowed_ips_warning(&name) {
+                    eprintln!("{warning}");
+                }
+                ControlResponse::tunnels(vec![status])
+            }
+            Err(err) => ControlResponse::error(err.to_string()),
+        }
+    }
+}
+
+/// Panics unless running as root in builds made with `--cfg release`. The cfg is set by the
+/// release build rather than Cargo, so it isn't known to the cfg check.
+#[allow(unexpected_cfgs)]
+fn require_root() {
     #[cfg(release)]
     if !nix::unistd::Uid::effective().is_root() {
         panic!("You must run this executable with root permissions");
     }
+}
+
+fn main() {
+    cli::init();
+
+    let gtk_app = gtk::Application::new(
+        Some("relm4.ghaf.wireguard-gui"),
+        gtk::gio::ApplicationFlags::HANDLES_OPEN,
+    );
+    // Only one instance runs at a time. Later launches raise its window and hand it the file
+    // to import, so they don't need root permissions.
+    match gtk_app.register(None::<&gtk::gio::Cancellable>) {
+        Ok(()) if gtk_app.is_remote() => {
+            match cli::get_args().import_path() {
+                Some(path) => gtk_app.open(&[gtk::gio::File::for_path(path)], ""),
+                None => gtk_app.activate(),
+            }
+            return;
+        }
+        Ok(()) => (),
+        Err(err) => eprintln!("Could not register application: {err}"),
+    }
+
+    require_root();
+
+    gtk_app.connect_activate(|_| APP_BROKER.send(AppMsg::ShowWindow));
+    gtk_app.connect_open(|_, files, _| {
+        for path in files.iter().filter_map(|f| f.path()) {
+            APP_BROKER.send(AppMsg::ImportTunnel(path));
+        }
+        APP_BROKER.send(AppMsg::ShowWindow);
+    });
 
-    let app = RelmApp::new("relm4.ghaf.wireguard-gui");
+    // Arguments are handled by `cli`, GTK must not try to parse them.
+    let app = RelmApp::from_app(gtk_app)
+        .with_broker(&APP_BROKER)
+        .with_args(vec![]);
     app.run::<App>(());
 }
@@ -1,22 +1,414 @@
 // use gtk::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Instant, SystemTime};
+
 use relm4::factory::{DynamicIndex, FactoryVecDeque};
 use relm4::{gtk::prelude::*, prelude::*};
+use relm4_components::alert::*;
+use relm4_components::open_button::{OpenButton, OpenButtonSettings};
+use relm4_components::open_dialog::OpenDialogSettings;
 
+use crate::cli;
 use crate::config::*;
 use crate::peer::*;
+use crate::qr::*;
+use crate::routing::*;
+use crate::stats::*;
+use crate::utils;
+
+const SHARE_WARNING: &str = "The configuration contains the client's private key. Anyone who reads the mail can connect as this client, so only send it over a channel you trust.";
+const REKEY_WARNING: &str = "The peer already has a PublicKey. The client configuration gets a new keypair that replaces it, so the current client stops connecting once the configuration is saved.";
 
 pub struct OverviewModel {
     interface: Interface,
+    /// All peers of the tunnel. Only the current page of them is shown in `peers`.
+    all_peers: Vec<Peer>,
+    /// Search query peers are filtered by.
+    query: String,
+    /// Indices of peers matching the query, in order they are shown.
+    matching: Vec<usize>,
+    /// Whether peers are shown by latest handshake, most stale first, instead of config order.
+    sort_by_handshake: bool,
+    /// Latest handshakes of peers of the active tunnel by public key.
+    handshakes: HashMap<String, u64>,
+    page: usize,
     peers: FactoryVecDeque<PeerComp>,
+    active: bool,
+    qr_dialog: Controller<QrDialog>,
+    share_dialog: Controller<Alert>,
+    script_button: Controller<OpenButton>,
+    fwmark_dialog: Controller<Alert>,
+    /// Routing script and its file name waiting for confirmation to replace FwMark of the
+    /// interface.
+    pending_script: Option<(RoutingHooks, String)>,
+    merge_dialog: Controller<Alert>,
+    /// Peers of another configuration waiting for confirmation to be appended.
+    pending_merge: Option<Vec<Peer>>,
+    /// Peer and server endpoint host of the client config waiting for share confirmation.
+    pending_share: Option<(DynamicIndex, Option<String>)>,
+    /// Last shared client config, kept until the next one is shared or the application exits,
+    /// as the mail client may read the attachment only when the mail is sent.
+    shared_config: Option<utils::SharedConfig>,
+    rekey_dialog: Controller<Alert>,
+    /// Peer and server endpoint host of the client config waiting for confirmation to replace
+    /// the peer's keys before showing it as QR code.
+    pending_qr: Option<(DynamicIndex, Option<String>)>,
+    keypair_dialog: Controller<Alert>,
+    /// Generated private key of a peer, shown until the dialog is closed.
+    generated_private_key: Option<String>,
+    /// Transfer samples of the active tunnel over the last minute, shared with the graph.
+    transfer: Rc<RefCell<TransferHistory>>,
+    /// Point in time transfer samples are timed from.
+    transfer_epoch: Instant,
+    /// Whether transfer counters are being read.
+    sampling: bool,
+    key_generation: KeyGeneration,
+    public_key_revealed: bool,
+    throughput_graph: gtk::DrawingArea,
+}
+
+/// Background generation of the interface keypair. Save asked for meanwhile is held until
+/// the keys arrive, so it doesn't write the old PrivateKey.
+#[derive(Debug, Default)]
+struct KeyGeneration {
+    /// Bumped on every shown config so that late keypairs are not applied to another tunnel.
+    epoch: u64,
+    running: bool,
+    save_held: bool,
+}
+
+impl KeyGeneration {
+    /// Starts generation unless one is running already, returning its epoch.
+    fn start(&mut self) -> Option<u64> {
+        if self.running {
+            return None;
+        }
+        self.running = true;
+        Some(self.epoch)
+    }
+
+    /// Forgets generation in progress, e.g. when another config is shown.
+    fn cancel(&mut self) {
+        self.epoch += 1;
+        self.running = false;
+        self.save_held = false;
+    }
+
+    /// Whether configuration can be saved right away. Otherwise save is held until generation
+    /// finishes.
+    fn save(&mut self) -> bool {
+        self.save_held |= self.running;
+        !self.running
+    }
+
+    /// Finishes generation started at `epoch`. Returns `None` if the result is stale,
+    /// otherwise whether a held save should run now.
+    fn finish(&mut self, epoch: u64) -> Option<bool> {
+        if !self.running || epoch != self.epoch {
+            return None;
+        }
+        self.running = false;
+        Some(std::mem::take(&mut self.save_held))
+    }
+}
+
+/// Draws received (blue) and sent (orange) rates as lines scaled to the highest rate.
+fn draw_throughput(cr: &gtk::cairo::Context, width: i32, height: i32, rates: &[TransferRate]) {
+    let max = rates.iter().flat_map(|r| [r.rx, r.tx]).fold(1.0, f64::max);
+    let step = f64::from(width) / (rates.len().max(2) - 1) as f64;
+    let y = |rate: f64| f64::from(height) * (1.0 - rate / max);
+
+    let received: Vec<f64> = rates.iter().map(|r| r.rx).collect();
+    let sent: Vec<f64> = rates.iter().map(|r| r.tx).collect();
+    for ((red, green, blue), values) in [((0.2, 0.4, 0.9), received), ((0.9, 0.5, 0.1), sent)] {
+        cr.set_source_rgb(red, green, blue);
+        for (i, value) in values.into_iter().enumerate() {
+            cr.line_to(i as f64 * step, y(value));
+        }
+        // Nothing can be done about drawing errors.
+        let _ = cr.stroke();
+    }
+}
+
+/// Describes the latest transfer rates.
+fn throughput_label(rates: &[TransferRate]) -> String {
+    match rates.last() {
+        Some(rate) => format!(
+            "Received {}, sent {}",
+            format_rate(rate.rx),
+            format_rate(rate.tx)
+        ),
+        None => String::from("Measuring..."),
+    }
+}
+
+/// Notes which routing script the hooks came from.
+fn routing_script_label(name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!(
+            "Hooks managed by routing script {name}. Applying it again appends its commands once more."
+        ),
+        None => String::new(),
+    }
+}
+
+/// Describes time tunnel was last connected in local time.
+fn last_connected_label(time: Option<&str>) -> String {
+    let Some(time) = time else {
+        return String::new();
+    };
+    let local = gtk::glib::DateTime::from_iso8601(time, None)
+        .and_then(|t| t.to_local())
+        .and_then(|t| t.format("%c"));
+    format!("Last connected: {}", local.as_deref().unwrap_or(time))
+}
+
+/// Backs up the keypair that is about to be replaced, if there is one.
+fn backup_keys(name: Option<String>, private_key: Option<String>) -> Result<(), String> {
+    let Some(private_key) = private_key else {
+        return Ok(());
+    };
+    let name = name.ok_or("Interface has no name")?;
+    utils::generate_public_key(private_key.clone())
+        .and_then(|public_key| {
+            utils::backup_keypair(
+                &utils::key_backups_dir(),
+                &name,
+                &private_key,
+                &public_key,
+                SystemTime::now(),
+            )
+        })
+        .map(|_| ())
+        .map_err(|e| format!("Could not back up keys of {name}: {e}"))
+}
+
+/// Whether field is set to something other than blank or `unknown`.
+fn has_value(value: &Option<String>) -> bool {
+    value
+        .as_deref()
+        .is_some_and(|v| !v.trim().is_empty() && v.trim() != "unknown")
 }
 
 impl OverviewModel {
     pub fn replace_peers(&mut self, peers: Vec<Peer>) {
+        self.all_peers = peers;
+        self.query.clear();
+        self.update_matching();
+        self.show_page(0);
+    }
+
+    /// Configuration as currently edited, including unsaved changes.
+    pub fn current_config(&mut self) -> WireguardConfig {
+        self.sync_page();
+        WireguardConfig {
+            interface: self.interface.clone(),
+            peers: self.all_peers.clone(),
+        }
+    }
+
+    fn update_matching(&mut self) {
+        self.matching = (0..self.all_peers.len())
+            .filter(|&i| self.all_peers[i].matches(&self.query))
+            .collect();
+        if self.sort_by_handshake {
+            let handshake = |i: usize| {
+                self.all_peers[i]
+                    .public_key
+                    .as_deref()
+                    .and_then(|k| self.handshakes.get(k.trim()).copied())
+            };
+            let mut matching = std::mem::take(&mut self.matching);
+            matching.sort_by(|&a, &b| stalest_first(handshake(a), handshake(b)));
+            self.matching = matching;
+        }
+    }
+
+    /// Shows peers in order of the new handshakes, staying on the current page. Rows are only
+    /// rebuilt if the order changed, so editing isn't interrupted needlessly.
+    fn resort_peers(&mut self) {
+        if !self.sort_by_handshake {
+            return;
+        }
+        self.sync_page();
+        let previous = self.matching.clone();
+        self.update_matching();
+        if self.matching != previous {
+            self.show_page(self.page);
+        }
+    }
+
+    fn page_size() -> usize {
+        cli::get_args().peers_per_page.max(1)
+    }
+
+    fn page_count(&self) -> usize {
+        self.matching.len().div_ceil(Self::page_size()).max(1)
+    }
+
+    /// Stores edits of peers on the current page.
+    fn sync_page(&mut self) {
+        let start = self.page * Self::page_size();
+        for (i, p) in self.peers.iter().enumerate() {
+            self.all_peers[self.matching[start + i]] = p.peer.clone();
+        }
+    }
+
+    /// Shows given page of peers. Edits on the current page have to be synced before.
+    fn show_page(&mut self, page: usize) {
+        self.page = page.min(self.page_count() - 1);
+
+        let start = self.page * Self::page_size();
+        let end = (start + Self::page_size()).min(self.matching.len());
+
         let mut ps = self.peers.guard();
         ps.clear();
+        for &i in &self.matching[start..end] {
+            ps.push_back(self.all_peers[i].clone());
+        }
+        drop(ps);
+
+        self.notify_peers_active();
+    }
+
+    /// Commands of interface hooks in order they are run.
+    fn hook_steps(&self) -> String {
+        format_hook_steps(&[
+            ("PreUp", &self.interface.pre_up),
+            ("PostUp", &self.interface.post_up),
+            ("PreDown", &self.interface.pre_down),
+            ("PostDown", &self.interface.post_down),
+        ])
+    }
+
+    /// Advisory warnings about the shown configuration. Peer edits are taken into account once
+    /// they are synced.
+    fn lints(&self) -> Vec<String> {
+        lint_config(&WireguardConfig {
+            interface: self.interface.clone(),
+            peers: self.all_peers.clone(),
+        })
+    }
+
+    /// Appends peer and shows it on the last page.
+    fn append_peer(&mut self, peer: Peer) {
+        self.sync_page();
+        self.all_peers.push(peer);
+        // New peer might not match the search.
+        self.query.clear();
+        self.update_matching();
+        self.show_page(self.page_count() - 1);
+    }
+
+    /// Derives configuration of the client behind peer with newly generated keys. Returns it
+    /// with client's public key.
+    fn client_config(
+        &self,
+        idx: &DynamicIndex,
+        endpoint_host: Option<String>,
+    ) -> Result<(WireguardConfig, String), String> {
+        let peer = self
+            .peers
+            .get(idx.current_index())
+            .ok_or("Peer doesn't exist")?;
+        let server_private_key = self
+            .interface
+            .private_key
+            .clone()
+            .ok_or("Interface has no PrivateKey")?;
+
+        let server_public_key =
+            utils::generate_public_key(server_private_key).map_err(|e| e.to_string())?;
+        let client_private_key = utils::generate_private_key().map_err(|e| e.to_string())?;
+        let client_public_key =
+            utils::generate_public_key(client_private_key.clone()).map_err(|e| e.to_string())?;
+
+        let config = derive_client_config(
+            &self.interface,
+            &server_public_key,
+            &peer.peer,
+            &client_private_key,
+            endpoint_host.as_deref(),
+        )?;
+
+        Ok((config, client_public_key))
+    }
+
+    /// Whether peer already has a PublicKey, which client configs derived for it replace.
+    fn peer_has_key(&self, idx: &DynamicIndex) -> bool {
+        self.peers
+            .get(idx.current_index())
+            .is_some_and(|p| has_value(&p.peer.public_key))
+    }
+
+    /// Derives client config of peer and shows it as QR code, replacing PublicKey of the peer
+    /// with the one of the client.
+    fn show_client_qr(
+        &mut self,
+        idx: &DynamicIndex,
+        host: Option<String>,
+        sender: &ComponentSender<Self>,
+    ) {
+        match self.client_config(idx, host) {
+            Ok((config, client_public_key)) => {
+                self.peers.send(
+                    idx.current_index(),
+                    PeerInput::Set(PeerSetKind::PublicKey, Some(client_public_key)),
+                );
+                self.qr_dialog.emit(QrInput::Show {
+                    title: format!(
+                        "Client configuration of {}",
+                        get_value(&config.interface.name)
+                    ),
+                    data: write_config(&config),
+                    note: String::from("New keys were generated for the client and peer PublicKey was updated. Save the configuration to apply it."),
+                });
+            }
+            Err(e) => sender.output_sender().emit(OverviewOutput::Error(e)),
+        }
+    }
 
-        for peer in peers {
-            ps.push_back(peer);
+    /// Generates new private key of the interface after backing up the current keypair.
+    fn rotate_keys(&mut self) -> Result<(), String> {
+        utils::rotate_keys(&mut self.interface, &utils::key_backups_dir()).map(|_| ())
+    }
+
+    /// Sets private key of the interface, keeping its stored public key in sync.
+    fn set_private_key(&mut self, private_key: String) -> Result<(), String> {
+        if self.interface.public_key.is_some() {
+            self.interface.public_key =
+                Some(utils::generate_public_key(private_key.clone()).map_err(|e| e.to_string())?);
+        }
+        self.interface.private_key = Some(private_key);
+        Ok(())
+    }
+
+    /// Drops generated private key of a peer once its dialog is closed.
+    fn forget_generated_key(&mut self) {
+        self.generated_private_key = None;
+        self.keypair_dialog
+            .state()
+            .get_mut()
+            .model
+            .settings
+            .secondary_text = None;
+    }
+
+    /// Drops transfer samples, e.g. when other tunnel is shown.
+    fn clear_transfer(&mut self) {
+        self.transfer.borrow_mut().clear();
+        self.handshakes.clear();
+        self.throughput_graph.queue_draw();
+    }
+
+    /// Lets peers know whether they can be reached through the tunnel.
+    fn notify_peers_active(&self) {
+        let interface = self.active.then(|| self.interface.name.clone()).flatten();
+        for i in 0..self.peers.len() {
+            self.peers
+                .send(i, PeerInput::SetActiveInterface(interface.clone()));
         }
     }
 }
@@ -24,11 +416,13 @@ impl OverviewModel {
 #[derive(Debug)]
 pub enum InterfaceSetKind {
     Name,
+    Tag,
     Address,
     ListenPort,
     PrivateKey,
     Dns,
     Table,
+    FwMark,
     Mtu,
     PreUp,
     PostUp,
@@ -36,18 +430,135 @@ pub enum InterfaceSetKind {
     PostDown,
 }
 
+impl InterfaceSetKind {
+    /// Current value of the field.
+    fn value<'a>(&self, interface: &'a Interface) -> &'a Option<String> {
+        match self {
+            Self::Name => &interface.name,
+            Self::Tag => &interface.tag,
+            Self::Address => &interface.address,
+            Self::ListenPort => &interface.listen_port,
+            Self::PrivateKey => &interface.private_key,
+            Self::Dns => &interface.dns,
+            Self::Table => &interface.table,
+            Self::FwMark => &interface.fwmark,
+            Self::Mtu => &interface.mtu,
+            Self::PreUp => &interface.pre_up,
+            Self::PostUp => &interface.post_up,
+            Self::PreDown => &interface.pre_down,
+            Self::PostDown => &interface.post_down,
+        }
+    }
+
+    /// Key of the field in configuration file, `None` for metadata.
+    fn config_key(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::Name | Self::Tag => return None,
+            Self::Address => "Address",
+            Self::ListenPort => "ListenPort",
+            Self::PrivateKey => "PrivateKey",
+            Self::Dns => "DNS",
+            Self::Table => "Table",
+            Self::FwMark => "FwMark",
+            Self::Mtu => "MTU",
+            Self::PreUp => "PreUp",
+            Self::PostUp => "PostUp",
+            Self::PreDown => "PreDown",
+            Self::PostDown => "PostDown",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum PageChange {
+    Previous,
+    Next,
+}
+
 #[derive(Debug)]
 pub enum OverviewInput {
     CollectTunnel,
+    /// Copies shown configuration with secret keys redacted to the clipboard.
+    CopySanitizedConfig,
     ShowConfig(Box<WireguardConfig>),
     RemovePeer(DynamicIndex),
+    /// Sets AllowedIPs of peer to the next free address of the interface subnet.
+    AssignPeerAddress(DynamicIndex),
+    /// Sets PublicKey of peer from a new keypair and shows its private key.
+    GeneratePeerKeypair(DynamicIndex),
+    #[doc(hidden)]
+    CopyGeneratedKey,
+    #[doc(hidden)]
+    DiscardGeneratedKey,
     AddPeer,
+    /// Appends peer parsed from `[Peer]` section in the clipboard.
+    PastePeer,
+    #[doc(hidden)]
+    PastePeerText(String),
+    ShowPage(PageChange),
+    SearchPeers(String),
+    /// Shows peers by latest handshake, most stale first, or in config order.
+    SortByHandshake(bool),
+    /// Sets PersistentKeepalive of every peer.
+    SetKeepaliveOnAll(String),
+    /// Reads transfer counters of the tunnel if it's active.
+    SampleTransfer,
+    #[doc(hidden)]
+    TransferSampled(Result<(TransferSample, HashMap<String, u64>), String>),
+    PeerError(String),
+    PeerWarning(String),
+    /// Generates new keys for the client behind peer and shows its config as QR code, asking
+    /// first if the peer already has a PublicKey.
+    ShowClientQr(DynamicIndex, Option<String>),
+    #[doc(hidden)]
+    ShowClientQrConfirmed,
+    #[doc(hidden)]
+    ShowClientQrCancelled,
+    /// Asks to confirm sharing client config of peer by mail.
+    ShareClientConfig(DynamicIndex, Option<String>),
+    #[doc(hidden)]
+    ShareClientConfigConfirmed,
+    #[doc(hidden)]
+    ShareClientConfigCancelled,
+    /// Whether shown tunnel is currently active.
+    SetActive(bool),
+    /// Time shown tunnel was last brought up.
+    SetLastConnected(Option<String>),
     SetInterface(InterfaceSetKind, Option<String>),
+    /// Adds route with destination and optional gateway as interface hooks.
+    AddRoute(String, String),
+    /// Appends hooks of routing script, asking first if it would replace FwMark.
+    ApplyRoutingScript(std::path::PathBuf),
+    #[doc(hidden)]
+    ApplyPendingScript,
+    #[doc(hidden)]
+    DiscardPendingScript,
+    /// Appends peers of configuration file that aren't present yet, after confirmation.
+    MergePeersFrom(std::path::PathBuf),
+    #[doc(hidden)]
+    MergePendingPeers,
+    #[doc(hidden)]
+    DiscardPendingPeers,
+    CopyPrivateKey,
+    CopyPublicKey,
+    RevealPublicKey(bool),
+    /// Replaces interface keypair with a new one, generated in the background.
+    /// Existing keypair is backed up first.
+    GenerateKeypair,
+    #[doc(hidden)]
+    SetGeneratedKeys(u64, Result<(String, String), String>),
+    /// Replaces interface private key with a new one, backing up the old keypair.
+    RotateKeys,
+    /// Restores private key from the latest keypair backup.
+    RestorePreviousKey,
 }
 
 #[derive(Debug)]
 pub enum OverviewOutput {
     SaveConfig(Box<WireguardConfig>),
+    Error(String),
+    /// Advisory message that doesn't prevent the change.
+    Warning(String),
 }
 
 #[relm4::component(pub)]
@@ -61,191 +572,475 @@ impl SimpleComponent for OverviewModel {
             set_orientation: gtk::Orientation::Vertical,
 
             gtk::Frame::new(Some("Interface:")) {
-                gtk::Grid {
-                    set_row_spacing: 5,
-                    set_column_spacing: 5,
-                    set_margin_all: 5,
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
 
-                    attach[0, 0, 1, 1] = &gtk::Label {
-                        set_label: "# Name:",
-                        set_halign: gtk::Align::Start,
-                    },
-                    #[name = "name"]
-                    attach[1, 0, 1, 1] = &gtk::EditableLabel {
-                        #[watch]
-                        set_text: get_value(&model.interface.name),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::Name, (new != "unknown").then_some(new)));
-                            }
+                    gtk::Grid {
+                        set_row_spacing: 5,
+                        set_column_spacing: 5,
+                        set_margin_all: 5,
+
+                        attach[0, 0, 1, 1] = &gtk::Label {
+                            set_label: "# Name:",
+                            set_halign: gtk::Align::Start,
+                        },
+                        #[name = "name"]
+                        attach[1, 0, 1, 1] = &gtk::EditableLabel {
+                            #[watch]
+                            set_text: get_value(&model.interface.name),
+                            connect_editing_notify[sender] => move |l| {
+                                if !l.is_editing() {
+                                    let new: String = l.text().trim().into();
+                                    sender.input(Self::Input::SetInterface(InterfaceSetKind::Name, (new != "unknown").then_some(new)));
+                                }
+                            },
                         },
-                    },
 
-                    attach[0, 1, 1, 1] = &gtk::Label {
-                        set_label: "Address:",
-                        set_halign: gtk::Align::Start,
-                    },
-                    #[name = "address"]
-                    attach[1, 1, 1, 1] = &gtk::EditableLabel {
-                        #[watch]
-                        set_text: get_value(&model.interface.address),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::Address, (new != "unknown").then_some(new)));
-                            }
+                        attach[0, 1, 1, 1] = &gtk::Label {
+                            set_label: "# Tag:",
+                            set_halign: gtk::Align::Start,
+                        },
+                        attach[1, 1, 1, 1] = &gtk::EditableLabel {
+                            #[watch]
+                            set_text: get_value(&model.interface.tag),
+                            connect_editing_notify[sender] => move |l| {
+                                if !l.is_editing() {
+                                    let new: String = l.text().trim().into();
+                                    sender.input(Self::Input::SetInterface(InterfaceSetKind::Tag, (new != "unknown" && !new.is_empty()).then_some(new)));
+                                }
+                            },
                         },
-                    },
 
-                    attach[0, 2, 1, 1] = &gtk::Label {
-                        set_label: "ListenPort:",
-                        set_halign: gtk::Align::Start,
-                    },
-                    #[name = "listen_port"]
-                    attach[1, 2, 1, 1] = &gtk::EditableLabel {
-                        #[watch]
-                        set_text: get_value(&model.interface.listen_port),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::ListenPort, (new != "unknown").then_some(new)));
-                            }
+                        attach[0, 2, 1, 1] = &gtk::Label {
+                            set_label: "Address:",
+                            set_halign: gtk::Align::Start,
+                        },
+                        #[name = "address"]
+                        attach[1, 2, 1, 1] = &gtk::EditableLabel {
+                            #[watch]
+                            set_text: get_value(&model.interface.address),
+                            connect_editing_notify[sender] => move |l| {
+                                if !l.is_editing() {
+                                    let new: String = l.text().trim().into();
+                                    sender.input(Self::Input::SetInterface(InterfaceSetKind::Address, (new != "unknown").then_some(new)));
+                                }
+                            },
                         },
-                    },
 
-                    // TODO: Just show omitted
-                    attach[0, 3, 1, 1] = &gtk::Label {
-                        set_label: "PrivateKey:",
-                        set_halign: gtk::Align::Start,
-                    },
-                    #[name = "private_key"]
-                    attach[1, 3, 1, 1] = &gtk::EditableLabel {
-                        #[watch]
-                        set_text: get_value(&model.interface.private_key),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::PrivateKey, (new != "unknown").then_some(new)));
-                            }
+                        attach[0, 3, 1, 1] = &gtk::Label {
+                            set_label: "ListenPort:",
+                            set_halign: gtk::Align::Start,
+                        },
+                        #[name = "listen_port"]
+                        attach[1, 3, 1, 1] = &gtk::EditableLabel {
+                            #[watch]
+                            set_text: get_value(&model.interface.listen_port),
+                            connect_editing_notify[sender] => move |l| {
+                                if !l.is_editing() {
+                                    let new: String = l.text().trim().into();
+                                    sender.input(Self::Input::SetInterface(InterfaceSetKind::ListenPort, (new != "unknown").then_some(new)));
+                                }
+                            },
                         },
-                    },
 
-                    attach[0, 4, 1, 1] = &gtk::Label {
-                        set_label: "DNS:",
-                        set_halign: gtk::Align::Start,
-                    },
-                    #[name = "dns"]
-                    attach[1, 4, 1, 1] = &gtk::EditableLabel {
-                        #[watch]
-                        set_text: get_value(&model.interface.dns),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::Dns, (new != "unknown").then_some(new)));
-                            }
+                        // TODO: Just show omitted
+                        attach[0, 4, 1, 1] = &gtk::Label {
+                            set_label: "PrivateKey:",
+                            set_halign: gtk::Align::Start,
+                        },
+                        #[name = "private_key"]
+                        attach[1, 4, 1, 1] = &gtk::EditableLabel {
+                            #[watch]
+                            set_text: get_value(&model.interface.private_key),
+                            connect_editing_notify[sender] => move |l| {
+                                if !l.is_editing() {
+                                    let new: String = l.text().trim().into();
+                                    sender.input(Self::Input::SetInterface(InterfaceSetKind::PrivateKey, (new != "unknown").then_some(new)));
+                                }
+                            },
                         },
-                    },
 
-                    attach[0, 5, 1, 1] = &gtk::Label {
-                        set_label: "Table:",
-                        set_halign: gtk::Align::Start,
-                    },
-                    #[name = "table"]
-                    attach[1, 5, 1, 1] = &gtk::EditableLabel {
-                        #[watch]
-                        set_text: get_value(&model.interface.table),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::Table, (new != "unknown").then_some(new)));
-                            }
+                        attach[2, 4, 1, 1] = &gtk::Box {
+                            set_spacing: 5,
+
+                            gtk::Button::with_label("Copy") {
+                                #[watch]
+                                set_sensitive: has_value(&model.interface.private_key),
+                                connect_clicked => Self::Input::CopyPrivateKey,
+                            },
+
+                            gtk::Button::with_label("Generate Keypair") {
+                                #[watch]
+                                set_sensitive: !model.key_generation.running,
+                                connect_clicked => Self::Input::GenerateKeypair,
+                            },
+
+                            gtk::Button::with_label("Rotate Keys") {
+                                connect_clicked => Self::Input::RotateKeys,
+                            },
+
+                            gtk::Button::with_label("Restore Previous Key") {
+                                connect_clicked => Self::Input::RestorePreviousKey,
+                            },
                         },
-                    },
 
-                    attach[0, 6, 1, 1] = &gtk::Label {
-                        set_label: "MTU:",
-                        set_halign: gtk::Align::Start,
+                        attach[0, 5, 1, 1] = &gtk::Label {
+                            set_label: "# PublicKey:",
+                            set_halign: gtk::Align::Start,
+                        },
+                        attach[1, 5, 1, 1] = &gtk::Label {
+                            set_halign: gtk::Align::Start,
+                            set_selectable: true,
+                            #[watch]
+                            set_label: masked(&model.interface.public_key, model.public_key_revealed),
+                        },
+                        attach[2, 5, 1, 1] = &gtk::Box {
+                            set_spacing: 5,
+
+                            gtk::ToggleButton::with_label("Reveal") {
+                                #[watch]
+                                #[block_signal(reveal_handler)]
+                                set_active: model.public_key_revealed,
+                                connect_toggled[sender] => move |b| {
+                                    sender.input(Self::Input::RevealPublicKey(b.is_active()));
+                                } @reveal_handler,
+                            },
+
+                            gtk::Button::with_label("Copy") {
+                                #[watch]
+                                set_sensitive: has_value(&model.interface.public_key),
+                                connect_clicked => Self::Input::CopyPublicKey,
+                            },
+                        },
+
+                        attach[0, 6, 1, 1] = &gtk::Label {
+                            set_label: "DNS:",
+                            set_halign: gtk::Align::Start,
+                        },
+                        #[name = "dns"]
+                        attach[1, 6, 1, 1] = &gtk::EditableLabel {
+                            #[watch]
+                            set_text: get_value(&model.interface.dns),
+                            connect_editing_notify[sender] => move |l| {
+                                if !l.is_editing() {
+                                    let new: String = l.text().trim().into();
+                                    sender.input(Self::Input::SetInterface(InterfaceSetKind::Dns, (new != "unknown").then_some(new)));
+                                }
+                            },
+                        },
                     },
-                    #[name = "mtu"]
-                    attach[1, 6, 1, 1] = &gtk::EditableLabel {
-                        #[watch]
-                        set_text: get_value(&model.interface.mtu),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::Mtu, (new != "unknown").then_some(new)));
-                            }
+
+                    // Collapsed until opened, then stays open while switching tunnels.
+                    gtk::Expander {
+                        set_label: Some("Advanced"),
+                        set_margin_all: 5,
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Vertical,
+
+                            gtk::Grid {
+                                set_row_spacing: 5,
+                                set_column_spacing: 5,
+                                set_margin_all: 5,
+
+                                attach[0, 0, 1, 1] = &gtk::Label {
+                                    set_label: "Table:",
+                                    set_halign: gtk::Align::Start,
+                                },
+                                #[name = "table"]
+                                attach[1, 0, 1, 1] = &gtk::EditableLabel {
+                                    #[watch]
+                                    set_text: get_value(&model.interface.table),
+                                    connect_editing_notify[sender] => move |l| {
+                                        if !l.is_editing() {
+                                            let new: String = l.text().trim().into();
+                                            sender.input(Self::Input::SetInterface(InterfaceSetKind::Table, (new != "unknown").then_some(new)));
+                                        }
+                                    },
+                                },
+
+                                attach[0, 1, 1, 1] = &gtk::Label {
+                                    set_label: "MTU:",
+                                    set_halign: gtk::Align::Start,
+                                },
+                                #[name = "mtu"]
+                                attach[1, 1, 1, 1] = &gtk::EditableLabel {
+                                    #[watch]
+                                    set_text: get_value(&model.interface.mtu),
+                                    connect_editing_notify[sender] => move |l| {
+                                        if !l.is_editing() {
+                                            let new: String = l.text().trim().into();
+                                            sender.input(Self::Input::SetInterface(InterfaceSetKind::Mtu, (new != "unknown").then_some(new)));
+                                        }
+                                    },
+                                },
+
+                                attach[0, 2, 1, 1] = &gtk::Label {
+                                    set_label: "FwMark:",
+                                    set_halign: gtk::Align::Start,
+                                },
+                                #[name = "fwmark"]
+                                attach[1, 2, 1, 1] = &gtk::EditableLabel {
+                                    #[watch]
+                                    set_text: get_value(&model.interface.fwmark),
+                                    connect_editing_notify[sender] => move |l| {
+                                        if !l.is_editing() {
+                                            let new: String = l.text().trim().into();
+                                            sender.input(Self::Input::SetInterface(InterfaceSetKind::FwMark, (new != "unknown").then_some(new)));
+                                        }
+                                    },
+                                },
+
+                                attach[0, 3, 1, 1] = &gtk::Label {
+                                    set_label: "PreUp:",
+                                    set_halign: gtk::Align::Start,
+                                },
+                                #[name = "pre_up"]
+                                attach[1, 3, 1, 1] = &gtk::EditableLabel {
+                                    #[watch]
+                                    set_text: get_value(&model.interface.pre_up),
+                                    connect_editing_notify[sender] => move |l| {
+                                        if !l.is_editing() {
+                                            let new: String = l.text().trim().into();
+                                            sender.input(Self::Input::SetInterface(InterfaceSetKind::PreUp, (new != "unknown").then_some(new)));
+                                        }
+                                    },
+                                },
+
+                                attach[0, 4, 1, 1] = &gtk::Label {
+                                    set_label: "PostUp:",
+                                    set_halign: gtk::Align::Start,
+                                },
+                                #[name = "post_up"]
+                                attach[1, 4, 1, 1] = &gtk::EditableLabel {
+                                    #[watch]
+                                    set_text: get_value(&model.interface.post_up),
+                                    connect_editing_notify[sender] => move |l| {
+                                        if !l.is_editing() {
+                                            let new: String = l.text().trim().into();
+                                            sender.input(Self::Input::SetInterface(InterfaceSetKind::PostUp, (new != "unknown").then_some(new)));
+                                        }
+                                    },
+                                },
+
+                                attach[0, 5, 1, 1] = &gtk::Label {
+                                    set_label: "PreDown:",
+                                    set_halign: gtk::Align::Start,
+                                },
+                                #[name = "pre_down"]
+                                attach[1, 5, 1, 1] = &gtk::EditableLabel {
+                                    #[watch]
+                                    set_text: get_value(&model.interface.pre_down),
+                                    connect_editing_notify[sender] => move |l| {
+                                        if !l.is_editing() {
+                                            let new: String = l.text().trim().into();
+                                            sender.input(Self::Input::SetInterface(InterfaceSetKind::PreDown, (new != "unknown").then_some(new)));
+                                        }
+                                    },
+                                },
+
+                                attach[0, 6, 1, 1] = &gtk::Label {
+                                    set_label: "PostDown:",
+                                    set_halign: gtk::Align::Start,
+                                },
+                                #[name = "post_down"]
+                                attach[1, 6, 1, 1] = &gtk::EditableLabel {
+                                    #[watch]
+                                    set_text: get_value(&model.interface.post_down),
+                                    connect_editing_notify[sender] => move |l| {
+                                        if !l.is_editing() {
+                                            let new: String = l.text().trim().into();
+                                            sender.input(Self::Input::SetInterface(InterfaceSetKind::PostDown, (new != "unknown").then_some(new)));
+                                        }
+                                    },
+                                },
+                            },
+
+                            gtk::Frame::new(Some("Routes:")) {
+                                gtk::Box {
+                                    set_spacing: 5,
+                                    set_margin_all: 5,
+
+                                    #[name = "route_destination"]
+                                    gtk::Entry {
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("Destination, e.g. 10.1.0.0/16"),
+                                    },
+                                    #[name = "route_via"]
+                                    gtk::Entry {
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("Via (Optional)"),
+                                    },
+                                    gtk::Button {
+                                        set_label: "Add Route",
+                                        connect_clicked[sender, route_destination, route_via] => move |_| {
+                                            sender.input(Self::Input::AddRoute(
+                                                route_destination.text().into(),
+                                                route_via.text().into(),
+                                            ));
+                                            route_destination.set_text("");
+                                            route_via.set_text("");
+                                        },
+                                    },
+
+                                    append: model.script_button.widget(),
+                                }
+                            },
                         },
                     },
+                }
+            },
+
+            gtk::Label {
+                add_css_class: "warning",
+                set_halign: gtk::Align::Start,
+                set_wrap: true,
+                set_margin_all: 5,
+                #[watch]
+                set_visible: !model.lints().is_empty(),
+                #[watch]
+                set_label: &model.lints().join("\n"),
+            },
+
+            gtk::Expander {
+                set_label: Some("Hook Commands"),
+                set_margin_all: 5,
+                #[watch]
+                set_visible: !model.hook_steps().is_empty(),
 
-                    attach[0, 7, 1, 1] = &gtk::Label {
-                        set_label: "PreUp:",
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 5,
+
+                    gtk::Label {
+                        add_css_class: "dim-label",
                         set_halign: gtk::Align::Start,
-                    },
-                    #[name = "pre_up"]
-                    attach[1, 7, 1, 1] = &gtk::EditableLabel {
+                        set_wrap: true,
                         #[watch]
-                        set_text: get_value(&model.interface.pre_up),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::PreUp, (new != "unknown").then_some(new)));
-                            }
-                        },
+                        set_visible: model.interface.routing_script.is_some(),
+                        #[watch]
+                        set_label: &routing_script_label(model.interface.routing_script.as_deref()),
                     },
 
-                    attach[0, 8, 1, 1] = &gtk::Label {
-                        set_label: "PostUp:",
+                    gtk::Label {
                         set_halign: gtk::Align::Start,
-                    },
-                    #[name = "post_up"]
-                    attach[1, 8, 1, 1] = &gtk::EditableLabel {
+                        set_selectable: true,
                         #[watch]
-                        set_text: get_value(&model.interface.post_up),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::PostUp, (new != "unknown").then_some(new)));
-                            }
-                        },
+                        set_label: &model.hook_steps(),
                     },
+                },
+            },
+
+            gtk::Label {
+                set_halign: gtk::Align::Start,
+                set_margin_all: 5,
+                #[watch]
+                set_visible: model.interface.last_connected.is_some(),
+                #[watch]
+                set_label: &last_connected_label(model.interface.last_connected.as_deref()),
+            },
+
+            gtk::Frame::new(Some("Throughput:")) {
+                #[watch]
+                set_visible: model.active && cli::get_args().stats_interval().is_some(),
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 5,
+                    set_margin_all: 5,
 
-                    attach[0, 9, 1, 1] = &gtk::Label {
-                        set_label: "PreDown:",
+                    gtk::Label {
                         set_halign: gtk::Align::Start,
-                    },
-                    #[name = "pre_down"]
-                    attach[1, 9, 1, 1] = &gtk::EditableLabel {
                         #[watch]
-                        set_text: get_value(&model.interface.pre_down),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::PreDown, (new != "unknown").then_some(new)));
-                            }
-                        },
+                        set_label: &throughput_label(&model.transfer.borrow().rates()),
                     },
 
-                    attach[0, 10, 1, 1] = &gtk::Label {
-                        set_label: "PostDown:",
-                        set_halign: gtk::Align::Start,
+                    #[local_ref]
+                    throughput_graph -> gtk::DrawingArea {
+                        set_hexpand: true,
+                        set_content_height: 40,
                     },
-                    #[name = "post_down"]
-                    attach[1, 10, 1, 1] = &gtk::EditableLabel {
-                        #[watch]
-                        set_text: get_value(&model.interface.post_down),
-                        connect_editing_notify[sender] => move |l| {
-                            if !l.is_editing() {
-                                let new: String = l.text().trim().into();
-                                sender.input(Self::Input::SetInterface(InterfaceSetKind::PostDown, (new != "unknown").then_some(new)));
-                            }
-                        },
+                },
+            },
+
+            gtk::Box {
+                set_spacing: 5,
+                set_margin_all: 5,
+
+                #[name = "keepalive_all"]
+                gtk::Entry {
+                    set_hexpand: true,
+                    set_placeholder_text: Some("PersistentKeepalive, e.g. 25 or off"),
+                },
+                gtk::Button {
+                    set_label: "Set Keepalive on All Peers",
+                    connect_clicked[sender, keepalive_all] => move |_| {
+                        sender.input(Self::Input::SetKeepaliveOnAll(keepalive_all.text().into()));
                     },
-                }
+                },
+            },
+
+            gtk::Box {
+                set_spacing: 5,
+                set_margin_all: 5,
+
+                gtk::SearchEntry {
+                    set_hexpand: true,
+                    set_placeholder_text: Some("Search peers by name, public key or endpoint"),
+                    #[track = "model.query.is_empty()"]
+                    #[block_signal(search_handler)]
+                    set_text: "",
+                    connect_search_changed[sender] => move |e| {
+                        sender.input(Self::Input::SearchPeers(e.text().trim().into()));
+                    } @search_handler,
+                },
+
+                gtk::CheckButton {
+                    set_label: Some("Most stale first"),
+                    set_tooltip_text: Some("Show peers by latest handshake while the tunnel is active, without changing their order in the configuration"),
+                    connect_toggled[sender] => move |b| {
+                        sender.input(Self::Input::SortByHandshake(b.is_active()));
+                    },
+                },
+            },
+
+            gtk::Label {
+                set_margin_all: 10,
+                #[watch]
+                set_visible: model.matching.is_empty(),
+                #[watch]
+                set_label: if model.all_peers.is_empty() {
+                    "No peers yet — click Add Peer"
+                } else {
+                    "No peers match the search"
+                },
             },
 
-            append: model.peers.widget()
+            append: model.peers.widget(),
+
+            gtk::Box {
+                set_spacing: 5,
+                set_halign: gtk::Align::Center,
+                #[watch]
+                set_visible: model.page_count() > 1,
+
+                gtk::Button::with_label("Previous") {
+                    #[watch]
+                    set_sensitive: model.page > 0,
+                    connect_clicked => Self::Input::ShowPage(PageChange::Previous),
+                },
+
+                gtk::Label {
+                    #[watch]
+                    set_label: &format!("Page {} of {}", model.page + 1, model.page_count()),
+                },
+
+                gtk::Button::with_label("Next") {
+                    #[watch]
+                    set_sensitive: model.page + 1 < model.page_count(),
+                    connect_clicked => Self::Input::ShowPage(PageChange::Next),
+                },
+            },
         }
     }
 
@@ -258,11 +1053,164 @@ impl SimpleComponent for OverviewModel {
             .launch(gtk::Box::new(gtk::Orientation::Vertical, 5))
             .forward(sender.input_sender(), |output| match output {
                 PeerOutput::Remove(idx) => Self::Input::RemovePeer(idx),
+                PeerOutput::AssignAddress(idx) => Self::Input::AssignPeerAddress(idx),
+                PeerOutput::GenerateKeypair(idx) => Self::Input::GeneratePeerKeypair(idx),
+                PeerOutput::ShowClientQr(idx, host) => Self::Input::ShowClientQr(idx, host),
+                PeerOutput::ShareClientConfig(idx, host) => {
+                    Self::Input::ShareClientConfig(idx, host)
+                }
+                PeerOutput::Error(e) => Self::Input::PeerError(e),
+                PeerOutput::Warning(w) => Self::Input::PeerWarning(w),
+            });
+
+        let qr_dialog = QrDialog::builder()
+            .launch(())
+            .forward(sender.input_sender(), |msg| match msg {
+                QrOutput::Error(e) => Self::Input::PeerError(e),
             });
 
+        let share_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::from("Share client configuration?"),
+                secondary_text: Some(String::from(SHARE_WARNING)),
+                confirm_label: Some(String::from("Share")),
+                cancel_label: Some(String::from("Cancel")),
+                option_label: None,
+                is_modal: true,
+                destructive_accept: true,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::ShareClientConfigConfirmed,
+                _ => Self::Input::ShareClientConfigCancelled,
+            });
+
+        let rekey_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::from("Replace keys of the client?"),
+                secondary_text: Some(String::from(REKEY_WARNING)),
+                confirm_label: Some(String::from("Generate New Keys")),
+                cancel_label: Some(String::from("Cancel")),
+                option_label: None,
+                is_modal: true,
+                destructive_accept: true,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::ShowClientQrConfirmed,
+                _ => Self::Input::ShowClientQrCancelled,
+            });
+
+        let keypair_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::from("Peer keypair generated"),
+                secondary_text: None,
+                confirm_label: Some(String::from("Copy Private Key")),
+                cancel_label: Some(String::from("Close")),
+                option_label: None,
+                is_modal: true,
+                destructive_accept: false,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::CopyGeneratedKey,
+                _ => Self::Input::DiscardGeneratedKey,
+            });
+
+        let script_button = OpenButton::builder()
+            .launch(OpenButtonSettings {
+                dialog_settings: OpenDialogSettings {
+                    folder_mode: false,
+                    accept_label: String::from("Apply"),
+                    cancel_label: String::from("Cancel"),
+                    create_folders: false,
+                    is_modal: true,
+                    filters: vec![],
+                },
+                text: "Apply Routing Script",
+                recently_opened_files: None,
+                max_recent_files: 0,
+            })
+            .forward(sender.input_sender(), Self::Input::ApplyRoutingScript);
+
+        let fwmark_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::from("Replace FwMark?"),
+                secondary_text: None,
+                confirm_label: Some(String::from("Replace")),
+                cancel_label: Some(String::from("Cancel")),
+                option_label: None,
+                is_modal: true,
+                destructive_accept: true,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::ApplyPendingScript,
+                _ => Self::Input::DiscardPendingScript,
+            });
+
+        let merge_dialog = Alert::builder()
+            .transient_for(&root)
+            .launch(AlertSettings {
+                text: String::from("Merge peers?"),
+                secondary_text: None,
+                confirm_label: Some(String::from("Merge")),
+                cancel_label: Some(String::from("Cancel")),
+                option_label: None,
+                is_modal: true,
+                destructive_accept: false,
+            })
+            .forward(sender.input_sender(), |response| match response {
+                AlertResponse::Confirm => Self::Input::MergePendingPeers,
+                _ => Self::Input::DiscardPendingPeers,
+            });
+
+        let interval = cli::get_args().stats_interval();
+        let transfer = Rc::new(RefCell::new(TransferHistory::new(
+            interval.map_or(0, |i| 60 / i as usize + 1),
+        )));
+        let throughput_graph = gtk::DrawingArea::new();
+        let graph_transfer = Rc::clone(&transfer);
+        throughput_graph.set_draw_func(move |_, cr, width, height| {
+            draw_throughput(cr, width, height, &graph_transfer.borrow().rates());
+        });
+        if let Some(interval) = interval {
+            let sample_sender = sender.clone();
+            gtk::glib::timeout_add_seconds_local(interval, move || {
+                sample_sender.input(OverviewInput::SampleTransfer);
+                gtk::glib::ControlFlow::Continue
+            });
+        }
+
         let mut model = Self {
             interface: config.interface,
+            all_peers: vec![],
+            query: String::new(),
+            matching: vec![],
+            sort_by_handshake: false,
+            handshakes: HashMap::new(),
+            page: 0,
             peers,
+            active: false,
+            qr_dialog,
+            share_dialog,
+            pending_share: None,
+            shared_config: None,
+            rekey_dialog,
+            pending_qr: None,
+            keypair_dialog,
+            generated_private_key: None,
+            script_button,
+            fwmark_dialog,
+            pending_script: None,
+            merge_dialog,
+            pending_merge: None,
+            transfer,
+            transfer_epoch: Instant::now(),
+            sampling: false,
+            key_generation: KeyGeneration::default(),
+            public_key_revealed: cli::get_args().reveal_public_keys,
+            throughput_graph: throughput_graph.clone(),
         };
 
         model.replace_peers(config.peers);
@@ -275,40 +1223,550 @@ impl SimpleComponent for OverviewModel {
     fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>) {
         match msg {
             Self::Input::CollectTunnel => {
+                if !self.key_generation.save() {
+                    sender
+                        .output_sender()
+                        .emit(Self::Output::Warning(String::from(
+                        "Key generation in progress, the configuration is saved once it finishes.",
+                    )));
+                    return;
+                }
+                self.sync_page();
+                let collapse = cli::get_args().collapse_allowed_ips;
+                for peer in &mut self.all_peers {
+                    if let Some(allowed_ips) = &mut peer.allowed_ips {
+                        *allowed_ips = utils::normalize_allowed_ips(allowed_ips, collapse);
+                    }
+                }
+                self.show_page(self.page);
                 let cfg = WireguardConfig {
                     interface: self.interface.clone(),
-                    peers: self.peers.iter().map(|p| p.peer.clone()).collect(),
+                    peers: self.all_peers.clone(),
                 };
                 sender
                     .output_sender()
                     .emit(Self::Output::SaveConfig(Box::new(cfg)));
             }
+            Self::Input::CopySanitizedConfig => {
+                let cfg = self.current_config();
+                if let Some(display) = gtk::gdk::Display::default() {
+                    display
+                        .clipboard()
+                        .set_text(&write_config(&redact_secrets(&cfg)));
+                }
+            }
             Self::Input::ShowConfig(config) => {
                 let WireguardConfig { interface, peers } = *config;
+                self.key_generation.cancel();
+                if self.interface.name != interface.name {
+                    self.clear_transfer();
+                }
                 self.interface = interface;
                 self.replace_peers(peers);
             }
+            Self::Input::SampleTransfer => {
+                let Some(name) = self.interface.name.clone().filter(|_| self.active) else {
+                    return;
+                };
+                if self.sampling {
+                    return;
+                }
+                self.sampling = true;
+                let epoch = self.transfer_epoch;
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    let sample = peer_stats(&name)
+                        .map(|peers| {
+                            let sample = TransferSample {
+                                time: epoch.elapsed(),
+                                rx: peers.iter().map(|p| p.rx).sum(),
+                                tx: peers.iter().map(|p| p.tx).sum(),
+                            };
+                            let handshakes = peers
+                                .into_iter()
+                                .map(|p| (p.public_key, p.latest_handshake))
+                                .collect();
+                            (sample, handshakes)
+                        })
+                        .map_err(|e| e.to_string());
+                    sender.input(OverviewInput::TransferSampled(sample));
+                });
+            }
+            Self::Input::TransferSampled(sample) => {
+                self.sampling = false;
+                match sample {
+                    Ok((sample, handshakes)) if self.active => {
+                        self.transfer.borrow_mut().push(sample);
+                        if self.handshakes != handshakes {
+                            self.handshakes = handshakes;
+                            self.resort_peers();
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(err) => eprintln!("Could not read transfer statistics: {err}"),
+                }
+                self.throughput_graph.queue_draw();
+            }
             Self::Input::RemovePeer(idx) => {
-                let mut peers = self.peers.guard();
-                peers.remove(idx.current_index());
-            }
-            Self::Input::AddPeer => {
-                let mut peers = self.peers.guard();
-                peers.push_back(Peer::default());
-            }
-            Self::Input::SetInterface(kind, value) => match kind {
-                InterfaceSetKind::Name => self.interface.name = value,
-                InterfaceSetKind::Address => self.interface.address = value,
-                InterfaceSetKind::ListenPort => self.interface.listen_port = value,
-                InterfaceSetKind::PrivateKey => self.interface.private_key = value,
-                InterfaceSetKind::Dns => self.interface.dns = value,
-                InterfaceSetKind::Table => self.interface.table = value,
-                InterfaceSetKind::Mtu => self.interface.mtu = value,
-                InterfaceSetKind::PreUp => self.interface.pre_up = value,
-                InterfaceSetKind::PostUp => self.interface.post_up = value,
-                InterfaceSetKind::PreDown => self.interface.pre_down = value,
-                InterfaceSetKind::PostDown => self.interface.post_down = value,
+                self.sync_page();
+                self.all_peers
+                    .remove(self.matching[self.page * Self::page_size() + idx.current_index()]);
+                self.update_matching();
+                self.show_page(self.page);
+            }
+            Self::Input::AssignPeerAddress(idx) => {
+                self.sync_page();
+                let peer_idx = self.matching[self.page * Self::page_size() + idx.current_index()];
+                let others: Vec<Peer> = self
+                    .all_peers
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| i != peer_idx)
+                    .map(|(_, p)| p.clone())
+                    .collect();
+                match next_free_address(&self.interface, &others) {
+                    Ok(address) => {
+                        let allowed_ips = with_assigned_address(
+                            &self.interface,
+                            self.all_peers[peer_idx].allowed_ips.as_deref(),
+                            address,
+                        );
+                        self.peers.send(
+                            idx.current_index(),
+                            PeerInput::Set(PeerSetKind::AllowedIps, Some(allowed_ips)),
+                        );
+                    }
+                    Err(e) => sender.output_sender().emit(Self::Output::Error(e)),
+                }
+            }
+            Self::Input::GeneratePeerKeypair(idx) => {
+                let Some(mut peer) = self.peers.get(idx.current_index()).map(|p| p.peer.clone())
+                else {
+                    return;
+                };
+                match utils::generate_peer_keypair(&mut peer) {
+                    Ok(private_key) => {
+                        self.peers.send(
+                            idx.current_index(),
+                            PeerInput::Set(PeerSetKind::PublicKey, peer.public_key),
+                        );
+                        self.keypair_dialog
+                            .state()
+                            .get_mut()
+                            .model
+                            .settings
+                            .secondary_text = Some(format!(
+                            "Private key of the client:\n{private_key}\n\nHand it to the client. It isn't stored in this configuration and won't be shown again."
+                        ));
+                        self.generated_private_key = Some(private_key);
+                        self.keypair_dialog.emit(AlertMsg::Show);
+                    }
+                    Err(e) => sender.output_sender().emit(Self::Output::Error(format!(
+                        "Could not generate keypair: {e}"
+                    ))),
+                }
+            }
+            Self::Input::CopyGeneratedKey => {
+                if let (Some(display), Some(key)) = (
+                    gtk::gdk::Display::default(),
+                    self.generated_private_key.as_deref(),
+                ) {
+                    display.clipboard().set_text(key);
+                }
+                self.forget_generated_key();
+            }
+            Self::Input::DiscardGeneratedKey => self.forget_generated_key(),
+            Self::Input::AddPeer => self.append_peer(Peer::default()),
+            Self::Input::PastePeer => {
+                let Some(display) = gtk::gdk::Display::default() else {
+                    return;
+                };
+                let sender = sender.clone();
+                display
+                    .clipboard()
+                    .read_text_async(None::<&gtk::gio::Cancellable>, move |text| match text {
+                        Ok(Some(text)) => sender.input(Self::Input::PastePeerText(text.into())),
+                        Ok(None) => sender
+                            .output_sender()
+                            .emit(Self::Output::Error(String::from(
+                                "Clipboard contains no text",
+                            ))),
+                        Err(e) => sender.output_sender().emit(Self::Output::Error(format!(
+                            "Could not read clipboard: {e}"
+                        ))),
+                    });
+            }
+            Self::Input::PastePeerText(text) => match parse_peer_fragment(&text) {
+                Ok(peer) => self.append_peer(peer),
+                Err(e) => sender
+                    .output_sender()
+                    .emit(Self::Output::Error(format!("Could not paste peer:\n{e}"))),
+            },
+            Self::Input::SetKeepaliveOnAll(keepalive) => {
+                self.sync_page();
+                let max = cli::get_args().max_keepalive;
+                match utils::set_keepalive_on_all(&mut self.all_peers, &keepalive, max) {
+                    Ok(warning) => {
+                        self.show_page(self.page);
+                        if let Some(warning) = warning {
+                            sender.output_sender().emit(Self::Output::Warning(warning));
+                        }
+                    }
+                    Err(e) => sender.output_sender().emit(Self::Output::Error(e)),
+                }
+            }
+            Self::Input::SortByHandshake(sort) => {
+                self.sync_page();
+                self.sort_by_handshake = sort;
+                self.update_matching();
+                self.show_page(0);
+            }
+            Self::Input::SearchPeers(query) => {
+                self.sync_page();
+                self.query = query;
+                self.update_matching();
+                self.show_page(0);
+            }
+            Self::Input::ShowPage(change) => {
+                self.sync_page();
+                let page = match change {
+                    PageChange::Previous => self.page.saturating_sub(1),
+                    PageChange::Next => self.page + 1,
+                };
+                self.show_page(page);
+            }
+            Self::Input::PeerError(e) => sender.output_sender().emit(Self::Output::Error(e)),
+            Self::Input::PeerWarning(w) => sender.output_sender().emit(Self::Output::Warning(w)),
+            Self::Input::ShowClientQr(idx, host) => {
+                if self.peer_has_key(&idx) {
+                    self.pending_qr = Some((idx, host));
+                    self.rekey_dialog.emit(AlertMsg::Show);
+                } else {
+                    self.show_client_qr(&idx, host, &sender);
+                }
+            }
+            Self::Input::ShowClientQrCancelled => self.pending_qr = None,
+            Self::Input::ShowClientQrConfirmed => {
+                let Some((idx, host)) = self.pending_qr.take() else {
+                    return;
+                };
+                self.show_client_qr(&idx, host, &sender);
+            }
+            Self::Input::ShareClientConfig(idx, host) => {
+                let mut warning = String::from(SHARE_WARNING);
+                if self.peer_has_key(&idx) {
+                    warning.push_str("\n\n");
+                    warning.push_str(REKEY_WARNING);
+                }
+                self.share_dialog
+                    .state()
+                    .get_mut()
+                    .model
+                    .settings
+                    .secondary_text = Some(warning);
+                self.pending_share = Some((idx, host));
+                self.share_dialog.emit(AlertMsg::Show);
+            }
+            Self::Input::ShareClientConfigCancelled => self.pending_share = None,
+            Self::Input::ShareClientConfigConfirmed => {
+                let Some((idx, host)) = self.pending_share.take() else {
+                    return;
+                };
+                let shared = self.client_config(&idx, host).and_then(|(config, key)| {
+                    let name = config.interface.name.as_deref().unwrap_or("client");
+                    let shared = utils::write_shared_config(
+                        &utils::shared_configs_dir(),
+                        name,
+                        &write_config(&config),
+                    )
+                    .and_then(|shared| {
+                        let subject = format!("WireGuard configuration {name}");
+                        utils::open_mail_composer(&subject, shared.path())?;
+                        Ok(shared)
+                    })
+                    .map_err(|e| format!("Couldn't share client configuration: {e}"))?;
+                    self.shared_config = Some(shared);
+                    Ok(key)
+                });
+                match shared {
+                    Ok(client_public_key) => self.peers.send(
+                        idx.current_index(),
+                        PeerInput::Set(PeerSetKind::PublicKey, Some(client_public_key)),
+                    ),
+                    Err(e) => sender.output_sender().emit(Self::Output::Error(e)),
+                }
+            }
+            Self::Input::SetLastConnected(time) => self.interface.last_connected = time,
+            Self::Input::SetActive(active) => {
+                if self.active != active {
+                    self.active = active;
+                    self.notify_peers_active();
+                    self.clear_transfer();
+                    self.resort_peers();
+                }
+            }
+            // Editing also stops when it's cancelled with Escape or the field loses focus
+            // without changes, neither of which should validate the value again.
+            Self::Input::SetInterface(kind, value) if *kind.value(&self.interface) == value => {}
+            Self::Input::SetInterface(kind, value)
+                if cli::get_args().comment_out_cleared
+                    && kind.config_key().is_some()
+                    && value.as_deref().is_none_or(str::is_empty) =>
+            {
+                if let Some(key) = kind.config_key() {
+                    self.interface.comment_out(key);
+                }
+            }
+            Self::Input::SetInterface(kind, value) => {
+                match kind {
+                    InterfaceSetKind::Name => {
+                        let collision = value.as_deref().map_or(Ok(()), |name| {
+                            let existing = match utils::non_wireguard_interfaces() {
+                                Ok((names, None)) => names,
+                                // WireGuard interfaces can't be told apart, so any of them could
+                                // be reported as a collision.
+                                Ok((_, Some(warning))) => {
+                                    eprintln!("{warning}");
+                                    vec![]
+                                }
+                                Err(err) => {
+                                    eprintln!("Could not list network interfaces: {:#?}", err);
+                                    vec![]
+                                }
+                            };
+                            utils::check_name_collision(name, &existing)
+                        });
+                        match collision {
+                            Ok(()) => self.interface.name = value,
+                            Err(e) => sender.output_sender().emit(Self::Output::Error(e)),
+                        }
+                    }
+                    InterfaceSetKind::Tag => self.interface.tag = value,
+                    InterfaceSetKind::Address => self.interface.address = value,
+                    InterfaceSetKind::ListenPort => {
+                        match value.as_deref().map(utils::parse_port).transpose() {
+                            Ok(port) => self.interface.listen_port = port.map(|p| p.to_string()),
+                            Err(e) => sender.output_sender().emit(Self::Output::Error(e)),
+                        }
+                    }
+                    InterfaceSetKind::PrivateKey => self.interface.private_key = value,
+                    InterfaceSetKind::Dns => self.interface.dns = value,
+                    InterfaceSetKind::Table => self.interface.table = value,
+                    InterfaceSetKind::FwMark => {
+                        match utils::parse_fwmark(value.as_deref().unwrap_or_default()) {
+                            Ok(fwmark) => {
+                                if let Some(script) = &self.interface.routing_script {
+                                    if fwmark != self.interface.fwmark {
+                                        sender.output_sender().emit(Self::Output::Warning(format!(
+                                        "Routing script {script} may set FwMark too, applying it again replaces this value."
+                                    )));
+                                    }
+                                }
+                                self.interface.fwmark = fwmark;
+                            }
+                            Err(e) => sender.output_sender().emit(Self::Output::Error(e)),
+                        }
+                    }
+                    InterfaceSetKind::Mtu => self.interface.mtu = value,
+                    InterfaceSetKind::PreUp => self.interface.pre_up = value,
+                    InterfaceSetKind::PostUp => self.interface.post_up = value,
+                    InterfaceSetKind::PreDown => self.interface.pre_down = value,
+                    InterfaceSetKind::PostDown => self.interface.post_down = value,
+                }
+                self.interface.prune_cleared();
+            }
+            Self::Input::ApplyRoutingScript(path) => match read_routing_script(&path) {
+                Ok((hooks, name)) => match hooks.fwmark_conflict(&self.interface) {
+                    Some(current) => {
+                        self.fwmark_dialog
+                                .state()
+                                .get_mut()
+                                .model
+                                .settings
+                                .secondary_text = Some(format!(
+                                "FwMark {current} set on the interface will be replaced by {} from the routing script.",
+                                hooks.fwmark.as_deref().unwrap_or_default()
+                            ));
+                        self.pending_script = Some((hooks, name));
+                        self.fwmark_dialog.emit(AlertMsg::Show);
+                    }
+                    None => {
+                        hooks.apply(&mut self.interface, &name);
+                        self.interface.prune_cleared();
+                    }
+                },
+                Err(e) => sender.output_sender().emit(Self::Output::Error(format!(
+                    "Could not apply routing script {}: {e}",
+                    path.display()
+                ))),
             },
+            Self::Input::ApplyPendingScript => {
+                if let Some((hooks, name)) = self.pending_script.take() {
+                    hooks.apply(&mut self.interface, &name);
+                    self.interface.prune_cleared();
+                }
+            }
+            Self::Input::DiscardPendingScript => self.pending_script = None,
+            Self::Input::MergePeersFrom(path) => {
+                let source = utils::read_config_file(&path, cli::get_args().max_config_size)
+                    .map_err(|e| e.to_string())
+                    .and_then(|c| parse_config(&c));
+                let source = match source {
+                    Ok(source) => source,
+                    Err(e) => {
+                        sender.output_sender().emit(Self::Output::Error(format!(
+                            "Could not merge peers of {}: {e}",
+                            path.display()
+                        )));
+                        return;
+                    }
+                };
+
+                self.sync_page();
+                let total = source.peers.len();
+                let peers = peers_to_merge(&self.all_peers, source.peers);
+                if peers.is_empty() {
+                    sender.output_sender().emit(Self::Output::Warning(format!(
+                        "{} has no peers missing from this tunnel.",
+                        path.display()
+                    )));
+                    return;
+                }
+                self.merge_dialog
+                    .state()
+                    .get_mut()
+                    .model
+                    .settings
+                    .secondary_text = Some(format!(
+                    "{} of {total} peers from {} will be appended. Peers whose PublicKey is already present or missing are skipped.",
+                    peers.len(),
+                    path.display()
+                ));
+                self.pending_merge = Some(peers);
+                self.merge_dialog.emit(AlertMsg::Show);
+            }
+            Self::Input::MergePendingPeers => {
+                if let Some(peers) = self.pending_merge.take() {
+                    self.sync_page();
+                    self.all_peers.extend(peers);
+                    self.query.clear();
+                    self.update_matching();
+                    self.show_page(self.page_count() - 1);
+                }
+            }
+            Self::Input::DiscardPendingPeers => self.pending_merge = None,
+            Self::Input::CopyPrivateKey => {
+                if let (Some(display), Some(key)) =
+                    (gtk::gdk::Display::default(), &self.interface.private_key)
+                {
+                    display.clipboard().set_text(key.trim());
+                    sender.output_sender().emit(Self::Output::Warning(String::from(
+                        "Private key was copied to the clipboard. Anyone with it can impersonate this interface, clear the clipboard once it's pasted.",
+                    )));
+                }
+            }
+            Self::Input::RevealPublicKey(revealed) => self.public_key_revealed = revealed,
+            Self::Input::CopyPublicKey => {
+                if let (Some(display), Some(key)) =
+                    (gtk::gdk::Display::default(), &self.interface.public_key)
+                {
+                    display.clipboard().set_text(key.trim());
+                }
+            }
+            Self::Input::GenerateKeypair => {
+                let Some(generation) = self.key_generation.start() else {
+                    return;
+                };
+                let name = self.interface.name.clone();
+                let old_private_key = self.interface.private_key.clone();
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    let keys = backup_keys(name, old_private_key).and_then(|()| {
+                        utils::generate_private_key()
+                            .and_then(|private_key| {
+                                let public_key = utils::generate_public_key(private_key.clone())?;
+                                Ok((private_key, public_key))
+                            })
+                            .map_err(|e| format!("Could not generate keypair: {e}"))
+                    });
+                    sender.input(OverviewInput::SetGeneratedKeys(generation, keys));
+                });
+            }
+            Self::Input::SetGeneratedKeys(generation, keys) => {
+                let Some(save) = self.key_generation.finish(generation) else {
+                    return;
+                };
+                match keys {
+                    Ok((private_key, public_key)) => {
+                        self.interface.private_key = Some(private_key);
+                        self.interface.public_key = Some(public_key);
+                    }
+                    Err(e) => sender.output_sender().emit(Self::Output::Error(e)),
+                }
+                if save {
+                    sender.input(OverviewInput::CollectTunnel);
+                }
+            }
+            Self::Input::RotateKeys => {
+                if let Err(e) = self.rotate_keys() {
+                    sender.output_sender().emit(Self::Output::Error(e));
+                }
+            }
+            Self::Input::RestorePreviousKey => {
+                let Some(name) = self.interface.name.clone() else {
+                    return;
+                };
+                match utils::latest_key_backup(&utils::key_backups_dir(), &name) {
+                    Ok(Some(private_key)) => {
+                        if let Err(e) = self.set_private_key(private_key) {
+                            sender.output_sender().emit(Self::Output::Error(e));
+                        }
+                    }
+                    Ok(None) => sender.output_sender().emit(Self::Output::Error(format!(
+                        "There is no key backup of {name}"
+                    ))),
+                    Err(e) => sender.output_sender().emit(Self::Output::Error(format!(
+                        "Could not read key backup of {name}: {e}"
+                    ))),
+                }
+            }
+            Self::Input::AddRoute(destination, via) => {
+                let hooks = Route::parse(&destination, &via)
+                    .map(|route| routes_to_script(&[route]))
+                    .and_then(|script| parse_routing_keywords(&script));
+                match hooks {
+                    Ok(hooks) => hooks.append_to(&mut self.interface),
+                    Err(e) => sender.output_sender().emit(Self::Output::Error(e)),
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_during_key_generation() {
+        let mut generation = KeyGeneration::default();
+        assert!(generation.save());
+
+        let epoch = generation.start().unwrap();
+        assert_eq!(generation.start(), None);
+        assert!(!generation.save());
+        assert_eq!(generation.finish(epoch), Some(true));
+        assert!(generation.save());
+
+        // Nothing is held without a save.
+        let epoch = generation.start().unwrap();
+        assert_eq!(generation.finish(epoch), Some(false));
+
+        // Keys generated for a config that isn't shown anymore are dropped with held save.
+        let epoch = generation.start().unwrap();
+        assert!(!generation.save());
+        generation.cancel();
+        assert_eq!(generation.finish(epoch), None);
+        assert!(generation.save());
+    }
+}
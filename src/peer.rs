@@ -1,16 +1,84 @@
 use gtk::prelude::*;
 use relm4::prelude::*;
 
+use crate::cli;
 use crate::config::*;
+use crate::ping::*;
+use crate::utils::{
+    format_endpoint, keepalive_warning, parse_endpoint, parse_keepalive, parse_port,
+    validate_allowed_ips, validate_endpoint, validate_endpoint_host,
+};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Default, Clone)]
 pub struct PeerComp {
     pub peer: Peer,
+    endpoint_host: Option<String>,
+    endpoint_port: Option<u16>,
+    /// Name of the tunnel interface when it is active.
+    active_interface: Option<String>,
+    pinging: bool,
+    ping_status: String,
+    public_key_revealed: bool,
+    preshared_key_revealed: bool,
+}
+
+/// Key as shown in the editor, masked unless revealed.
+pub fn masked(value: &Option<String>, revealed: bool) -> &str {
+    match value {
+        Some(_) if !revealed => "••••••••••••",
+        _ => get_value(value),
+    }
 }
 
 impl PeerComp {
     pub fn new(peer: Peer) -> Self {
-        Self { peer }
+        let mut comp = Self {
+            peer,
+            public_key_revealed: cli::get_args().reveal_public_keys,
+            ..Default::default()
+        };
+        comp.split_endpoint();
+        comp
+    }
+
+    fn split_endpoint(&mut self) {
+        (self.endpoint_host, self.endpoint_port) =
+            match self.peer.endpoint.as_deref().map(parse_endpoint) {
+                Some(Ok((host, port))) => (Some(host), Some(port)),
+                _ => (self.peer.endpoint.clone(), None),
+            };
+    }
+
+    /// Current value of the field as shown in the editor.
+    fn value_of(&self, kind: &PeerSetKind) -> Option<String> {
+        match kind {
+            PeerSetKind::Name => self.peer.name.clone(),
+            PeerSetKind::AllowedIps => self.peer.allowed_ips.clone(),
+            PeerSetKind::Endpoint => self.peer.endpoint.clone(),
+            PeerSetKind::EndpointHost => self.endpoint_host.clone(),
+            PeerSetKind::EndpointPort => self.endpoint_port.map(|p| p.to_string()),
+            PeerSetKind::PublicKey => self.peer.public_key.clone(),
+            PeerSetKind::PresharedKey => self.peer.preshared_key.clone(),
+            PeerSetKind::PersistentKeepalive => self.peer.persistent_keepalive.clone(),
+        }
+    }
+
+    /// Public key as shown in the editor, masked unless revealed.
+    fn displayed_public_key(&self) -> &str {
+        masked(&self.peer.public_key, self.public_key_revealed)
+    }
+
+    /// Preshared key as shown in the editor, masked unless revealed.
+    fn displayed_preshared_key(&self) -> &str {
+        masked(&self.peer.preshared_key, self.preshared_key_revealed)
+    }
+
+    /// Recombines endpoint from host and port, clearing it while either of them is missing.
+    fn update_endpoint(&mut self) {
+        self.peer.endpoint = match (&self.endpoint_host, self.endpoint_port) {
+            (Some(host), Some(port)) => Some(format_endpoint(host, port)),
+            _ => None,
+        };
     }
 }
 
@@ -19,18 +87,40 @@ pub enum PeerSetKind {
     Name,
     AllowedIps,
     Endpoint,
+    EndpointHost,
+    EndpointPort,
     PublicKey,
+    PresharedKey,
     PersistentKeepalive,
 }
 
 #[derive(Debug)]
 pub enum PeerInput {
     Set(PeerSetKind, Option<String>),
+    /// Tunnel interface name if the tunnel is active.
+    SetActiveInterface(Option<String>),
+    /// Pings given address, or the one derived from AllowedIPs if empty.
+    Ping(String),
+    RevealPublicKey(bool),
+    CopyPublicKey,
+    RevealPresharedKey(bool),
+    /// Disabled peers stay in the configuration, but are commented out.
+    SetEnabled(bool),
 }
 
 #[derive(Debug)]
 pub enum PeerOutput {
     Remove(DynamicIndex),
+    /// Generates keypair of the client behind peer, showing its private key.
+    GenerateKeypair(DynamicIndex),
+    /// Sets AllowedIPs to the next address of the interface subnet not used by other peers.
+    AssignAddress(DynamicIndex),
+    /// Shows QR code of the client config, using given host as server endpoint.
+    ShowClientQr(DynamicIndex, Option<String>),
+    /// Opens mail composer with the client config attached, using given host as server endpoint.
+    ShareClientConfig(DynamicIndex, Option<String>),
+    Error(String),
+    Warning(String),
 }
 
 #[relm4::factory(pub)]
@@ -38,7 +128,7 @@ impl FactoryComponent for PeerComp {
     type Init = Peer;
     type Input = PeerInput;
     type Output = PeerOutput;
-    type CommandOutput = ();
+    type CommandOutput = Result<PingResult, String>;
     type ParentWidget = gtk::Box;
 
     view! {
@@ -56,7 +146,15 @@ impl FactoryComponent for PeerComp {
 
                 gtk::Label {
                     set_label: "Peer:"
-                }
+                },
+
+                gtk::CheckButton {
+                    set_label: Some("Enabled"),
+                    set_active: !self.peer.disabled,
+                    connect_toggled[sender] => move |b| {
+                        sender.input(Self::Input::SetEnabled(b.is_active()));
+                    },
+                },
             },
 
             gtk::Grid {
@@ -83,12 +181,24 @@ impl FactoryComponent for PeerComp {
                     set_label: "AllowedIPs:",
                     set_halign: gtk::Align::Start,
                 },
-                attach[1, 1, 1, 1] = &gtk::EditableLabel {
-                    set_text: get_value(&self.peer.allowed_ips),
-                    connect_editing_notify[sender] => move |l| {
-                        if !l.is_editing() {
-                            let new: String = l.text().trim().into();
-                            sender.input(Self::Input::Set(PeerSetKind::AllowedIps, (new != "unknown").then_some(new)));
+                attach[1, 1, 1, 1] = &gtk::Box {
+                    set_spacing: 5,
+
+                    gtk::EditableLabel {
+                        set_hexpand: true,
+                        #[watch]
+                        set_text: get_value(&self.peer.allowed_ips),
+                        connect_editing_notify[sender] => move |l| {
+                            if !l.is_editing() {
+                                let new: String = l.text().trim().into();
+                                sender.input(Self::Input::Set(PeerSetKind::AllowedIps, (new != "unknown").then_some(new)));
+                            }
+                        },
+                    },
+
+                    gtk::Button::with_label("Assign Address") {
+                        connect_clicked[sender, index] => move |_| {
+                            sender.output(Self::Output::AssignAddress(index.clone())).unwrap();
                         }
                     },
                 },
@@ -97,13 +207,33 @@ impl FactoryComponent for PeerComp {
                     set_label: "Endpoint:",
                     set_halign: gtk::Align::Start,
                 },
-                attach[1, 2, 1, 1] = &gtk::EditableLabel {
-                    set_text: get_value(&self.peer.endpoint),
-                    connect_editing_notify[sender] => move |l| {
-                        if !l.is_editing() {
-                            let new: String = l.text().trim().into();
-                            sender.input(Self::Input::Set(PeerSetKind::Endpoint, (new != "unknown").then_some(new)));
-                        }
+                attach[1, 2, 1, 1] = &gtk::Box {
+                    set_spacing: 5,
+
+                    gtk::EditableLabel {
+                        #[watch]
+                        set_text: get_value(&self.endpoint_host),
+                        connect_editing_notify[sender] => move |l| {
+                            if !l.is_editing() {
+                                let new: String = l.text().trim().into();
+                                sender.input(Self::Input::Set(PeerSetKind::EndpointHost, (new != "unknown").then_some(new)));
+                            }
+                        },
+                    },
+
+                    gtk::Label {
+                        set_label: ":",
+                    },
+
+                    gtk::EditableLabel {
+                        #[watch]
+                        set_text: &self.endpoint_port.map_or(String::from("unknown"), |p| p.to_string()),
+                        connect_editing_notify[sender] => move |l| {
+                            if !l.is_editing() {
+                                let new: String = l.text().trim().into();
+                                sender.input(Self::Input::Set(PeerSetKind::EndpointPort, (new != "unknown" && !new.is_empty()).then_some(new)));
+                            }
+                        },
                     },
                 },
 
@@ -111,21 +241,82 @@ impl FactoryComponent for PeerComp {
                     set_label: "PublicKey:",
                     set_halign: gtk::Align::Start,
                 },
-                attach[1, 3, 1, 1] = &gtk::EditableLabel {
-                    set_text: get_value(&self.peer.public_key),
-                    connect_editing_notify[sender] => move |l| {
-                        if !l.is_editing() {
-                            let new: String = l.text().trim().into();
-                            sender.input(Self::Input::Set(PeerSetKind::PublicKey, (new != "unknown").then_some(new)));
+                attach[1, 3, 1, 1] = &gtk::Box {
+                    set_spacing: 5,
+
+                    gtk::EditableLabel {
+                        set_hexpand: true,
+                        #[watch]
+                        set_text: self.displayed_public_key(),
+                        #[watch]
+                        set_editable: self.public_key_revealed,
+                        connect_editing_notify[sender] => move |l| {
+                            if !l.is_editing() {
+                                let new: String = l.text().trim().into();
+                                sender.input(Self::Input::Set(PeerSetKind::PublicKey, (new != "unknown").then_some(new)));
+                            }
+                        },
+                    },
+
+                    gtk::ToggleButton::with_label("Reveal") {
+                        #[watch]
+                        #[block_signal(reveal_handler)]
+                        set_active: self.public_key_revealed,
+                        connect_toggled[sender] => move |b| {
+                            sender.input(Self::Input::RevealPublicKey(b.is_active()));
+                        } @reveal_handler,
+                    },
+
+                    gtk::Button::with_label("Copy") {
+                        #[watch]
+                        set_sensitive: self.peer.public_key.is_some(),
+                        connect_clicked => Self::Input::CopyPublicKey,
+                    },
+
+                    gtk::Button::with_label("Generate Keypair") {
+                        connect_clicked[sender, index] => move |_| {
+                            sender.output(Self::Output::GenerateKeypair(index.clone())).unwrap();
                         }
                     },
                 },
 
                 attach[0, 4, 1, 1] = &gtk::Label {
+                    set_label: "PresharedKey:",
+                    set_halign: gtk::Align::Start,
+                },
+                attach[1, 4, 1, 1] = &gtk::Box {
+                    set_spacing: 5,
+
+                    gtk::EditableLabel {
+                        set_hexpand: true,
+                        #[watch]
+                        set_text: self.displayed_preshared_key(),
+                        #[watch]
+                        set_editable: self.preshared_key_revealed,
+                        connect_editing_notify[sender] => move |l| {
+                            if !l.is_editing() {
+                                let new: String = l.text().trim().into();
+                                sender.input(Self::Input::Set(PeerSetKind::PresharedKey, (new != "unknown" && !new.is_empty()).then_some(new)));
+                            }
+                        },
+                    },
+
+                    gtk::ToggleButton::with_label("Reveal") {
+                        #[watch]
+                        #[block_signal(reveal_psk_handler)]
+                        set_active: self.preshared_key_revealed,
+                        connect_toggled[sender] => move |b| {
+                            sender.input(Self::Input::RevealPresharedKey(b.is_active()));
+                        } @reveal_psk_handler,
+                    },
+                },
+
+                attach[0, 5, 1, 1] = &gtk::Label {
                     set_label: "PersistentKeepalive:",
                     set_halign: gtk::Align::Start,
                 },
-                attach[1, 4, 1, 1] = &gtk::EditableLabel {
+                attach[1, 5, 1, 1] = &gtk::EditableLabel {
+                    #[watch]
                     set_text: get_value(&self.peer.persistent_keepalive),
                     connect_editing_notify[sender] => move |l| {
                         if !l.is_editing() {
@@ -134,6 +325,66 @@ impl FactoryComponent for PeerComp {
                         }
                     },
                 },
+
+                attach[0, 6, 1, 1] = &gtk::Label {
+                    set_label: "Ping:",
+                    set_halign: gtk::Align::Start,
+                },
+                attach[1, 6, 1, 1] = &gtk::Box {
+                    set_spacing: 5,
+
+                    #[name = "ping_address"]
+                    gtk::Entry {
+                        #[watch]
+                        set_placeholder_text: self
+                            .peer
+                            .allowed_ips
+                            .as_deref()
+                            .and_then(ping_target)
+                            .map(|a| a.to_string())
+                            .as_deref(),
+                    },
+
+                    gtk::Button::with_label("Ping") {
+                        #[watch]
+                        set_sensitive: self.active_interface.is_some() && !self.pinging,
+                        connect_clicked[sender, ping_address] => move |_| {
+                            sender.input(Self::Input::Ping(ping_address.text().trim().into()));
+                        }
+                    },
+
+                    gtk::Label {
+                        #[watch]
+                        set_label: &self.ping_status,
+                    },
+                },
+
+                attach[0, 7, 1, 1] = &gtk::Label {
+                    set_label: "Client Config:",
+                    set_halign: gtk::Align::Start,
+                },
+                attach[1, 7, 1, 1] = &gtk::Box {
+                    set_spacing: 5,
+
+                    #[name = "server_host"]
+                    gtk::Entry {
+                        set_placeholder_text: Some("Server endpoint host"),
+                    },
+
+                    gtk::Button::with_label("Show Client QR") {
+                        connect_clicked[sender, index, server_host] => move |_| {
+                            let host: String = server_host.text().trim().into();
+                            sender.output(Self::Output::ShowClientQr(index.clone(), (!host.is_empty()).then_some(host))).unwrap();
+                        }
+                    },
+
+                    gtk::Button::with_label("Share Client Config") {
+                        connect_clicked[sender, index, server_host] => move |_| {
+                            let host: String = server_host.text().trim().into();
+                            sender.output(Self::Output::ShareClientConfig(index.clone(), (!host.is_empty()).then_some(host))).unwrap();
+                        }
+                    },
+                },
             }
         }
     }
@@ -146,15 +397,116 @@ impl FactoryComponent for PeerComp {
         Self::new(peer_config)
     }
 
-    fn update(&mut self, msg: Self::Input, _sender: relm4::FactorySender<Self>) {
+    fn update(&mut self, msg: Self::Input, sender: relm4::FactorySender<Self>) {
         match msg {
+            // Editing also stops when it's cancelled with Escape or the field loses focus
+            // without changes, neither of which should validate the value again.
+            Self::Input::Set(k, value) if self.value_of(&k) == value => {}
             Self::Input::Set(k, value) => match k {
                 PeerSetKind::Name => self.peer.name = value,
-                PeerSetKind::AllowedIps => self.peer.allowed_ips = value,
-                PeerSetKind::Endpoint => self.peer.endpoint = value,
+                PeerSetKind::AllowedIps => {
+                    match validate_allowed_ips(value.as_deref().unwrap_or_default()) {
+                        Ok(()) => self.peer.allowed_ips = value,
+                        Err(e) => sender.output(Self::Output::Error(e)).unwrap(),
+                    }
+                }
+                PeerSetKind::Endpoint => match value
+                    .as_deref()
+                    .filter(|v| !v.trim().is_empty())
+                    .map(validate_endpoint)
+                {
+                    Some(Err(e)) => sender.output(Self::Output::Error(e)).unwrap(),
+                    _ => {
+                        self.peer.endpoint = value;
+                        self.split_endpoint();
+                    }
+                },
+                PeerSetKind::EndpointHost => {
+                    let host = value.filter(|h| !h.trim().is_empty());
+                    match host.as_deref().map(validate_endpoint_host) {
+                        Some(Err(e)) => sender.output(Self::Output::Error(e)).unwrap(),
+                        _ => {
+                            self.endpoint_host = host.map(|h| {
+                                h.trim()
+                                    .trim_start_matches('[')
+                                    .trim_end_matches(']')
+                                    .to_string()
+                            });
+                            self.update_endpoint();
+                        }
+                    }
+                }
+                PeerSetKind::EndpointPort => match value.as_deref().map(parse_port).transpose() {
+                    Ok(port) => {
+                        self.endpoint_port = port;
+                        self.update_endpoint();
+                    }
+                    Err(e) => sender.output(Self::Output::Error(e)).unwrap(),
+                },
                 PeerSetKind::PublicKey => self.peer.public_key = value,
-                PeerSetKind::PersistentKeepalive => self.peer.persistent_keepalive = value,
+                PeerSetKind::PresharedKey => match value {
+                    Some(key) if !is_base64_key(&key) => sender
+                        .output(Self::Output::Error(String::from(
+                            "Invalid PresharedKey, expected 32 bytes encoded in base64.",
+                        )))
+                        .unwrap(),
+                    key => self.peer.preshared_key = key,
+                },
+                PeerSetKind::PersistentKeepalive => {
+                    match value.as_deref().map(parse_keepalive).transpose() {
+                        Ok(keepalive) => {
+                            let keepalive = keepalive.flatten();
+                            self.peer.persistent_keepalive = keepalive.map(|k| k.to_string());
+                            if let Some(warning) =
+                                keepalive_warning(keepalive, cli::get_args().max_keepalive)
+                            {
+                                sender.output(Self::Output::Warning(warning)).unwrap();
+                            }
+                        }
+                        Err(e) => sender.output(Self::Output::Error(e)).unwrap(),
+                    }
+                }
             },
+            Self::Input::SetActiveInterface(interface) => self.active_interface = interface,
+            Self::Input::RevealPublicKey(revealed) => self.public_key_revealed = revealed,
+            Self::Input::RevealPresharedKey(revealed) => self.preshared_key_revealed = revealed,
+            Self::Input::SetEnabled(enabled) => self.peer.disabled = !enabled,
+            Self::Input::CopyPublicKey => {
+                if let (Some(display), Some(key)) =
+                    (gtk::gdk::Display::default(), &self.peer.public_key)
+                {
+                    display.clipboard().set_text(key);
+                }
+            }
+            Self::Input::Ping(address) => {
+                let Some(interface) = self.active_interface.clone() else {
+                    return;
+                };
+                let target = if address.is_empty() {
+                    self.peer.allowed_ips.as_deref().and_then(ping_target)
+                } else {
+                    address.parse().ok()
+                };
+                let Some(target) = target else {
+                    self.ping_status = String::from("No address to ping");
+                    return;
+                };
+
+                self.pinging = true;
+                self.ping_status = format!("Pinging {target}...");
+                sender.spawn_oneshot_command(move || {
+                    ping(&interface, target).map_err(|e| e.to_string())
+                });
+            }
         }
     }
+
+    fn update_cmd(&mut self, result: Self::CommandOutput, _sender: FactorySender<Self>) {
+        self.pinging = false;
+        self.ping_status = match result {
+            Ok(res) if res.success() => res.to_string(),
+            Ok(res) => format!("Failed: {res}"),
+            Err(e) => format!("Failed: {e}"),
+        };
+    }
 }
@@ -0,0 +1,166 @@
+use std::{
+    fmt,
+    io::{self, Read},
+    net::IpAddr,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use cidr::IpCidr;
+
+use crate::utils::wait_cmd_with_timeout;
+
+/// Number of echo requests sent by [`ping`].
+const PING_COUNT: u32 = 3;
+/// Time after which unfinished ping is killed.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Summary of ping run.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PingResult {
+    pub transmitted: u32,
+    pub received: u32,
+    /// Average round trip time in milliseconds.
+    pub avg_rtt_ms: Option<f64>,
+}
+
+impl PingResult {
+    pub fn success(&self) -> bool {
+        self.received > 0
+    }
+}
+
+impl fmt::Display for PingResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} replies", self.received, self.transmitted)?;
+        if let Some(avg) = self.avg_rtt_ms {
+            write!(f, ", avg {avg:.1} ms")?;
+        }
+        Ok(())
+    }
+}
+
+/// Picks address to ping from peer's AllowedIPs: the first host address, or the first
+/// usable address of the first network. Default routes are skipped.
+pub fn ping_target(allowed_ips: &str) -> Option<IpAddr> {
+    let cidrs: Vec<IpCidr> = allowed_ips
+        .split(',')
+        .filter_map(|s| s.trim().parse().ok())
+        .filter(|c: &IpCidr| c.network_length() > 0)
+        .collect();
+
+    if let Some(host) = cidrs.iter().find(|c| c.is_host_address()) {
+        return Some(host.first_address());
+    }
+
+    cidrs.first().map(|c| match c.first_address() {
+        IpAddr::V4(a) => IpAddr::V4((u32::from(a) + 1).into()),
+        IpAddr::V6(a) => IpAddr::V6((u128::from(a) + 1).into()),
+    })
+}
+
+/// Parses summary printed by iputils or busybox ping.
+pub fn parse_ping_output(output: &str) -> Option<PingResult> {
+    let stats = output.lines().find(|l| l.contains("transmitted"))?;
+    let mut parts = stats.split(',');
+    let count =
+        |part: Option<&str>| -> Option<u32> { part?.split_whitespace().next()?.parse().ok() };
+    let transmitted = count(parts.next())?;
+    let received = count(parts.next())?;
+
+    let avg_rtt_ms = output
+        .lines()
+        .find(|l| l.contains("min/avg/max"))
+        .and_then(|l| l.split_once('='))
+        .and_then(|(_, values)| values.trim().split('/').nth(1))
+        .and_then(|avg| avg.trim().parse().ok());
+
+    Some(PingResult {
+        transmitted,
+        received,
+        avg_rtt_ms,
+    })
+}
+
+/// Pings address through given interface.
+pub fn ping(interface: &str, address: IpAddr) -> io::Result<PingResult> {
+    let mut child = Command::new(if address.is_ipv4() { "ping" } else { "ping6" })
+        .args([
+            "-c",
+            &PING_COUNT.to_string(),
+            "-I",
+            interface,
+            &address.to_string(),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    wait_cmd_with_timeout(&mut child, PING_TIMEOUT)?;
+
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        stdout.read_to_string(&mut output)?;
+    }
+
+    parse_ping_output(&output)
+        .ok_or_else(|| io::Error::other(format!("Could not ping {address} via {interface}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_output() {
+        let iputils = "PING 10.0.0.1 (10.0.0.1) from 10.0.0.2 wg0: 56(84) bytes of data.
+64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=12.1 ms
+64 bytes from 10.0.0.1: icmp_seq=3 ttl=64 time=14.5 ms
+
+--- 10.0.0.1 ping statistics ---
+3 packets transmitted, 2 received, 33.3333% packet loss, time 2003ms
+rtt min/avg/max/mdev = 12.100/13.300/14.500/1.200 ms
+";
+        let res = parse_ping_output(iputils).unwrap();
+        assert_eq!(
+            res,
+            PingResult {
+                transmitted: 3,
+                received: 2,
+                avg_rtt_ms: Some(13.3),
+            }
+        );
+        assert!(res.success());
+        assert_eq!(res.to_string(), "2/3 replies, avg 13.3 ms");
+
+        let busybox = "--- 10.0.0.1 ping statistics ---
+3 packets transmitted, 3 packets received, 0% packet loss
+round-trip min/avg/max = 0.045/0.057/0.070 ms
+";
+        assert_eq!(parse_ping_output(busybox).unwrap().avg_rtt_ms, Some(0.057));
+
+        let lost = "--- 10.0.0.1 ping statistics ---
+3 packets transmitted, 0 received, 100% packet loss, time 2040ms
+";
+        let res = parse_ping_output(lost).unwrap();
+        assert!(!res.success());
+        assert_eq!(res.avg_rtt_ms, None);
+
+        assert_eq!(parse_ping_output("ping: unknown iface wg0"), None);
+    }
+
+    #[test]
+    fn target_from_allowed_ips() {
+        assert_eq!(
+            ping_target("0.0.0.0/0, 10.0.0.2/32"),
+            Some("10.0.0.2".parse().unwrap())
+        );
+        assert_eq!(
+            ping_target("10.0.0.0/24"),
+            Some("10.0.0.1".parse().unwrap())
+        );
+        assert_eq!(ping_target("fd00::/64"), Some("fd00::1".parse().unwrap()));
+        assert_eq!(ping_target("0.0.0.0/0, ::/0"), None);
+        assert_eq!(ping_target(""), None);
+    }
+}
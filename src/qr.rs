@@ -0,0 +1,137 @@
+use qrcode::{Color, QrCode};
+use relm4::gtk::{gdk, glib};
+use relm4::{gtk::prelude::*, prelude::*};
+
+/// Size of a single QR module in pixels.
+const MODULE_SIZE: usize = 8;
+/// Width of the light border around the code in modules.
+const QUIET_ZONE: usize = 4;
+
+/// Renders data as black on white QR code.
+pub fn qr_texture(data: &str) -> Result<gdk::Texture, String> {
+    let code = QrCode::new(data.as_bytes()).map_err(|e| e.to_string())?;
+    let modules = code.width();
+    let size = (modules + 2 * QUIET_ZONE) * MODULE_SIZE;
+    let mut pixels = vec![0xff; size * size * 3];
+
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Light {
+            continue;
+        }
+        let x = (i % modules + QUIET_ZONE) * MODULE_SIZE;
+        let y = (i / modules + QUIET_ZONE) * MODULE_SIZE;
+        for row in y..y + MODULE_SIZE {
+            let start = (row * size + x) * 3;
+            pixels[start..start + MODULE_SIZE * 3].fill(0);
+        }
+    }
+
+    let texture = gdk::MemoryTexture::new(
+        size as i32,
+        size as i32,
+        gdk::MemoryFormat::R8g8b8,
+        &glib::Bytes::from_owned(pixels),
+        size * 3,
+    );
+
+    Ok(texture.upcast())
+}
+
+#[derive(Debug, Default)]
+pub struct QrDialog {
+    visible: bool,
+    title: String,
+    note: String,
+    texture: Option<gdk::Texture>,
+}
+
+#[derive(Debug)]
+pub enum QrInput {
+    /// Shows data as QR code, with note below it.
+    Show {
+        title: String,
+        data: String,
+        note: String,
+    },
+    Hide,
+}
+
+#[derive(Debug)]
+pub enum QrOutput {
+    Error(String),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for QrDialog {
+    type Init = ();
+    type Input = QrInput;
+    type Output = QrOutput;
+
+    view! {
+        gtk::Window {
+            #[watch]
+            set_title: Some(&model.title),
+            #[watch]
+            set_visible: model.visible,
+            set_deletable: false,
+            set_modal: true,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 5,
+                set_margin_all: 10,
+
+                gtk::Picture {
+                    #[watch]
+                    set_paintable: model.texture.as_ref(),
+                    set_size_request: (320, 320),
+                },
+
+                gtk::Label {
+                    #[watch]
+                    set_label: &model.note,
+                    #[watch]
+                    set_visible: !model.note.is_empty(),
+                    set_wrap: true,
+                    set_max_width_chars: 40,
+                },
+
+                gtk::Button {
+                    set_label: "Close",
+                    connect_clicked => Self::Input::Hide,
+                },
+            }
+        }
+    }
+
+    fn init(
+        _: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self::default();
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>) {
+        match msg {
+            Self::Input::Show { title, data, note } => match qr_texture(&data) {
+                Ok(texture) => {
+                    self.title = title;
+                    self.note = note;
+                    self.texture = Some(texture);
+                    self.visible = true;
+                }
+                Err(e) => sender.output_sender().emit(Self::Output::Error(format!(
+                    "Could not create QR code: {e}"
+                ))),
+            },
+            Self::Input::Hide => {
+                self.visible = false;
+                self.texture = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use std::net::IpAddr;
+use std::path::Path;
+
+use cidr::IpCidr;
+
+use crate::cli;
+use crate::config::Interface;
+use crate::utils::read_config_file;
+
+/// Hooks extracted from a routing script.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct RoutingHooks {
+    pub pre_up: Option<String>,
+    pub post_up: Option<String>,
+    pub pre_down: Option<String>,
+    pub post_down: Option<String>,
+    /// Firewall mark replacing the one set on the interface.
+    pub fwmark: Option<String>,
+}
+
+impl RoutingHooks {
+    /// Appends hooks to the ones already set on the interface.
+    pub fn append_to(&self, interface: &mut Interface) {
+        fn append(field: &mut Option<String>, commands: &Option<String>) {
+            let Some(commands) = commands else {
+                return;
+            };
+            *field = match field.take() {
+                Some(existing) if !existing.trim().is_empty() => {
+                    Some(format!("{existing}; {commands}"))
+                }
+                _ => Some(commands.clone()),
+            };
+        }
+
+        append(&mut interface.pre_up, &self.pre_up);
+        append(&mut interface.post_up, &self.post_up);
+        append(&mut interface.pre_down, &self.pre_down);
+        append(&mut interface.post_down, &self.post_down);
+        if self.fwmark.is_some() {
+            interface.fwmark = self.fwmark.clone();
+        }
+    }
+
+    /// Appends hooks to the interface, remembering name of the script they come from.
+    pub fn apply(&self, interface: &mut Interface, script_name: &str) {
+        self.append_to(interface);
+        interface.routing_script = Some(script_name.to_string());
+    }
+
+    /// Returns FwMark set on the interface if applying hooks would replace it with a
+    /// different one.
+    pub fn fwmark_conflict<'a>(&self, interface: &'a Interface) -> Option<&'a str> {
+        let current = interface.fwmark.as_deref().map(str::trim)?;
+        let new = self.fwmark.as_deref()?;
+        (!current.is_empty() && current != new).then_some(current)
+    }
+}
+
+/// Parses routing script in `Keyword = command` format. Scripts aren't executed as a whole:
+/// every command is a hook run by wg-quick, so apart from keyword lines only empty lines,
+/// `#` comments and a `#!` shebang (kept so the file can double as a shell script) are
+/// allowed. Several lines with the same keyword are joined with `; ` in order of appearance.
+/// `FwMark = mark` sets firewall mark of the interface instead, the last one wins.
+pub fn parse_routing_keywords(s: &str) -> Result<RoutingHooks, String> {
+    let mut hooks = RoutingHooks::default();
+
+    for (i, line) in s.lines().map(str::trim).enumerate() {
+        // Shebang is skipped as a comment too.
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("Couldn't parse line {}: `{}`", i + 1, line));
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("Empty command on line {}.", i + 1));
+        }
+
+        if key.trim() == "FwMark" {
+            hooks.fwmark = Some(value.to_string());
+            continue;
+        }
+
+        let field = match key.trim() {
+            "PreUp" => &mut hooks.pre_up,
+            "PostUp" => &mut hooks.post_up,
+            "PreDown" => &mut hooks.pre_down,
+            "PostDown" => &mut hooks.post_down,
+            k => return Err(format!("Unexpected routing keyword {}.", k)),
+        };
+
+        match field {
+            Some(commands) => {
+                commands.push_str("; ");
+                commands.push_str(value);
+            }
+            None => *field = Some(value.to_string()),
+        }
+    }
+
+    Ok(hooks)
+}
+
+/// Reads routing script, returning its hooks together with its file name.
+pub fn read_routing_script(path: &Path) -> Result<(RoutingHooks, String), String> {
+    let hooks = read_config_file(path, cli::get_args().max_config_size)
+        .map_err(|e| e.to_string())
+        .and_then(|script| parse_routing_keywords(&script))?;
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into(),
+    );
+    Ok((hooks, name))
+}
+
+/// Applies hooks to every interface whose FwMark they wouldn't replace. Returns reasons the
+/// other interfaces were skipped for, as there is nobody to confirm the replacement with.
+pub fn apply_to_all<'a>(
+    hooks: &RoutingHooks,
+    script_name: &str,
+    interfaces: impl IntoIterator<Item = &'a mut Interface>,
+) -> Vec<String> {
+    let mut skipped = vec![];
+    for interface in interfaces {
+        match hooks.fwmark_conflict(interface) {
+            Some(current) => skipped.push(format!(
+                "{}: FwMark {current} would be replaced by {}",
+                interface.name.as_deref().unwrap_or("unknown"),
+                hooks.fwmark.as_deref().unwrap_or_default()
+            )),
+            None => hooks.apply(interface, script_name),
+        }
+    }
+    skipped
+}
+
+/// Splits hook into commands run one after another. Semicolons inside quotes don't separate
+/// commands.
+pub fn split_hook_commands(hook: &str) -> Vec<String> {
+    let mut commands = vec![];
+    let mut current = String::new();
+    let mut quote = None;
+
+    for c in hook.chars() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (';', None) => {
+                commands.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    commands.push(current);
+
+    commands
+        .into_iter()
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+/// Lists commands of every set hook as numbered steps, e.g. `PostUp:\n  1. ip route add ...`.
+pub fn format_hook_steps(hooks: &[(&str, &Option<String>)]) -> String {
+    let mut res = vec![];
+    for (name, hook) in hooks {
+        let commands = hook.as_deref().map(split_hook_commands).unwrap_or_default();
+        if commands.is_empty() {
+            continue;
+        }
+        res.push(format!("{name}:"));
+        for (i, command) in commands.iter().enumerate() {
+            res.push(format!("  {}. {command}", i + 1));
+        }
+    }
+    res.join("\n")
+}
+
+/// Static route through the tunnel interface.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Route {
+    pub destination: IpCidr,
+    pub via: Option<IpAddr>,
+}
+
+impl Route {
+    pub fn parse(destination: &str, via: &str) -> Result<Self, String> {
+        let destination = destination
+            .trim()
+            .parse::<IpCidr>()
+            .map_err(|_| format!("Could not parse route destination `{}`", destination.trim()))?;
+        let via = match via.trim() {
+            "" => None,
+            v => Some(
+                v.parse::<IpAddr>()
+                    .map_err(|_| format!("Could not parse gateway address `{v}`"))?,
+            ),
+        };
+
+        if via.is_some_and(|v| v.is_ipv4() != destination.is_ipv4()) {
+            return Err(String::from(
+                "Route destination and gateway must be of the same address family",
+            ));
+        }
+
+        Ok(Self { destination, via })
+    }
+
+    fn args(&self) -> String {
+        match self.via {
+            Some(via) => format!("{} via {} dev %i", self.destination, via),
+            None => format!("{} dev %i", self.destination),
+        }
+    }
+
+    pub fn up_command(&self) -> String {
+        format!("ip route add {}", self.args())
+    }
+
+    pub fn down_command(&self) -> String {
+        format!("ip route del {}", self.args())
+    }
+}
+
+/// Builds routing script that adds routes after interface is brought up and removes
+/// them after it is brought down.
+pub fn routes_to_script(routes: &[Route]) -> String {
+    let mut script = String::new();
+
+    for route in routes {
+        script.push_str(&format!("PostUp = {}\n", route.up_command()));
+        script.push_str(&format!("PostDown = {}\n", route.down_command()));
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_commands() {
+        let route = Route::parse("10.1.0.0/16", "10.0.0.1").unwrap();
+        assert_eq!(
+            route.up_command(),
+            "ip route add 10.1.0.0/16 via 10.0.0.1 dev %i"
+        );
+        assert_eq!(
+            route.down_command(),
+            "ip route del 10.1.0.0/16 via 10.0.0.1 dev %i"
+        );
+
+        let route = Route::parse("fd00::/64", "").unwrap();
+        assert_eq!(route.up_command(), "ip route add fd00::/64 dev %i");
+        assert_eq!(route.down_command(), "ip route del fd00::/64 dev %i");
+
+        assert!(Route::parse("10.1.0.0/33", "").is_err());
+        assert!(Route::parse("10.1.0.0/16", "fd00::1").is_err());
+    }
+
+    #[test]
+    fn routes_script_roundtrip() {
+        let routes = [
+            Route::parse("10.1.0.0/16", "10.0.0.1").unwrap(),
+            Route::parse("192.168.5.0/24", "").unwrap(),
+        ];
+        let script = routes_to_script(&routes);
+        assert_eq!(
+            script,
+            "PostUp = ip route add 10.1.0.0/16 via 10.0.0.1 dev %i
+PostDown = ip route del 10.1.0.0/16 via 10.0.0.1 dev %i
+PostUp = ip route add 192.168.5.0/24 dev %i
+PostDown = ip route del 192.168.5.0/24 dev %i
+"
+        );
+
+        let hooks = parse_routing_keywords(&script).unwrap();
+        assert_eq!(
+            hooks.post_up.as_deref(),
+            Some(
+                "ip route add 10.1.0.0/16 via 10.0.0.1 dev %i; ip route add 192.168.5.0/24 dev %i"
+            )
+        );
+        assert_eq!(
+            hooks.post_down.as_deref(),
+            Some(
+                "ip route del 10.1.0.0/16 via 10.0.0.1 dev %i; ip route del 192.168.5.0/24 dev %i"
+            )
+        );
+        assert_eq!(hooks.pre_up, None);
+
+        let mut interface = Interface {
+            post_up: Some("sysctl -w net.ipv4.ip_forward=1".into()),
+            ..Default::default()
+        };
+        hooks.append_to(&mut interface);
+        assert_eq!(
+            interface.post_up.as_deref(),
+            Some("sysctl -w net.ipv4.ip_forward=1; ip route add 10.1.0.0/16 via 10.0.0.1 dev %i; ip route add 192.168.5.0/24 dev %i")
+        );
+    }
+
+    #[test]
+    fn shebang_and_comments() {
+        let hooks = parse_routing_keywords(
+            "#!/bin/sh
+# Route office network through the tunnel.
+PostUp = ip route add 10.1.0.0/16 dev %i
+
+  # Indented comment
+PostDown = ip route del 10.1.0.0/16 dev %i
+",
+        )
+        .unwrap();
+        assert_eq!(
+            hooks.post_up.as_deref(),
+            Some("ip route add 10.1.0.0/16 dev %i")
+        );
+        assert_eq!(
+            hooks.post_down.as_deref(),
+            Some("ip route del 10.1.0.0/16 dev %i")
+        );
+
+        // Plain shell lines are still rejected.
+        assert!(parse_routing_keywords("#!/bin/sh\nip route add 10.1.0.0/16 dev wg0").is_err());
+    }
+
+    #[test]
+    fn hook_steps() {
+        assert_eq!(
+            split_hook_commands("ip route add 10.1.0.0/16 dev %i;ip link set %i up ; "),
+            ["ip route add 10.1.0.0/16 dev %i", "ip link set %i up"]
+        );
+        assert_eq!(
+            split_hook_commands("echo 'a; b'; sh -c \"x; y\""),
+            ["echo 'a; b'", "sh -c \"x; y\""]
+        );
+
+        let post_up = Some(String::from(
+            "sysctl -w net.ipv4.ip_forward=1; iptables -A FORWARD -i %i -j ACCEPT",
+        ));
+        let post_down = Some(String::from("iptables -D FORWARD -i %i -j ACCEPT"));
+        assert_eq!(
+            format_hook_steps(&[
+                ("PreUp", &None),
+                ("PostUp", &post_up),
+                ("PreDown", &Some(String::from(" ; "))),
+                ("PostDown", &post_down),
+            ]),
+            "PostUp:
+  1. sysctl -w net.ipv4.ip_forward=1
+  2. iptables -A FORWARD -i %i -j ACCEPT
+PostDown:
+  1. iptables -D FORWARD -i %i -j ACCEPT"
+        );
+        assert_eq!(format_hook_steps(&[("PreUp", &None)]), "");
+    }
+
+    #[test]
+    fn fwmark_conflict() {
+        let hooks = parse_routing_keywords("FwMark = 0x1\nFwMark = 51820\nPostUp = true").unwrap();
+        assert_eq!(hooks.fwmark.as_deref(), Some("51820"));
+
+        let mut interface = Interface::default();
+        assert_eq!(hooks.fwmark_conflict(&interface), None);
+
+        interface.fwmark = Some("51820".into());
+        assert_eq!(hooks.fwmark_conflict(&interface), None);
+
+        interface.fwmark = Some(" ".into());
+        assert_eq!(hooks.fwmark_conflict(&interface), None);
+
+        interface.fwmark = Some("0xca6c".into());
+        assert_eq!(hooks.fwmark_conflict(&interface), Some("0xca6c"));
+        assert_eq!(RoutingHooks::default().fwmark_conflict(&interface), None);
+
+        hooks.append_to(&mut interface);
+        assert_eq!(interface.fwmark.as_deref(), Some("51820"));
+        assert_eq!(interface.post_up.as_deref(), Some("true"));
+    }
+
+    #[test]
+    fn script_for_all_interfaces() {
+        let hooks = parse_routing_keywords("FwMark = 51820\nPostUp = ip rule add %i").unwrap();
+        let mut interfaces = [
+            Interface {
+                name: Some("office".into()),
+                post_up: Some("true".into()),
+                ..Default::default()
+            },
+            Interface {
+                name: Some("home".into()),
+                fwmark: Some("0xca6c".into()),
+                ..Default::default()
+            },
+            Interface {
+                name: Some("lab".into()),
+                fwmark: Some("51820".into()),
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(
+            apply_to_all(&hooks, "policy.sh", &mut interfaces),
+            ["home: FwMark 0xca6c would be replaced by 51820"]
+        );
+        let [office, home, lab] = &interfaces;
+        assert_eq!(office.post_up.as_deref(), Some("true; ip rule add %i"));
+        assert_eq!(office.fwmark.as_deref(), Some("51820"));
+        assert_eq!(office.routing_script.as_deref(), Some("policy.sh"));
+        assert_eq!(home.post_up, None);
+        assert_eq!(home.fwmark.as_deref(), Some("0xca6c"));
+        assert_eq!(home.routing_script, None);
+        assert_eq!(lab.post_up.as_deref(), Some("ip rule add %i"));
+        assert_eq!(lab.routing_script.as_deref(), Some("policy.sh"));
+    }
+
+    #[test]
+    fn unknown_keyword() {
+        assert!(parse_routing_keywords("Table = 123").is_err());
+        assert!(parse_routing_keywords("PostUp").is_err());
+    }
+}
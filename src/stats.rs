@@ -0,0 +1,338 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::io;
+use std::process::Command;
+use std::time::Duration;
+
+/// Received and sent bytes of all peers of an interface at some point in time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TransferSample {
+    /// Time the counters were read, relative to any fixed point.
+    pub time: Duration,
+    pub rx: u64,
+    pub tx: u64,
+}
+
+/// Transfer rates in bytes per second.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct TransferRate {
+    pub rx: f64,
+    pub tx: f64,
+}
+
+impl TransferRate {
+    /// Rates between two samples. Counters going backwards, e.g. after the interface was
+    /// restarted, count as no transfer.
+    pub fn between(earlier: &TransferSample, later: &TransferSample) -> Self {
+        let secs = later.time.saturating_sub(earlier.time).as_secs_f64();
+        if secs == 0.0 {
+            return Self::default();
+        }
+
+        Self {
+            rx: later.rx.saturating_sub(earlier.rx) as f64 / secs,
+            tx: later.tx.saturating_sub(earlier.tx) as f64 / secs,
+        }
+    }
+}
+
+/// Bounded history of transfer samples, oldest first.
+#[derive(Clone, Default, Debug)]
+pub struct TransferHistory {
+    samples: VecDeque<TransferSample>,
+    capacity: usize,
+}
+
+impl TransferHistory {
+    /// History keeping at most `capacity` samples.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds sample, dropping the oldest one once the history is full.
+    pub fn push(&mut self, sample: TransferSample) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        if self.capacity > 0 {
+            self.samples.push_back(sample);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Rates between consecutive samples, oldest first.
+    pub fn rates(&self) -> Vec<TransferRate> {
+        self.samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(earlier, later)| TransferRate::between(earlier, later))
+            .collect()
+    }
+}
+
+/// Sums received and sent bytes of all peers in output of `wg show <interface> dump`. The first
+/// line describes the interface, every following one a peer with its transfer counters in the
+/// sixth and seventh column.
+pub fn parse_transfer_totals(dump: &str) -> Option<(u64, u64)> {
+    let mut lines = dump.lines();
+    lines.next()?;
+
+    let mut totals = (0, 0);
+    for line in lines.filter(|l| !l.trim().is_empty()) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let rx: u64 = fields.get(5)?.parse().ok()?;
+        let tx: u64 = fields.get(6)?.parse().ok()?;
+        totals.0 += rx;
+        totals.1 += tx;
+    }
+
+    Some(totals)
+}
+
+/// Latest handshake and transfer counters of a peer of an active interface.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct PeerStats {
+    pub public_key: String,
+    /// Seconds since the epoch, 0 if the peer hasn't completed a handshake yet.
+    pub latest_handshake: u64,
+    pub rx: u64,
+    pub tx: u64,
+}
+
+/// Statistics of every peer in output of `wg show <interface> dump`, skipping malformed lines.
+/// Public key, latest handshake and transfer counters are in the first, fifth, sixth and
+/// seventh column of peer lines.
+pub fn parse_wg_dump(dump: &str) -> Vec<PeerStats> {
+    dump.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            Some(PeerStats {
+                public_key: fields.first().filter(|k| !k.is_empty())?.to_string(),
+                latest_handshake: fields.get(4)?.parse().ok()?,
+                rx: fields.get(5)?.parse().ok()?,
+                tx: fields.get(6)?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// One line summary of peer statistics of an active interface, e.g.
+/// `Handshake 12 s ago, received 1.5 KiB, sent 300 B`. `now` is in seconds since the epoch.
+pub fn stats_summary(peers: &[PeerStats], now: u64) -> String {
+    if peers.is_empty() {
+        return String::from("No peers");
+    }
+
+    let handshake = match peers.iter().map(|p| p.latest_handshake).max() {
+        Some(0) | None => String::from("No handshake yet"),
+        Some(latest) => format!("Handshake {} ago", format_age(now.saturating_sub(latest))),
+    };
+    let rx: u64 = peers.iter().map(|p| p.rx).sum();
+    let tx: u64 = peers.iter().map(|p| p.tx).sum();
+    format!(
+        "{handshake}, received {}, sent {}",
+        format_bytes(rx as f64),
+        format_bytes(tx as f64)
+    )
+}
+
+/// Formats duration in seconds in its largest whole unit, e.g. `3 min`.
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs} s"),
+        60..3600 => format!("{} min", secs / 60),
+        3600..86400 => format!("{} h", secs / 3600),
+        _ => format!("{} d", secs / 86400),
+    }
+}
+
+/// Orders peers by latest handshake, the ones that never completed one first and those whose
+/// handshake isn't known last.
+pub fn stalest_first(a: Option<u64>, b: Option<u64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Reads statistics of all peers of the interface using wireguard-tools.
+pub fn peer_stats(interface: &str) -> io::Result<Vec<PeerStats>> {
+    let output = Command::new("wg")
+        .args(["show", interface, "dump"])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "wg show {interface} dump failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let dump = String::from_utf8_lossy(&output.stdout);
+    if parse_transfer_totals(&dump).is_none() {
+        return Err(io::Error::other(format!(
+            "Could not parse wg show {interface} dump"
+        )));
+    }
+    Ok(parse_wg_dump(&dump))
+}
+
+/// Formats rate in bytes per second with binary unit prefix, e.g. `1.5 KiB/s`.
+pub fn format_rate(bytes_per_sec: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_sec))
+}
+
+/// Formats number of bytes with binary unit prefix, e.g. `1.5 KiB`.
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(secs: u64, rx: u64, tx: u64) -> TransferSample {
+        TransferSample {
+            time: Duration::from_secs(secs),
+            rx,
+            tx,
+        }
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(
+            TransferRate::between(&sample(10, 1000, 500), &sample(12, 5000, 600)),
+            TransferRate {
+                rx: 2000.0,
+                tx: 50.0
+            }
+        );
+        // Counters reset when interface was restarted.
+        assert_eq!(
+            TransferRate::between(&sample(10, 1000, 500), &sample(12, 10, 20)),
+            TransferRate::default()
+        );
+        assert_eq!(
+            TransferRate::between(&sample(10, 1000, 500), &sample(10, 2000, 500)),
+            TransferRate::default()
+        );
+
+        let mut history = TransferHistory::new(3);
+        assert_eq!(history.rates(), []);
+        history.push(sample(0, 0, 0));
+        assert_eq!(history.rates(), []);
+        history.push(sample(1, 100, 10));
+        history.push(sample(2, 300, 10));
+        history.push(sample(4, 700, 50));
+        assert_eq!(
+            history.rates(),
+            [
+                TransferRate { rx: 200.0, tx: 0.0 },
+                TransferRate {
+                    rx: 200.0,
+                    tx: 20.0
+                }
+            ]
+        );
+        history.clear();
+        assert_eq!(history.rates(), []);
+    }
+
+    #[test]
+    fn dump_totals() {
+        let dump = "privateKey=\tpublicKey=\t51820\toff
+peerA=\t(none)\t203.0.113.1:51820\t10.0.0.2/32\t1700000000\t1000\t2000\t25
+peerB=\t(none)\t(none)\t10.0.0.3/32\t0\t0\t0\toff
+peerC=\t(none)\t198.51.100.7:51820\t10.0.0.4/32\t1700000100\t24\t48\toff
+";
+        assert_eq!(parse_transfer_totals(dump), Some((1024, 2048)));
+        assert_eq!(
+            parse_transfer_totals("privateKey=\tpublicKey=\t51820\toff\n"),
+            Some((0, 0))
+        );
+        assert_eq!(parse_transfer_totals(""), None);
+        assert_eq!(
+            parse_transfer_totals("interface\npeer\t(none)\t(none)\t10.0.0.2/32\t0\tx\t0\toff"),
+            None
+        );
+    }
+
+    #[test]
+    fn handshakes() {
+        let dump = "privateKey=\tpublicKey=\t51820\toff
+peerA=\t(none)\t203.0.113.1:51820\t10.0.0.2/32\t1700000000\t1000\t2000\t25
+peerB=\t(none)\t(none)\t10.0.0.3/32\t0\t0\t0\toff
+peerC=\t(none)\t198.51.100.7:51820\t10.0.0.4/32\t1700000100\t24\t48\toff
+";
+        let stats = parse_wg_dump(dump);
+        assert_eq!(stats.len(), 3);
+        assert_eq!(
+            stats[0],
+            PeerStats {
+                public_key: String::from("peerA="),
+                latest_handshake: 1700000000,
+                rx: 1000,
+                tx: 2000,
+            }
+        );
+        assert_eq!(stats[1].latest_handshake, 0);
+        assert_eq!(
+            stats_summary(&stats, 1700000142),
+            "Handshake 42 s ago, received 1.0 KiB, sent 2.0 KiB"
+        );
+        assert_eq!(
+            stats_summary(&stats[1..2], 1700000142),
+            "No handshake yet, received 0 B, sent 0 B"
+        );
+        assert_eq!(parse_wg_dump("privateKey=\tpublicKey=\t51820\toff\n"), []);
+        assert_eq!(stats_summary(&[], 1700000142), "No peers");
+        assert_eq!(parse_wg_dump(""), []);
+
+        let handshake = |key: &str| {
+            stats
+                .iter()
+                .find(|p| p.public_key == key)
+                .map(|p| p.latest_handshake)
+        };
+        let mut peers = ["peerC=", "unknown=", "peerA=", "peerB="];
+        peers.sort_by(|a, b| stalest_first(handshake(a), handshake(b)));
+        assert_eq!(peers, ["peerB=", "peerA=", "peerC=", "unknown="]);
+        assert_eq!(stalest_first(None, None), Ordering::Equal);
+    }
+
+    #[test]
+    fn rate_format() {
+        assert_eq!(format_rate(0.0), "0 B/s");
+        assert_eq!(format_rate(1023.0), "1023 B/s");
+        assert_eq!(format_rate(1536.0), "1.5 KiB/s");
+        assert_eq!(format_rate(3.0 * 1024.0 * 1024.0), "3.0 MiB/s");
+        assert_eq!(format_bytes(300.0), "300 B");
+
+        assert_eq!(format_age(59), "59 s");
+        assert_eq!(format_age(150), "2 min");
+        assert_eq!(format_age(7200), "2 h");
+        assert_eq!(format_age(200000), "2 d");
+    }
+}
@@ -0,0 +1,90 @@
+//! Optional StatusNotifierItem tray icon with a menu toggling the tunnels.
+
+use ksni::menu::{CheckmarkItem, StandardItem};
+use ksni::{MenuItem, TrayMethods};
+use tokio::sync::watch;
+
+use crate::control::TunnelStatus;
+
+type ToggleCallback = Box<dyn Fn(String, bool) + Send>;
+type ShowCallback = Box<dyn Fn() + Send>;
+
+pub struct TunnelsTray {
+    tunnels: Vec<TunnelStatus>,
+    on_toggle: ToggleCallback,
+    on_show: ShowCallback,
+}
+
+impl ksni::Tray for TunnelsTray {
+    fn id(&self) -> String {
+        env!("CARGO_PKG_NAME").into()
+    }
+
+    fn title(&self) -> String {
+        String::from("Wireguard")
+    }
+
+    fn icon_name(&self) -> String {
+        if self.tunnels.iter().any(|t| t.active) {
+            String::from("network-vpn-symbolic")
+        } else {
+            String::from("network-vpn-disconnected-symbolic")
+        }
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        (self.on_show)();
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        let mut items: Vec<MenuItem<Self>> = self
+            .tunnels
+            .iter()
+            .map(|t| {
+                let name = t.name.clone();
+                let up = !t.active;
+                CheckmarkItem {
+                    label: t.name.clone(),
+                    checked: t.active,
+                    activate: Box::new(move |this: &mut Self| (this.on_toggle)(name.clone(), up)),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect();
+
+        items.push(MenuItem::Separator);
+        items.push(
+            StandardItem {
+                label: String::from("Show Window"),
+                activate: Box::new(|this: &mut Self| (this.on_show)()),
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        items
+    }
+}
+
+/// Shows the tray icon and keeps its menu in sync with `tunnels`. Returns an error if the
+/// desktop environment doesn't provide a tray.
+pub async fn run(
+    mut tunnels: watch::Receiver<Vec<TunnelStatus>>,
+    on_toggle: impl Fn(String, bool) + Send + 'static,
+    on_show: impl Fn() + Send + 'static,
+) -> Result<(), ksni::Error> {
+    let tray = TunnelsTray {
+        tunnels: tunnels.borrow_and_update().clone(),
+        on_toggle: Box::new(on_toggle),
+        on_show: Box::new(on_show),
+    };
+    let handle = tray.spawn().await?;
+
+    while tunnels.changed().await.is_ok() {
+        let statuses = tunnels.borrow_and_update().clone();
+        handle.update(|tray| tray.tunnels = statuses).await;
+    }
+
+    Ok(())
+}
@@ -1,108 +1,738 @@
-use std::{fs, io, process::Command};
+use std::io::{self, Write};
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::SystemTime,
+};
 
 use gtk::prelude::*;
 use relm4::prelude::*;
 
+use crate::cli;
 use crate::config::*;
+use crate::stats::{peer_stats, stats_summary, PeerStats};
+use crate::utils::{
+    check_interface_keys, check_peers_allowed_ips, check_private_key, read_config_file,
+    wg_iface_state, IfaceState, RetryPolicy,
+};
+
+/// Configuration file a tunnel was loaded from.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct TunnelSource {
+    pub path: PathBuf,
+    /// Modification time of the file when it was loaded, written by the application or when
+    /// the user chose to keep the version in the list over it.
+    pub modified: Option<SystemTime>,
+}
+
+impl TunnelSource {
+    /// Source at `path` as it is now.
+    pub fn new(path: PathBuf) -> Self {
+        let mut source = Self {
+            path,
+            modified: None,
+        };
+        source.modified = source.current_modified();
+        source
+    }
+
+    /// Current modification time of the file, `None` if it can't be read.
+    pub fn current_modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Whether the file changed since it was last seen.
+    pub fn changed(&self) -> bool {
+        self.current_modified() != self.modified
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Default, Clone)]
 pub struct Tunnel {
     pub name: String,
     pub config: WireguardConfig,
     pub active: bool,
+    /// Whether an interface with the tunnel's name exists, but isn't a WireGuard one.
+    pub name_conflict: bool,
+    /// Whether bringing the tunnel up failed because its interface was left over, e.g. after
+    /// a crash.
+    pub stale_interface: bool,
+    /// Name of the group shown above the tunnel if it's the first one of its group.
+    pub header: Option<String>,
+    /// Whether the group of the tunnel is collapsed.
+    pub collapsed: bool,
+    /// Whether the configuration is unchanged since it was loaded from [`Tunnel::source`].
+    pub saved: bool,
+    /// File the tunnel was loaded from, `None` for imported and generated tunnels.
+    pub source: Option<TunnelSource>,
+    /// Latest handshake and transfer totals while the tunnel is active.
+    stats: Option<String>,
+    /// Whether statistics are being polled.
+    polling: bool,
 }
 
 impl Tunnel {
-    pub fn new(config: WireguardConfig) -> Self {
+    /// Creates tunnel as inactive, its state is checked by [`TunnelMsg::CheckState`].
+    pub fn new(config: WireguardConfig, source: Option<TunnelSource>) -> Self {
         let name = config.interface.name.clone().unwrap_or("unknown".into());
-        let interface_path = format!("/sys/class/net/{name}/operstate");
-
-        let mut active = false;
-
-        if let Ok(status) = fs::read_to_string(interface_path) {
-            if status == "up\n" {
-                active = true;
-            }
-        }
 
         Self {
             name,
-            active,
+            saved: source.is_some(),
             config,
+            source,
+            ..Default::default()
+        }
+    }
+
+    /// Replaces configuration with an edited one. State of the interface has to be checked
+    /// again if this renames the tunnel.
+    pub fn set_config(&mut self, config: WireguardConfig) {
+        self.name = config.interface.name.clone().unwrap_or("unknown".into());
+        self.saved &= config == self.config;
+        self.config = config;
+    }
+
+    /// Replaces configuration with the one just loaded from [`Tunnel::source`].
+    pub fn reload(&mut self, config: WireguardConfig) {
+        self.set_config(config);
+        self.saved = self.source.is_some();
+    }
+
+    /// Text of the dialog confirming tunnel removal.
+    pub fn removal_confirmation(&self) -> String {
+        match &self.source {
+            Some(source) => format!(
+                "Deleting tunnel {} will permanently remove {}.\nRemoving it from the list only keeps the file on disk and hides the tunnel on next start too.",
+                self.name,
+                source.path.display()
+            ),
+            None => format!(
+                "Tunnel {} wasn't loaded from a file, it will only be removed from the list.",
+                self.name
+            ),
+        }
+    }
+
+    /// Stores current time as the last time tunnel was brought up, also in the file it was
+    /// loaded from if there is one.
+    fn record_connected(&mut self) {
+        let Ok(now) = gtk::glib::DateTime::now_utc().and_then(|t| t.format("%FT%TZ")) else {
+            return;
+        };
+        self.config.interface.last_connected = Some(now.to_string());
+
+        let Some(source) = &mut self.source else {
+            return;
+        };
+        // Only the timestamp line is updated, unsaved edits stay out of the file and the rest
+        // of it is kept as it is.
+        let res = read_config_file(&source.path, cli::get_args().max_config_size)
+            .and_then(|content| {
+                with_last_connected(&content, &now)
+                    .ok_or_else(|| io::Error::other("it has no [Interface] section"))
+            })
+            .and_then(|content| {
+                write_file_atomically(&source.path, |f| f.write_all(content.as_bytes()))
+            });
+        match res {
+            Ok(()) => source.modified = source.current_modified(),
+            Err(err) => eprintln!(
+                "Could not store last connection time in {}: {:#?}",
+                source.path.display(),
+                err
+            ),
         }
     }
 
     /// Toggle actual interface using wireguard-tools.
     pub fn toggle(&mut self) -> Result<(), io::Error> {
+        self.set_active(!self.active)
+    }
+
+    /// Bring interface up or down using wireguard-tools.
+    pub fn set_active(&mut self, up: bool) -> Result<(), io::Error> {
+        if self.active == up {
+            return Ok(());
+        }
+
+        if up {
+            check_private_key(&self.config.interface).map_err(io::Error::other)?;
+            check_peers_allowed_ips(&self.config.peers).map_err(io::Error::other)?;
+        }
+        if up && wg_iface_state(&self.name, RetryPolicy::default()) == IfaceState::NotWireguard {
+            self.name_conflict = true;
+            return Err(io::Error::other(format!(
+                "Interface {} already exists and isn't a WireGuard interface",
+                self.name
+            )));
+        }
+        self.name_conflict = false;
+
         let dir = tempfile::tempdir()?;
 
         let config_path = dir.path().join(format!("{}.conf", self.name));
 
         fs::write(&config_path, write_config(&self.config))?;
 
-        Command::new("wg-quick")
+        // wg-quick replaces resolver of a full tunnel, a failed attempt may leave it changed.
+        let resolv_conf_before =
+            overrides_system_dns(&self.config).then(|| fs::read_to_string(RESOLV_CONF).ok());
+
+        let output = Command::new("wg-quick")
             .args([
-                if self.active { "up" } else { "down" },
+                if up { "up" } else { "down" },
                 config_path.to_str().unwrap(),
             ])
+            .stderr(Stdio::piped())
             .spawn()?
-            .wait()?;
+            .wait_with_output()?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // Captured only to recognize errors, wg-quick's output still ends up on the terminal.
+        eprint!("{stderr}");
+        if !output.status.success() {
+            self.stale_interface = up && is_interface_exists_error(&stderr);
+            if self.stale_interface {
+                return Err(io::Error::other(format!(
+                    "Interface {} already exists, probably left over from an earlier run. Use Force Restart to delete it and bring the tunnel up again.",
+                    self.name
+                )));
+            }
+            let mut msg = format!(
+                "wg-quick failed to bring {} {}: {}\n{}",
+                self.name,
+                if up { "up" } else { "down" },
+                output.status,
+                stderr.trim()
+            );
+            if let Some(before) = resolv_conf_before {
+                let after = fs::read_to_string(RESOLV_CONF).ok();
+                if let Some(note) =
+                    dns_restore_note(&self.name, before.as_deref(), after.as_deref())
+                {
+                    msg.push_str("\n\n");
+                    msg.push_str(&note);
+                }
+            }
+            return Err(io::Error::other(msg));
+        }
+
+        self.active = up;
+        self.stale_interface = false;
+        if up {
+            self.record_connected();
+        }
 
         Ok(())
     }
+
+    /// Deletes interface left over with the tunnel's name, then brings the tunnel up.
+    pub fn force_up(&mut self) -> Result<(), io::Error> {
+        if wg_iface_state(&self.name, RetryPolicy::default()) == IfaceState::NotWireguard {
+            self.name_conflict = true;
+            return Err(io::Error::other(format!(
+                "Interface {} isn't a WireGuard interface, it won't be deleted",
+                self.name
+            )));
+        }
+
+        let output = Command::new("ip")
+            .args(["link", "delete", "dev", &self.name])
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "Could not delete interface {}: {}",
+                self.name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        self.active = false;
+        self.set_active(true)
+    }
+}
+
+/// Sets `# LastConnected` of the interface section in contents of a configuration file,
+/// adding the line below the section header if it's missing. Other lines are left as they are.
+/// Returns `None` if there is no interface section.
+pub fn with_last_connected(content: &str, time: &str) -> Option<String> {
+    let line = format!("# LastConnected = {time}\n");
+    let mut res = String::with_capacity(content.len() + line.len());
+    let mut header_end = None;
+    let mut in_interface = false;
+    let mut replaced = false;
+
+    for l in content.split_inclusive('\n') {
+        let trimmed = l.trim();
+        if trimmed.starts_with('[') {
+            in_interface = trimmed.eq_ignore_ascii_case("[Interface]");
+            if in_interface && header_end.is_none() {
+                res.push_str(l);
+                if !l.ends_with('\n') {
+                    res.push('\n');
+                }
+                header_end = Some(res.len());
+                continue;
+            }
+        }
+        let is_last_connected = trimmed
+            .strip_prefix('#')
+            .and_then(|c| c.split_once('='))
+            .is_some_and(|(key, _)| key.trim().eq_ignore_ascii_case("LastConnected"));
+        if in_interface && is_last_connected && !replaced {
+            res.push_str(&line);
+            replaced = true;
+        } else {
+            res.push_str(l);
+        }
+    }
+
+    let header_end = header_end?;
+    if !replaced {
+        res.insert_str(header_end, &line);
+    }
+    Some(res)
+}
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Explains how to restore system DNS if resolv.conf differs from its state before wg-quick
+/// was run.
+pub fn dns_restore_note(
+    interface: &str,
+    before: Option<&str>,
+    after: Option<&str>,
+) -> Option<String> {
+    (before != after).then(|| {
+        format!(
+            "System DNS in {RESOLV_CONF} was changed and may not resolve until it's restored, e.g. with `resolvconf -d {interface} -f` or `resolvectl revert {interface}`."
+        )
+    })
+}
+
+/// Whether wg-quick failed because an interface with the tunnel's name already exists.
+pub fn is_interface_exists_error(stderr: &str) -> bool {
+    stderr
+        .lines()
+        .any(|l| l.starts_with("wg-quick:") && l.trim_end().ends_with("already exists"))
+}
+
+/// Name of the group of tunnels without a tag.
+pub const UNGROUPED: &str = "Ungrouped";
+
+/// Group tunnel with given tag belongs to, `None` for ungrouped tunnels.
+pub fn group_of(tag: &Option<String>) -> Option<&str> {
+    tag.as_deref().filter(|t| !t.is_empty())
+}
+
+/// Name of the group tunnel with given tag belongs to.
+pub fn group_name(tag: &Option<String>) -> &str {
+    group_of(tag).unwrap_or(UNGROUPED)
+}
+
+/// Ordering of groups: tagged ones alphabetically, followed by ungrouped tunnels.
+fn group_key(tag: &Option<String>) -> (bool, &str) {
+    match group_of(tag) {
+        Some(t) => (false, t),
+        None => (true, ""),
+    }
+}
+
+/// Index at which tunnel with given tag has to be inserted into grouped list of tunnels,
+/// so it ends up last in its group.
+pub fn group_insert_position(tags: &[Option<String>], tag: &Option<String>) -> usize {
+    tags.iter()
+        .filter(|t| group_key(t) <= group_key(tag))
+        .count()
+}
+
+/// Returns name of the group for the first tunnel of every group in grouped list of
+/// tunnels, `None` for the rest.
+pub fn group_headers(tags: &[Option<String>]) -> Vec<Option<String>> {
+    tags.iter()
+        .enumerate()
+        .map(|(i, tag)| {
+            (i == 0 || group_of(&tags[i - 1]) != group_of(tag)).then(|| group_name(tag).to_string())
+        })
+        .collect()
+}
+
+/// Like [`group_headers`], but only tunnels that are shown get headers, so every group with a
+/// shown tunnel keeps its header.
+pub fn shown_group_headers(tags: &[Option<String>], shown: &[bool]) -> Vec<Option<String>> {
+    let shown_tags: Vec<Option<String>> = tags
+        .iter()
+        .zip(shown)
+        .filter(|(_, &s)| s)
+        .map(|(t, _)| t.clone())
+        .collect();
+    let mut headers = group_headers(&shown_tags).into_iter();
+
+    shown
+        .iter()
+        .map(|&s| if s { headers.next().flatten() } else { None })
+        .collect()
 }
 
 #[derive(Debug)]
 pub enum TunnelMsg {
     Toggle,
+    /// Checks in background whether the tunnel's interface is up.
+    CheckState,
+    /// Starts polling statistics if the tunnel is active and they aren't polled yet.
+    PollStats,
+    #[doc(hidden)]
+    ReadStats,
+    /// Deletes left over interface and brings the tunnel up.
+    ForceUp,
+    SetGroup {
+        header: Option<String>,
+        collapsed: bool,
+    },
 }
 
 #[derive(Debug)]
 pub enum TunnelOutput {
     Remove(DynamicIndex),
+    /// Collapses or expands group of the tunnel.
+    SetGroupCollapsed(DynamicIndex, bool),
+    /// Tunnel with given name was brought up or down.
+    Toggled(String),
+    /// State of the tunnel's interface was found out by [`TunnelMsg::CheckState`].
+    StateChecked,
     Error(String),
 }
 
+/// Result of work done by [`Tunnel`] in background.
+#[derive(Debug)]
+pub enum TunnelCommandOutput {
+    State(IfaceState),
+    Stats(Result<Vec<PeerStats>, String>),
+}
+
 #[relm4::factory(pub)]
 impl FactoryComponent for Tunnel {
-    type Init = WireguardConfig;
+    type Init = (WireguardConfig, Option<TunnelSource>);
     type Input = TunnelMsg;
     type Output = TunnelOutput;
-    type CommandOutput = ();
+    type CommandOutput = TunnelCommandOutput;
     type ParentWidget = gtk::ListBox;
 
     view! {
         #[root]
         gtk::Box {
-            set_orientation: gtk::Orientation::Horizontal,
-            set_spacing: 5,
+            set_orientation: gtk::Orientation::Vertical,
 
-            gtk::CheckButton {
-                connect_toggled => Self::Input::Toggle,
-                set_active: self.active,
-                set_label: Some(&self.name),
+            gtk::Expander {
+                #[watch]
+                set_visible: self.header.is_some(),
+                #[watch]
+                set_label: self.header.as_deref(),
+                #[watch]
+                #[block_signal(expand_handler)]
+                set_expanded: !self.collapsed,
+                connect_expanded_notify[sender, index] => move |e| {
+                    sender.output(Self::Output::SetGroupCollapsed(index.clone(), !e.is_expanded())).unwrap();
+                } @expand_handler,
             },
 
-            gtk::Button::with_label("Remove") {
-                connect_clicked[sender, index] => move |_| {
-                    sender.output(Self::Output::Remove(index.clone())).unwrap();
-                }
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+                #[watch]
+                set_visible: !self.collapsed,
+
+                gtk::CheckButton {
+                    #[watch]
+                    #[block_signal(toggle_handler)]
+                    set_active: self.active,
+                    set_label: Some(&self.name),
+                    connect_toggled => Self::Input::Toggle @toggle_handler,
+                },
+
+                gtk::Label {
+                    set_label: "\u{2022}",
+                    set_tooltip_text: Some(&match &self.source {
+                        Some(source) => format!("Changes aren't written to {}", source.path.display()),
+                        None => String::from("Tunnel isn't stored in a file"),
+                    }),
+                    #[watch]
+                    set_visible: !self.saved,
+                },
+
+                gtk::Label {
+                    add_css_class: "dim-label",
+                    #[watch]
+                    set_visible: self.active && self.stats.is_some(),
+                    #[watch]
+                    set_label: self.stats.as_deref().unwrap_or_default(),
+                },
+
+                gtk::Label {
+                    set_label: "Name is used by a non-WireGuard interface",
+                    #[watch]
+                    set_visible: self.name_conflict,
+                },
+
+                gtk::Button::with_label("Force Restart") {
+                    set_tooltip_text: Some("Delete the left over interface and bring the tunnel up again"),
+                    #[watch]
+                    set_visible: self.stale_interface,
+                    connect_clicked => Self::Input::ForceUp,
+                },
+
+                gtk::Button::with_label("Remove") {
+                    connect_clicked[sender, index] => move |_| {
+                        sender.output(Self::Output::Remove(index.clone())).unwrap();
+                    }
+                },
             },
         }
     }
 
-    fn init_model(config: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
-        Self::new(config)
+    fn init_model(
+        (config, source): Self::Init,
+        _index: &DynamicIndex,
+        sender: FactorySender<Self>,
+    ) -> Self {
+        sender.input(TunnelMsg::CheckState);
+        Self::new(config, source)
     }
 
     fn update(&mut self, msg: Self::Input, sender: relm4::FactorySender<Self>) {
         match msg {
             Self::Input::Toggle => match self.toggle() {
-                Ok(_) => self.active = !self.active,
+                Ok(()) => {
+                    if self.active {
+                        sender.input(Self::Input::PollStats);
+                        if let Err(err) = check_interface_keys(&self.config.interface) {
+                            sender.output_sender().emit(Self::Output::Error(err));
+                        }
+                    }
+                    sender
+                        .output_sender()
+                        .emit(Self::Output::Toggled(self.name.clone()))
+                }
+                Err(err) => sender
+                    .output_sender()
+                    .emit(Self::Output::Error(err.to_string())),
+            },
+            Self::Input::ForceUp => match self.force_up() {
+                Ok(()) => {
+                    sender.input(Self::Input::PollStats);
+                    sender
+                        .output_sender()
+                        .emit(Self::Output::Toggled(self.name.clone()))
+                }
                 Err(err) => sender
                     .output_sender()
                     .emit(Self::Output::Error(err.to_string())),
             },
+            Self::Input::CheckState => {
+                let name = self.name.clone();
+                sender.spawn_oneshot_command(move || {
+                    TunnelCommandOutput::State(wg_iface_state(
+                        &name,
+                        cli::get_args().state_check_policy(),
+                    ))
+                });
+            }
+            Self::Input::SetGroup { header, collapsed } => {
+                self.header = header;
+                self.collapsed = collapsed;
+            }
+            Self::Input::PollStats => {
+                if self.active && !self.polling && cli::get_args().poll_interval().is_some() {
+                    self.polling = true;
+                    sender.input(Self::Input::ReadStats);
+                }
+            }
+            Self::Input::ReadStats => {
+                if !self.active {
+                    self.polling = false;
+                    self.stats = None;
+                    return;
+                }
+                let name = self.name.clone();
+                sender.spawn_oneshot_command(move || {
+                    TunnelCommandOutput::Stats(peer_stats(&name).map_err(|e| e.to_string()))
+                });
+            }
         }
     }
+
+    fn update_cmd(&mut self, output: Self::CommandOutput, sender: FactorySender<Self>) {
+        let stats = match output {
+            TunnelCommandOutput::State(state) => {
+                self.active = state == IfaceState::WireguardUp;
+                self.name_conflict = state == IfaceState::NotWireguard;
+                sender.input(TunnelMsg::PollStats);
+                sender.output_sender().emit(TunnelOutput::StateChecked);
+                return;
+            }
+            TunnelCommandOutput::Stats(stats) => stats,
+        };
+        if !self.active {
+            self.polling = false;
+            self.stats = None;
+            return;
+        }
+
+        match stats {
+            Ok(peers) => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                self.stats = Some(stats_summary(&peers, now));
+            }
+            Err(err) => {
+                eprintln!("Could not read statistics of {}: {err}", self.name);
+                self.stats = None;
+            }
+        }
+
+        // Polling stops once the tunnel is down or removed from the list.
+        let interval = cli::get_args().poll_interval().unwrap_or(1);
+        let input = sender.input_sender().clone();
+        gtk::glib::timeout_add_seconds_local_once(interval, move || {
+            let _ = input.send(TunnelMsg::ReadStats);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<Option<String>> {
+        tags.iter()
+            .map(|t| (!t.is_empty()).then(|| t.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn grouping() {
+        let mut list = vec![];
+        for tag in tags(&["Work", "", "Personal", "Work", "", "Lab"]) {
+            let pos = group_insert_position(&list, &tag);
+            list.insert(pos, tag);
+        }
+        assert_eq!(list, tags(&["Lab", "Personal", "Work", "Work", "", ""]));
+
+        assert_eq!(
+            group_headers(&list),
+            [
+                Some("Lab".to_string()),
+                Some("Personal".to_string()),
+                Some("Work".to_string()),
+                None,
+                Some(UNGROUPED.to_string()),
+                None,
+            ]
+        );
+        assert_eq!(group_headers(&[]), Vec::<Option<String>>::new());
+
+        // Tag named like the group of untagged tunnels is a group of its own.
+        assert_eq!(
+            group_headers(&tags(&[UNGROUPED, ""])),
+            [Some(UNGROUPED.to_string()), Some(UNGROUPED.to_string())]
+        );
+    }
+
+    #[test]
+    fn shown_grouping() {
+        let list = tags(&["Lab", "Lab", "Work", "Work", ""]);
+        assert_eq!(
+            shown_group_headers(&list, &[false, true, true, false, false]),
+            [
+                None,
+                Some("Lab".to_string()),
+                Some("Work".to_string()),
+                None,
+                None
+            ]
+        );
+        assert_eq!(shown_group_headers(&list, &[true; 5]), group_headers(&list));
+    }
+
+    #[test]
+    fn last_connected_line() {
+        const TIME: &str = "2024-05-01T10:00:00Z";
+        assert_eq!(
+            with_last_connected(
+                "# Office VPN\n[Interface]\n# Name = wg0\n#LastConnected=2024-01-01T00:00:00Z\nAddress = 10.0.0.1/24 # home\n[Peer]\nPublicKey = a\n",
+                TIME
+            )
+            .as_deref(),
+            Some("# Office VPN\n[Interface]\n# Name = wg0\n# LastConnected = 2024-05-01T10:00:00Z\nAddress = 10.0.0.1/24 # home\n[Peer]\nPublicKey = a\n")
+        );
+        assert_eq!(
+            with_last_connected("[Interface]\r\nAddress = 10.0.0.1/24\r\n", TIME).as_deref(),
+            Some(
+                "[Interface]\r\n# LastConnected = 2024-05-01T10:00:00Z\nAddress = 10.0.0.1/24\r\n"
+            )
+        );
+        assert_eq!(
+            with_last_connected("[Interface]", TIME).as_deref(),
+            Some("[Interface]\n# LastConnected = 2024-05-01T10:00:00Z\n")
+        );
+        assert_eq!(with_last_connected("[Peer]\nPublicKey = a\n", TIME), None);
+    }
+
+    #[test]
+    fn saved_state() {
+        let config = parse_config("[Interface]\n# Name = wg0\nAddress = 10.0.0.1/24\n").unwrap();
+        let source = TunnelSource::new(PathBuf::from("/etc/wireguard/wg0.conf"));
+        assert!(!Tunnel::new(config.clone(), None).saved);
+
+        let mut tunnel = Tunnel::new(config.clone(), Some(source));
+        assert!(tunnel.saved);
+        tunnel.set_config(config.clone());
+        assert!(tunnel.saved);
+
+        let mut edited = config.clone();
+        edited.interface.address = Some("10.0.0.2/24".into());
+        tunnel.set_config(edited);
+        assert!(!tunnel.saved);
+        // Reverting the edit doesn't make the file match again as far as the list knows.
+        tunnel.set_config(config.clone());
+        assert!(!tunnel.saved);
+
+        tunnel.reload(config);
+        assert!(tunnel.saved);
+    }
+
+    #[test]
+    fn dns_restore() {
+        let original = "nameserver 192.168.1.1\n";
+        let tunnel = "nameserver 10.0.0.1\n";
+        assert_eq!(
+            dns_restore_note("wg0", Some(original), Some(original)),
+            None
+        );
+        assert_eq!(dns_restore_note("wg0", None, None), None);
+
+        let note = dns_restore_note("wg0", Some(original), Some(tunnel)).unwrap();
+        assert!(note.contains("resolvconf -d wg0 -f"));
+        assert!(dns_restore_note("wg0", Some(original), None).is_some());
+    }
+
+    #[test]
+    fn interface_exists_detection() {
+        assert!(is_interface_exists_error(
+            "wg-quick: `wg0' already exists\n"
+        ));
+        assert!(is_interface_exists_error(
+            "[#] ip link add wg0 type wireguard\nwg-quick: `wg0' already exists"
+        ));
+        assert!(!is_interface_exists_error(
+            "[#] ip link add wg0 type wireguard\nRTNETLINK answers: Operation not permitted\n"
+        ));
+        assert!(!is_interface_exists_error(
+            "wg-quick: `wg0' is not a WireGuard interface"
+        ));
+        assert!(!is_interface_exists_error(""));
+    }
 }
@@ -1,43 +1,425 @@
+use std::collections::HashMap;
 use std::fs;
-use std::io::{self, Error, Result, Write};
+use std::io::{Error, ErrorKind, Read, Result, Seek, Write};
+use std::net::IpAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
 use std::process::*;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::config::{parse_config, WireguardConfig};
+use cidr::{IpCidr, IpInet};
 
-const TUNNELS_PATH: &str = "/etc/wireguard";
+use nix::unistd::{getuid, AccessFlags, Group, Uid, User};
+
+use crate::cli;
+use crate::config::{
+    is_base64_key, lint_config, parse_config_with_errors, Interface, Peer, WireguardConfig,
+};
+
+pub const TUNNELS_PATH: &str = "/etc/wireguard";
 
 pub fn load_existing_configurations() -> Result<Vec<WireguardConfig>> {
-    let mut cfgs = vec![];
+    let (paths, skipped) =
+        limit_configuration_paths(existing_configuration_paths()?, cli::get_args().max_tunnels);
+    if skipped > 0 {
+        eprintln!("Skipped {skipped} configurations over the limit of loaded tunnels");
+    }
+    let mut names = HashMap::new();
+    let mut configs = vec![];
+    for path in paths {
+        let cfg = load_configuration(&path)?;
+        match claim_tunnel_name(&cfg, &path, &mut names) {
+            Ok(()) => configs.push(cfg),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+    Ok(configs)
+}
+
+/// Reads configuration file, refusing files larger than `max_size` bytes.
+pub fn read_config_file(path: &Path, max_size: u64) -> Result<String> {
+    let too_large = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is larger than {max_size} bytes", path.display()),
+        )
+    };
+
+    let file = fs::File::open(path)?;
+    if file.metadata()?.len() > max_size {
+        return Err(too_large());
+    }
+
+    // File might grow after its size was checked.
+    let mut content = vec![];
+    file.take(max_size + 1).read_to_end(&mut content)?;
+    if content.len() as u64 > max_size {
+        return Err(too_large());
+    }
+
+    decode_config(path, content, cli::get_args().lossy_utf8)
+}
+
+/// Decodes configuration file as UTF-8, pointing out the first invalid byte unless invalid
+/// sequences are to be replaced with U+FFFD.
+fn decode_config(path: &Path, content: Vec<u8>, lossy: bool) -> Result<String> {
+    match String::from_utf8(content) {
+        Ok(content) => Ok(content),
+        Err(e) if lossy => Ok(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+        Err(e) => {
+            let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+            let line_start = valid.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} is not valid UTF-8: byte 0x{:02x} on line {}, column {}",
+                    path.display(),
+                    e.as_bytes()[valid.len()],
+                    valid.iter().filter(|&&b| b == b'\n').count() + 1,
+                    valid.len() - line_start + 1
+                ),
+            ))
+        }
+    }
+}
+
+/// Makes sure tunnel configurations can be stored in `dir`, creating it accessible only by
+/// the owner if it's missing.
+pub fn ensure_tunnels_dir(dir: &Path) -> std::result::Result<(), String> {
+    let unusable = |reason: String| format!("Tunnel directory {} {reason}", dir.display());
+
+    if !dir.exists() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|e| unusable(format!("could not be created: {e}")))?;
+    }
+    if !dir.is_dir() {
+        return Err(unusable(String::from("is not a directory")));
+    }
+    nix::unistd::access(dir, AccessFlags::W_OK | AccessFlags::X_OK)
+        .map_err(|e| unusable(format!("is not writable: {e}")))
+}
+
+/// Paths of configuration files in [`TUNNELS_PATH`], without the ignored ones.
+pub fn existing_configuration_paths() -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    let ignored = read_ignored_configs(&ignored_configs_path()).unwrap_or_else(|err| {
+        eprintln!("Could not read ignored configurations: {:#?}", err);
+        vec![]
+    });
 
     for entry in fs::read_dir(TUNNELS_PATH)? {
         let file = entry?;
         if file.file_type()?.is_file() {
             let file_path = file.path();
-            let file_content = fs::read_to_string(&file_path)?;
-            let mut cfg = parse_config(&file_content).map_err(Error::other)?;
-            if cfg.interface.name.is_none() {
-                if let Some(file_name) = file_path.file_stem().and_then(|n| n.to_str()) {
-                    cfg.interface.name = Some(file_name.to_string());
-                }
+            if ignored.iter().any(|i| Path::new(i) == file_path) {
+                continue;
+            }
+            paths.push(file_path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Keeps first `max` paths in name order, returning them with the number of skipped ones.
+pub fn limit_configuration_paths(mut paths: Vec<PathBuf>, max: usize) -> (Vec<PathBuf>, usize) {
+    paths.sort();
+    let skipped = paths.len().saturating_sub(max);
+    paths.truncate(max);
+    (paths, skipped)
+}
+
+/// Reads configuration file, naming the tunnel after the file if it has no name.
+pub fn load_configuration(file_path: &Path) -> Result<WireguardConfig> {
+    let file_content = read_config_file(file_path, cli::get_args().max_config_size)?;
+    let mut cfg = parse_config_with_errors(&file_content)
+        .map_err(|e| Error::other(format!("{}: {}", file_path.display(), e.join("\n"))))?;
+    if cfg.interface.name.is_none() {
+        if let Some(file_name) = file_path.file_stem().and_then(|n| n.to_str()) {
+            cfg.interface.name = Some(file_name.to_string());
+        }
+    }
+
+    Ok(cfg)
+}
+
+/// Records name of the tunnel loaded from `path`, failing if a configuration loaded earlier
+/// resolved to the same name. Tunnels are told apart by their names, so both would share the
+/// interface and the configuration file.
+pub fn claim_tunnel_name(
+    cfg: &WireguardConfig,
+    path: &Path,
+    loaded: &mut HashMap<String, PathBuf>,
+) -> std::result::Result<(), String> {
+    let name = cfg.interface.name.clone().unwrap_or_default();
+    if let Some(other) = loaded.get(&name) {
+        return Err(format!(
+            "{}: tunnel {name} is already loaded from {}, set a different # Name to load both",
+            path.display(),
+            other.display()
+        ));
+    }
+    loaded.insert(name, path.to_path_buf());
+    Ok(())
+}
+
+/// Returns paths of `.conf` files in directory, sorted by name.
+pub fn list_configs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_file() && path.extension().is_some_and(|e| e == "conf") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Non-root user who started the app as root through pkexec or sudo.
+pub fn invoking_user() -> Option<User> {
+    resolve_invoking_user(
+        getuid().is_root(),
+        |key| std::env::var(key).ok(),
+        |uid| User::from_uid(Uid::from_raw(uid)).ok().flatten(),
+        |name| User::from_name(name).ok().flatten(),
+    )
+}
+
+/// Looks up user set by pkexec in `PKEXEC_UID` or by sudo in `SUDO_USER`. Both are only
+/// trusted when running as root.
+fn resolve_invoking_user<T>(
+    is_root: bool,
+    env: impl Fn(&str) -> Option<String>,
+    by_uid: impl FnOnce(u32) -> Option<T>,
+    by_name: impl FnOnce(&str) -> Option<T>,
+) -> Option<T> {
+    if !is_root {
+        return None;
+    }
+    if let Some(uid) = env("PKEXEC_UID")
+        .and_then(|uid| uid.trim().parse::<u32>().ok())
+        .filter(|&uid| uid != 0)
+    {
+        return by_uid(uid);
+    }
+    env("SUDO_USER")
+        .filter(|name| !name.is_empty() && name != "root")
+        .and_then(|name| by_name(&name))
+}
+
+/// Home directory of the user, which is the invoking user's one rather than root's when the
+/// app was started through pkexec or sudo.
+pub fn invoking_user_home() -> Option<PathBuf> {
+    invoking_user()
+        .map(|user| user.dir)
+        .or_else(|| std::env::var_os("HOME").map(PathBuf::from))
+}
+
+/// Path of the file listing configurations hidden from the tunnel list.
+pub fn ignored_configs_path() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| invoking_user_home().map(|h| h.join(".config")))
+        .unwrap_or_else(|| PathBuf::from("/etc"))
+        .join("wireguard-gui")
+        .join("ignored-configs")
+}
+
+fn parse_ignored_configs(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Reads paths of ignored configuration files, one per line. Missing file means nothing is
+/// ignored.
+pub fn read_ignored_configs(path: &Path) -> Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(parse_ignored_configs(&content)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err),
+    }
+}
+
+/// Adds configuration file to the ignore list so it isn't loaded on next start.
+pub fn add_ignored_config(path: &Path, config: &Path) -> Result<()> {
+    let config = config
+        .to_str()
+        .ok_or_else(|| Error::other(format!("{} is not a utf-8 path", config.display())))?;
+    let mut file = open_ignored_configs(path, invoking_user().as_ref())?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+
+    let mut ignored = parse_ignored_configs(&content);
+    if ignored.iter().any(|i| i == config) {
+        return Ok(());
+    }
+    ignored.push(config.to_string());
+
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all((ignored.join("\n") + "\n").as_bytes())
+}
+
+/// Opens the ignore list for reading and writing, creating it if needed. Paths in the home of
+/// the invoking user are opened by [`open_in_home`], as the user controls them.
+fn open_ignored_configs(path: &Path, user: Option<&User>) -> Result<fs::File> {
+    match user {
+        Some(user) => open_in_home(path, user),
+        None => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
             }
-            cfgs.push(cfg);
+            fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        }
+    }
+}
+
+/// Opens file in the home of `user` for reading and writing, creating it and the directories
+/// on the way as owned by the user. Symlinks below the home aren't followed and existing
+/// entries have to be owned by the user, the file also can't be a hard link, so a root
+/// process isn't tricked into writing elsewhere.
+fn open_in_home(path: &Path, user: &User) -> Result<fs::File> {
+    use nix::fcntl::{openat, OFlag};
+    use nix::sys::stat::{mkdirat, Mode};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    fn open_at(dir: &fs::File, name: &std::ffi::OsStr, flags: OFlag) -> Result<fs::File> {
+        let flags = flags | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC;
+        let fd = openat(
+            Some(dir.as_raw_fd()),
+            name,
+            flags,
+            Mode::from_bits_truncate(0o644),
+        )?;
+        // SAFETY: `openat` returned a new descriptor that nothing else owns.
+        Ok(fs::File::from(unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+    let owned_by_user = |file: &fs::File, path: &Path| {
+        let metadata = file.metadata()?;
+        if metadata.uid() != user.uid.as_raw() {
+            return Err(Error::other(format!(
+                "{} is not owned by {}",
+                path.display(),
+                user.name
+            )));
+        }
+        Ok(metadata)
+    };
+
+    let invalid = || {
+        Error::other(format!(
+            "{} is not a file in home of {}",
+            path.display(),
+            user.name
+        ))
+    };
+    let relative = path.strip_prefix(&user.dir).map_err(|_| invalid())?;
+    let mut names = relative
+        .components()
+        .map(|c| match c {
+            std::path::Component::Normal(name) => Ok(name),
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let file_name = names.pop().ok_or_else(invalid)?;
+    let (uid, gid) = (Some(user.uid.as_raw()), Some(user.gid.as_raw()));
+
+    let mut dir = fs::File::open(&user.dir)?;
+    let mut current = user.dir.clone();
+    for name in names {
+        current.push(name);
+        let created = match mkdirat(Some(dir.as_raw_fd()), name, Mode::S_IRWXU) {
+            Ok(()) => true,
+            Err(nix::errno::Errno::EEXIST) => false,
+            Err(err) => return Err(err.into()),
+        };
+        dir = open_at(&dir, name, OFlag::O_RDONLY | OFlag::O_DIRECTORY)?;
+        if created {
+            fchown(&dir, uid, gid)?;
         }
+        owned_by_user(&dir, &current)?;
     }
 
-    Ok(cfgs)
+    current.push(file_name);
+    match open_at(
+        &dir,
+        file_name,
+        OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL,
+    ) {
+        Ok(file) => {
+            fchown(&file, uid, gid)?;
+            Ok(file)
+        }
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            let file = open_at(&dir, file_name, OFlag::O_RDWR)?;
+            let metadata = owned_by_user(&file, &current)?;
+            if !metadata.is_file() || metadata.nlink() != 1 {
+                return Err(Error::other(format!(
+                    "{} is not a regular file",
+                    current.display()
+                )));
+            }
+            Ok(file)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// External tools the application runs, with features that don't work without them.
+pub const REQUIRED_TOOLS: &[(&str, &str)] = &[
+    (
+        "wg",
+        if cfg!(feature = "native-keys") {
+            "tunnel state checks"
+        } else {
+            "key generation and tunnel state checks"
+        },
+    ),
+    ("wg-quick", "bringing tunnels up and down"),
+];
+
+/// Returns binaries that aren't found as executable files in any directory listed in `path`,
+/// which has the format of `PATH` environment variable.
+pub fn missing_binaries<'a>(binaries: &[&'a str], path: &std::ffi::OsStr) -> Vec<&'a str> {
+    binaries
+        .iter()
+        .copied()
+        .filter(|binary| {
+            !std::env::split_paths(path).any(|dir| {
+                fs::metadata(dir.join(binary))
+                    .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            })
+        })
+        .collect()
 }
 
+#[cfg(not(feature = "native-keys"))]
 pub fn generate_private_key() -> Result<String> {
     let output = Command::new("wg")
         .arg("genkey")
         .stdout(Stdio::piped())
         .output()?;
 
-    String::from_utf8(output.stdout)
-        .map(|s| s.trim().into())
-        .map_err(|_| io::Error::other("Could not convert output of `wg genkey` to utf-8 string."))
+    key_from_output("wg genkey", output.stdout)
 }
 
+#[cfg(not(feature = "native-keys"))]
 pub fn generate_public_key(priv_key: String) -> Result<String> {
     let mut child = Command::new("wg")
         .arg("pubkey")
@@ -55,12 +437,2144 @@ pub fn generate_public_key(priv_key: String) -> Result<String> {
 
     let output = child.wait_with_output().expect("Failed to read stdout");
 
-    String::from_utf8(output.stdout)
+    key_from_output("wg pubkey", output.stdout)
+}
+
+#[cfg(not(feature = "native-keys"))]
+pub fn generate_preshared_key() -> Result<String> {
+    let output = Command::new("wg")
+        .arg("genpsk")
+        .stdout(Stdio::piped())
+        .output()?;
+
+    key_from_output("wg genpsk", output.stdout)
+}
+
+/// Converts output of key generating command into trimmed string. The error contains the
+/// output with invalid bytes replaced, so it can be diagnosed.
+#[cfg(not(feature = "native-keys"))]
+fn key_from_output(command: &str, stdout: Vec<u8>) -> Result<String> {
+    String::from_utf8(stdout)
         .map(|s| s.trim().into())
-        .map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                "Could not convert output of `wg pubkey` to utf-8 string.",
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Output of `{command}` is not a utf-8 string: {:?}",
+                    String::from_utf8_lossy(e.as_bytes())
+                ),
+            )
+        })
+}
+
+/// Generates clamped Curve25519 private key in-process, the same way `wg genkey` does.
+#[cfg(feature = "native-keys")]
+pub fn generate_private_key() -> Result<String> {
+    use base64::prelude::*;
+
+    let mut key = x25519_dalek::StaticSecret::random().to_bytes();
+    key[0] &= 248;
+    key[31] = (key[31] & 127) | 64;
+
+    Ok(BASE64_STANDARD.encode(key))
+}
+
+/// Generates 32 random bytes in-process, the same way `wg genpsk` does.
+#[cfg(feature = "native-keys")]
+pub fn generate_preshared_key() -> Result<String> {
+    use base64::prelude::*;
+
+    Ok(BASE64_STANDARD.encode(x25519_dalek::StaticSecret::random().to_bytes()))
+}
+
+/// Derives public key in-process, the same way `wg pubkey` does.
+#[cfg(feature = "native-keys")]
+pub fn generate_public_key(priv_key: String) -> Result<String> {
+    use base64::prelude::*;
+
+    let key: [u8; 32] = BASE64_STANDARD
+        .decode(priv_key.trim())
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "Key is not a valid base64 encoded 32 byte key",
             )
+        })?;
+    let public = x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(key));
+
+    Ok(BASE64_STANDARD.encode(public.as_bytes()))
+}
+
+/// Resolves configured owner and group names into ids.
+pub fn resolve_ownership(
+    owner: Option<&str>,
+    group: Option<&str>,
+) -> Result<(Option<u32>, Option<u32>)> {
+    let uid = owner
+        .map(|name| match User::from_name(name) {
+            Ok(Some(user)) => Ok(user.uid.as_raw()),
+            Ok(None) => Err(Error::other(format!("Unknown user {name}"))),
+            Err(e) => Err(Error::from(e)),
+        })
+        .transpose()?;
+    let gid = group
+        .map(|name| match Group::from_name(name) {
+            Ok(Some(group)) => Ok(group.gid.as_raw()),
+            Ok(None) => Err(Error::other(format!("Unknown group {name}"))),
+            Err(e) => Err(Error::from(e)),
+        })
+        .transpose()?;
+
+    Ok((uid, gid))
+}
+
+/// Restricts configuration file to be readable only by its owner and applies ownership.
+pub fn fix_config_permissions(path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    if uid.is_some() || gid.is_some() {
+        std::os::unix::fs::chown(path, uid, gid)?;
+    }
+    Ok(())
+}
+
+/// Applies [`fix_config_permissions`] to every `.conf` file in `dir`, returning result for
+/// each file.
+pub fn fix_configs_permissions(
+    dir: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> Result<Vec<(PathBuf, Result<()>)>> {
+    let mut results = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == "conf") {
+            let res = fix_config_permissions(&path, uid, gid);
+            results.push((path, res));
+        }
+    }
+
+    Ok(results)
+}
+
+/// Checks whether file is owned by the given user and group. Unset ids always match.
+pub fn ownership_matches(path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<bool> {
+    let stat = nix::sys::stat::stat(path)?;
+    Ok(uid.is_none_or(|uid| stat.st_uid == uid) && gid.is_none_or(|gid| stat.st_gid == gid))
+}
+
+/// Lists `.conf` files in `dir` not owned by the given user and group.
+pub fn find_wrong_ownership(
+    dir: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+
+    if uid.is_none() && gid.is_none() {
+        return Ok(paths);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file()
+            && path.extension().is_some_and(|e| e == "conf")
+            && !ownership_matches(&path, uid, gid)?
+        {
+            paths.push(path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Waits for child process to exit, killing it if it doesn't finish within timeout.
+pub fn wait_cmd_with_timeout(child: &mut Child, timeout: Duration) -> Result<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(Error::new(ErrorKind::TimedOut, "Command timed out"));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Retry policy with exponential backoff.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub attempts: u32,
+    /// Delay before the second attempt.
+    pub interval: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            interval: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns delay before the attempt following failed `attempt` (counted from 0), or
+    /// `None` if no attempts are left.
+    pub fn next_delay(&self, attempt: u32) -> Option<Duration> {
+        (attempt.saturating_add(1) < self.attempts)
+            .then(|| self.interval.saturating_mul(2u32.saturating_pow(attempt)))
+    }
+
+    /// Runs check until it succeeds or attempts are exhausted.
+    pub fn retry(&self, mut check: impl FnMut() -> bool) -> bool {
+        let mut attempt = 0;
+        loop {
+            if check() {
+                return true;
+            }
+            match self.next_delay(attempt) {
+                Some(delay) => std::thread::sleep(delay),
+                None => return false,
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// State of the network interface a tunnel is brought up as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IfaceState {
+    Down,
+    WireguardUp,
+    /// Interface of the same name exists, but it isn't a WireGuard one.
+    NotWireguard,
+}
+
+/// Tells WireGuard interface apart from an unrelated one of the same name, given contents of
+/// its sysfs `uevent` if it exists and whether `wg show` recognized it. Userspace
+/// implementations run on tun devices, so `wg show` is trusted over the device type.
+pub fn classify_iface(uevent: Option<&str>, wg_show_ok: bool) -> IfaceState {
+    if wg_show_ok {
+        return IfaceState::WireguardUp;
+    }
+    match uevent {
+        Some(uevent) if !uevent.lines().any(|l| l.trim() == "DEVTYPE=wireguard") => {
+            IfaceState::NotWireguard
+        }
+        _ => IfaceState::Down,
+    }
+}
+
+/// Checks whether interface is up and is a WireGuard one using `wg show` and sysfs.
+pub fn wg_iface_state(iface: &str, policy: RetryPolicy) -> IfaceState {
+    let mut state = IfaceState::Down;
+    policy.retry(|| {
+        let wg_show_ok = Command::new("wg")
+            .args(["show", iface])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        let uevent = fs::read_to_string(Path::new("/sys/class/net").join(iface).join("uevent"));
+        state = classify_iface(uevent.ok().as_deref(), wg_show_ok);
+        state != IfaceState::Down
+    });
+    state
+}
+
+/// Names of network interfaces of the system that aren't WireGuard ones. When sysfs is
+/// unavailable, e.g. in containers, all non-loopback interfaces are listed together with a
+/// warning, as WireGuard ones can't be told apart.
+pub fn non_wireguard_interfaces() -> Result<(Vec<String>, Option<String>)> {
+    list_non_wireguard_interfaces(Path::new("/sys/class/net"), Path::new("/proc/net/dev"))
+}
+
+fn list_non_wireguard_interfaces(
+    sysfs: &Path,
+    proc_net_dev: &Path,
+) -> Result<(Vec<String>, Option<String>)> {
+    let warning = |source: &Path| {
+        format!(
+            "Could not read interface types from {}, listing all non-loopback interfaces of {}",
+            sysfs.display(),
+            source.display()
+        )
+    };
+
+    let entries = match fs::read_dir(sysfs) {
+        Ok(entries) => entries.collect::<Result<Vec<_>>>()?,
+        Err(err) => {
+            let Ok(dev) = fs::read_to_string(proc_net_dev) else {
+                return Err(err);
+            };
+            return Ok((proc_net_dev_interfaces(&dev), Some(warning(proc_net_dev))));
+        }
+    };
+
+    let mut names = vec![];
+    let mut all = vec![];
+    let mut unreadable = 0;
+    for entry in &entries {
+        let Some(name) = entry.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        match fs::read_to_string(entry.path().join("uevent")) {
+            Ok(uevent) if classify_iface(Some(&uevent), false) == IfaceState::NotWireguard => {
+                names.push(name.clone())
+            }
+            Ok(_) => {}
+            Err(_) => unreadable += 1,
+        }
+        if name != "lo" {
+            all.push(name);
+        }
+    }
+
+    if !entries.is_empty() && unreadable == entries.len() {
+        return Ok((all, Some(warning(sysfs))));
+    }
+    Ok((names, None))
+}
+
+/// Names of interfaces in `/proc/net/dev` except loopback. The first two lines are headers,
+/// every following one starts with interface name followed by a colon.
+fn proc_net_dev_interfaces(dev: &str) -> Vec<String> {
+    dev.lines()
+        .skip(2)
+        .filter_map(|l| l.split_once(':'))
+        .map(|(name, _)| name.trim().to_string())
+        .filter(|name| !name.is_empty() && name != "lo")
+        .collect()
+}
+
+/// Rejects tunnel name that is already used by one of given non-WireGuard interfaces.
+pub fn check_name_collision(
+    name: &str,
+    non_wireguard: &[String],
+) -> std::result::Result<(), String> {
+    if non_wireguard.iter().any(|i| i == name) {
+        return Err(format!(
+            "Interface {name} already exists and isn't a WireGuard interface"
+        ));
+    }
+    Ok(())
+}
+
+/// Removes duplicate entries of comma separated AllowedIPs, as well as entries contained in
+/// other ones if `collapse_subsumed` is set. Order of the remaining entries is kept and
+/// entries that can't be parsed are only deduplicated textually.
+pub fn normalize_allowed_ips(allowed_ips: &str, collapse_subsumed: bool) -> String {
+    let entries: Vec<(&str, Option<IpCidr>)> = allowed_ips
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|e| (e, e.parse::<IpCidr>().ok()))
+        .collect();
+
+    let mut kept: Vec<(&str, Option<IpCidr>)> = vec![];
+    for &(entry, cidr) in &entries {
+        let duplicate = kept.iter().any(|&(e, c)| match (c, cidr) {
+            (Some(c), Some(cidr)) => c == cidr,
+            _ => e == entry,
+        });
+        let subsumed = collapse_subsumed
+            && cidr.is_some_and(|cidr| {
+                entries.iter().any(|&(_, other)| {
+                    other.is_some_and(|other| {
+                        other.is_ipv4() == cidr.is_ipv4()
+                            && other.network_length() < cidr.network_length()
+                            && other.contains(&cidr.first_address())
+                    })
+                })
+            });
+        if !duplicate && !subsumed {
+            kept.push((entry, cidr));
+        }
+    }
+
+    kept.iter().map(|(e, _)| *e).collect::<Vec<_>>().join(", ")
+}
+
+/// Whether two networks share any address.
+pub fn cidrs_overlap(a: &IpCidr, b: &IpCidr) -> bool {
+    a.is_ipv4() == b.is_ipv4() && (a.contains(&b.first_address()) || b.contains(&a.first_address()))
+}
+
+/// Lists AllowedIPs of enabled peers of `config` overlapping with ones of the other tunnels,
+/// given with their names. Routes of different interfaces conflict with each other, unlike
+/// the ones of peers of the same interface.
+pub fn allowed_ips_conflicts<'a>(
+    config: &WireguardConfig,
+    others: impl IntoIterator<Item = (&'a str, &'a WireguardConfig)>,
+) -> Vec<String> {
+    fn networks(cfg: &WireguardConfig) -> Vec<IpCidr> {
+        cfg.peers
+            .iter()
+            .filter(|p| !p.disabled)
+            .filter_map(|p| p.allowed_ips.as_deref())
+            .flat_map(|ips| ips.split(','))
+            .filter_map(parse_allowed_ip)
+            .collect()
+    }
+
+    let own = networks(config);
+    let mut conflicts = vec![];
+    for (name, other) in others {
+        for theirs in networks(other) {
+            for ours in own.iter().filter(|ours| cidrs_overlap(ours, &theirs)) {
+                conflicts.push(format!(
+                    "AllowedIPs {ours} overlap with {theirs} of active tunnel {name}"
+                ));
+            }
+        }
+    }
+    conflicts
+}
+
+/// Splits endpoint in `host:port` or `[ipv6]:port` form into host and port. Brackets are
+/// removed from IPv6 hosts.
+pub fn parse_endpoint(s: &str) -> std::result::Result<(String, u16), String> {
+    let s = s.trim();
+    let (host, port) = s
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Endpoint `{s}` doesn't specify a port"))?;
+
+    let host = match host.strip_prefix('[') {
+        Some(h) => h
+            .strip_suffix(']')
+            .ok_or_else(|| format!("Unbalanced brackets in endpoint `{s}`"))?,
+        None if host.contains(':') => {
+            return Err(format!(
+                "IPv6 endpoint `{s}` must be written as [address]:port"
+            ))
+        }
+        None => host,
+    };
+    if host.is_empty() {
+        return Err(format!("Endpoint `{s}` doesn't specify a host"));
+    }
+
+    let port = parse_port(port)?;
+
+    Ok((host.to_string(), port))
+}
+
+/// Checks that endpoint host is an IP address or a plausible DNS name.
+pub fn validate_endpoint_host(host: &str) -> std::result::Result<(), String> {
+    let host = host.trim();
+    if let Some(ip) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        return match ip.parse::<std::net::Ipv6Addr>() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("`{ip}` is not a valid IPv6 address")),
+        };
+    }
+    if host.parse::<IpAddr>().is_ok() {
+        return Ok(());
+    }
+
+    let name = host.strip_suffix('.').unwrap_or(host);
+    let valid_label = |label: &str| {
+        (1..=63).contains(&label.len())
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+    };
+    // Top-level domains are never numeric, which rules out mistyped IPv4 addresses.
+    let numeric_tld = name
+        .rsplit('.')
+        .next()
+        .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()));
+    if name.len() > 253 || !name.split('.').all(valid_label) || numeric_tld {
+        return Err(format!(
+            "Endpoint host `{host}` is neither an IP address nor a valid DNS name"
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that endpoint has `host:port` or `[ipv6]:port` form with valid host and port.
+pub fn validate_endpoint(s: &str) -> std::result::Result<(), String> {
+    let (host, _) = parse_endpoint(s)?;
+    if s.trim().starts_with('[') {
+        validate_endpoint_host(&format!("[{host}]"))
+    } else {
+        validate_endpoint_host(&host)
+    }
+}
+
+/// Parses non-zero port number.
+pub fn parse_port(s: &str) -> std::result::Result<u16, String> {
+    match s.trim().parse::<u16>() {
+        Ok(0) | Err(_) => Err(format!(
+            "Port `{}` must be a number between 1 and 65535",
+            s.trim()
+        )),
+        Ok(port) => Ok(port),
+    }
+}
+
+/// Parses PersistentKeepalive interval in seconds. Empty value and `off` disable keepalive.
+pub fn parse_keepalive(s: &str) -> std::result::Result<Option<u16>, String> {
+    match s.trim() {
+        "" | "off" => Ok(None),
+        s => s.parse::<u16>().map(Some).map_err(|_| {
+            format!("PersistentKeepalive `{s}` must be `off` or a number between 0 and 65535")
+        }),
+    }
+}
+
+/// Parses FwMark of interface: `off` or a 32 bit number, decimal or hexadecimal with `0x`
+/// prefix. Returns value to store, `None` for empty value clearing the field.
+pub fn parse_fwmark(s: &str) -> std::result::Result<Option<String>, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+
+    let valid = s.eq_ignore_ascii_case("off")
+        || match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16).is_ok(),
+            None => s.parse::<u32>().is_ok(),
+        };
+    if valid {
+        Ok(Some(s.to_string()))
+    } else {
+        Err(format!(
+            "FwMark `{s}` must be `off` or a 32 bit number, e.g. 51820 or 0xca6c"
+        ))
+    }
+}
+
+/// Warns about PersistentKeepalive longer than `max` seconds, which is most likely a mistake.
+pub fn keepalive_warning(keepalive: Option<u16>, max: u16) -> Option<String> {
+    keepalive.filter(|&k| k > max).map(|k| {
+        format!(
+            "PersistentKeepalive of {k} seconds is longer than {max} seconds, which is usually a mistake. NAT mappings typically need a keepalive of 25 seconds."
+        )
+    })
+}
+
+/// Sets PersistentKeepalive of every peer after validating it. Returns warning if it's longer
+/// than `max` seconds.
+pub fn set_keepalive_on_all(
+    peers: &mut [Peer],
+    keepalive: &str,
+    max: u16,
+) -> std::result::Result<Option<String>, String> {
+    let keepalive = parse_keepalive(keepalive)?;
+    for peer in peers {
+        peer.persistent_keepalive = keepalive.map(|k| k.to_string());
+    }
+    Ok(keepalive_warning(keepalive, max))
+}
+
+/// Combines host and port into endpoint, wrapping IPv6 addresses in brackets.
+pub fn format_endpoint(host: &str, port: u16) -> String {
+    let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// Directory in which configs written to be shared are placed: the runtime directory if it's
+/// private to the current user, the temporary directory otherwise.
+pub fn shared_configs_dir() -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| {
+            fs::symlink_metadata(dir).is_ok_and(|m| {
+                m.is_dir() && m.uid() == nix::unistd::geteuid().as_raw() && m.mode() & 0o077 == 0
+            })
+        })
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Config written to be shared. The file and its directory are removed when it's dropped.
+#[derive(Debug)]
+pub struct SharedConfig {
+    file: tempfile::NamedTempFile,
+    _dir: tempfile::TempDir,
+}
+
+impl SharedConfig {
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
+
+/// Writes config to be shared as `<name>.conf` into a new directory in `base`, both of them
+/// accessible only by the owner. Neither of them may exist beforehand, so nothing already
+/// placed in `base` is written to.
+pub fn write_shared_config(base: &Path, name: &str, contents: &str) -> Result<SharedConfig> {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    if name.trim_matches('.').is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Invalid config name"));
+    }
+
+    let dir = tempfile::Builder::new()
+        .prefix("wireguard-gui-")
+        .permissions(fs::Permissions::from_mode(0o700))
+        .tempdir_in(base)?;
+    // Files are created exclusively, which doesn't follow a symlink put in their place.
+    let mut file = tempfile::Builder::new()
+        .prefix(&name)
+        .suffix(".conf")
+        .rand_bytes(0)
+        .permissions(fs::Permissions::from_mode(0o600))
+        .tempfile_in(dir.path())?;
+    file.write_all(contents.as_bytes())?;
+
+    Ok(SharedConfig { file, _dir: dir })
+}
+
+/// Writes new file into directory, both of them accessible only by the owner.
+fn write_private_file(dir: &Path, file_name: &str, contents: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+
+    let path = dir.join(file_name);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    file.write_all(contents.as_bytes())?;
+
+    Ok(path)
+}
+
+/// Checks that public key stored in interface is derived from its private key. Interfaces
+/// missing either of the keys pass.
+pub fn check_interface_keys(interface: &Interface) -> std::result::Result<(), String> {
+    check_keypair(interface, generate_public_key)
+}
+
+/// Warning about keys of interface that don't match, shown when it's saved. It's saved anyway,
+/// as the keys may be fixed up later.
+pub fn saved_keys_warning(interface: &Interface) -> Option<String> {
+    saved_keys_warning_with(interface, generate_public_key)
+}
+
+fn saved_keys_warning_with(
+    interface: &Interface,
+    derive: impl FnOnce(String) -> Result<String>,
+) -> Option<String> {
+    check_keypair(interface, derive)
+        .err()
+        .map(|e| format!("Saved, but PrivateKey and # PublicKey do not match: {e}"))
+}
+
+fn check_keypair(
+    interface: &Interface,
+    derive: impl FnOnce(String) -> Result<String>,
+) -> std::result::Result<(), String> {
+    let name = interface.name.as_deref().unwrap_or("unknown");
+    let (Some(private_key), Some(public_key)) = (&interface.private_key, &interface.public_key)
+    else {
+        return Ok(());
+    };
+
+    let derived = derive(private_key.clone())
+        .map_err(|e| format!("Could not derive public key of {name}: {e}"))?;
+    if derived != public_key.trim() {
+        return Err(format!(
+            "PublicKey of {name} doesn't match its PrivateKey, the key derived from it is {derived}"
+        ));
+    }
+    Ok(())
+}
+
+/// Checks configuration more strictly than the parser: every key listed in `interface_fields`
+/// and `peer_fields` has to be set, and set values have to be well-formed. Reports all problems
+/// at once.
+pub fn strict_check(
+    cfg: &WireguardConfig,
+    interface_fields: &[String],
+    peer_fields: &[String],
+) -> std::result::Result<(), Vec<String>> {
+    fn each<T>(v: &str, parse: impl Fn(&str) -> Option<T>) -> bool {
+        v.split(',').all(|e| parse(e.trim()).is_some())
+    }
+    fn check_section(
+        errors: &mut Vec<String>,
+        section: &str,
+        kvs: Vec<(&'static str, Option<String>)>,
+        required: &[String],
+    ) {
+        for field in required {
+            let value = kvs
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(field.trim()));
+            match value {
+                None => errors.push(format!("{section}: unknown required field {field}")),
+                Some((key, v)) if v.as_deref().is_none_or(|v| v.trim().is_empty()) => {
+                    errors.push(format!("{section}: {key} is missing"))
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (key, value) in &kvs {
+            let Some(value) = value.as_deref().filter(|v| !v.trim().is_empty()) else {
+                continue;
+            };
+            let valid = match *key {
+                "Address" => each(value, |e| e.parse::<IpInet>().ok()),
+                "ListenPort" => parse_port(value).is_ok(),
+                "PrivateKey" | "PublicKey" | "# PublicKey" | "PresharedKey" => is_base64_key(value),
+                "MTU" => value.trim().parse::<u16>().is_ok(),
+                "AllowedIPs" => each(value, parse_allowed_ip),
+                "Endpoint" => parse_endpoint(value).is_ok(),
+                "PersistentKeepalive" => parse_keepalive(value).is_ok(),
+                _ => true,
+            };
+            if !valid {
+                errors.push(format!("{section}: invalid {key} `{value}`"));
+            }
+        }
+    }
+
+    let mut errors = vec![];
+    check_section(
+        &mut errors,
+        "Interface",
+        cfg.interface.kvs(),
+        interface_fields,
+    );
+    for (i, peer) in cfg.peers.iter().enumerate() {
+        let section = match &peer.name {
+            Some(name) => format!("Peer {name}"),
+            None => format!("Peer {}", i + 1),
+        };
+        check_section(&mut errors, &section, peer.kvs(), peer_fields);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that interface has a PrivateKey, without one the tunnel can't run as this node.
+/// Interfaces setting the key from a hook with `wg set %i private-key` only get a warning.
+pub fn check_private_key(interface: &Interface) -> std::result::Result<Option<String>, String> {
+    if interface
+        .private_key
+        .as_deref()
+        .is_some_and(|k| !k.trim().is_empty())
+    {
+        return Ok(None);
+    }
+
+    let name = interface.name.as_deref().unwrap_or("unknown");
+    let hooks = [&interface.pre_up, &interface.post_up];
+    if hooks
+        .into_iter()
+        .flatten()
+        .any(|hook| hook.contains("private-key"))
+    {
+        return Ok(Some(format!(
+            "{name} has no PrivateKey, it's expected to be set by a PreUp or PostUp hook"
+        )));
+    }
+    Err(format!("{name} has no PrivateKey"))
+}
+
+/// Checks that every enabled peer has AllowedIPs made of valid addresses, as a peer without
+/// them routes nothing. Blank peers that are still being filled in pass.
+pub fn check_peers_allowed_ips(peers: &[Peer]) -> std::result::Result<(), String> {
+    fn is_blank(v: &Option<String>) -> bool {
+        v.as_deref().is_none_or(|v| v.trim().is_empty())
+    }
+
+    for (i, peer) in peers.iter().enumerate().filter(|(_, p)| !p.disabled) {
+        let fields = [
+            &peer.name,
+            &peer.allowed_ips,
+            &peer.endpoint,
+            &peer.public_key,
+            &peer.persistent_keepalive,
+        ];
+        if fields.into_iter().all(is_blank) {
+            continue;
+        }
+
+        let name = peer
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Peer {}", i + 1));
+        let allowed_ips = peer.allowed_ips.as_deref().unwrap_or_default();
+        if allowed_ips.trim().is_empty() {
+            return Err(format!(
+                "{name} has no AllowedIPs, it wouldn't route any traffic"
+            ));
+        }
+        if let Some(entry) = invalid_allowed_ip(allowed_ips) {
+            return Err(format!("{name} has invalid AllowedIPs entry `{entry}`"));
+        }
+    }
+    Ok(())
+}
+
+/// Network of an AllowedIPs entry. Like wg, host bits are accepted, so `10.0.0.2/24` is
+/// `10.0.0.0/24`. Shortened addresses like `10.0.0` are accepted by `cidr`, but not by wg.
+pub fn parse_allowed_ip(entry: &str) -> Option<IpCidr> {
+    let entry = entry.trim();
+    let address = entry.split_once('/').map_or(entry, |(a, _)| a);
+    address.parse::<IpAddr>().ok()?;
+    entry.parse::<IpInet>().ok().map(|inet| inet.network())
+}
+
+/// First entry of comma separated AllowedIPs that isn't an address or a network.
+fn invalid_allowed_ip(allowed_ips: &str) -> Option<&str> {
+    allowed_ips
+        .split(',')
+        .map(str::trim)
+        .find(|entry| parse_allowed_ip(entry).is_none())
+}
+
+/// Checks that AllowedIPs are a comma separated list of addresses and networks. Empty value
+/// passes, it clears the field.
+pub fn validate_allowed_ips(allowed_ips: &str) -> std::result::Result<(), String> {
+    if allowed_ips.trim().is_empty() {
+        return Ok(());
+    }
+    match invalid_allowed_ip(allowed_ips) {
+        Some(entry) => Err(format!("Invalid AllowedIPs entry `{entry}`")),
+        None => Ok(()),
+    }
+}
+
+/// Problems of configuration found by the checks run before bringing the tunnel up, by the
+/// format checks of `--strict-import` and by the lints.
+pub fn config_issues(cfg: &WireguardConfig) -> Vec<String> {
+    let mut issues = vec![];
+    match check_private_key(&cfg.interface) {
+        Ok(warning) => issues.extend(warning),
+        Err(err) => issues.push(err),
+    }
+    if let Err(err) = check_peers_allowed_ips(&cfg.peers) {
+        issues.push(err);
+    }
+    if let Err(errors) = strict_check(cfg, &[], &[]) {
+        issues.extend(errors);
+    }
+    issues.extend(lint_config(cfg));
+    issues
+}
+
+/// Names of tunnels with their problems, leaving out tunnels without any.
+pub fn validation_report<'a>(
+    configs: impl IntoIterator<Item = &'a WireguardConfig>,
+) -> Vec<(String, Vec<String>)> {
+    configs
+        .into_iter()
+        .map(|cfg| {
+            let name = cfg.interface.name.as_deref().unwrap_or("unknown");
+            (name.to_string(), config_issues(cfg))
         })
+        .filter(|(_, issues)| !issues.is_empty())
+        .collect()
+}
+
+/// Generates keypair of the client behind peer, setting peer's PublicKey. The private key is
+/// only returned, so it can be handed to the client without being stored in the config.
+pub fn generate_peer_keypair(peer: &mut Peer) -> Result<String> {
+    set_generated_keypair(peer, generate_private_key, generate_public_key)
+}
+
+fn set_generated_keypair(
+    peer: &mut Peer,
+    generate: impl FnOnce() -> Result<String>,
+    derive: impl FnOnce(String) -> Result<String>,
+) -> Result<String> {
+    let private_key = generate()?;
+    peer.public_key = Some(derive(private_key.clone())?);
+    Ok(private_key)
+}
+
+/// Directory holding interface keypairs replaced by key rotation.
+pub fn key_backups_dir() -> PathBuf {
+    Path::new(TUNNELS_PATH).join("key-backups")
+}
+
+/// Backs up keypair of interface as `<iface>-<unix time in ms>.key` in `dir`.
+pub fn backup_keypair(
+    dir: &Path,
+    iface: &str,
+    private_key: &str,
+    public_key: &str,
+    time: SystemTime,
+) -> Result<PathBuf> {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(Error::other)?
+        .as_millis();
+    write_private_file(
+        dir,
+        &format!("{iface}-{millis}.key"),
+        &format!("PrivateKey = {private_key}\nPublicKey = {public_key}\n"),
+    )
+}
+
+/// Returns private key from the latest keypair backup of interface in `dir`, if there is one.
+pub fn latest_key_backup(dir: &Path, iface: &str) -> Result<Option<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let mut latest: Option<(u128, PathBuf)> = None;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(millis) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| {
+                n.strip_prefix(iface)?
+                    .strip_prefix('-')?
+                    .strip_suffix(".key")
+            })
+            .and_then(|t| t.parse::<u128>().ok())
+        else {
+            continue;
+        };
+        if latest.as_ref().is_none_or(|(l, _)| *l < millis) {
+            latest = Some((millis, path));
+        }
+    }
+
+    let Some((_, path)) = latest else {
+        return Ok(None);
+    };
+    let private_key = fs::read_to_string(&path)?
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .find(|(k, _)| k.trim() == "PrivateKey")
+        .map(|(_, v)| v.trim().to_string())
+        .ok_or_else(|| Error::other(format!("{} has no PrivateKey", path.display())))?;
+
+    Ok(Some(private_key))
+}
+
+/// Public keys of interface before and after its keys were rotated.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyRotation {
+    pub old_public_key: Option<String>,
+    pub new_public_key: String,
+}
+
+/// Generates new private key of the interface after backing up its current keypair into
+/// `backups_dir`. Stored public key is kept in sync.
+pub fn rotate_keys(
+    interface: &mut Interface,
+    backups_dir: &Path,
+) -> std::result::Result<KeyRotation, String> {
+    rotate_keys_with(
+        interface,
+        backups_dir,
+        generate_private_key,
+        generate_public_key,
+    )
+}
+
+fn rotate_keys_with(
+    interface: &mut Interface,
+    backups_dir: &Path,
+    generate: impl FnOnce() -> Result<String>,
+    derive: impl Fn(String) -> Result<String>,
+) -> std::result::Result<KeyRotation, String> {
+    let name = interface.name.clone().ok_or("Interface has no name")?;
+
+    let old_public_key = match &interface.private_key {
+        Some(private_key) => Some(
+            derive(private_key.clone())
+                .and_then(|public_key| {
+                    backup_keypair(
+                        backups_dir,
+                        &name,
+                        private_key,
+                        &public_key,
+                        SystemTime::now(),
+                    )?;
+                    Ok(public_key)
+                })
+                .map_err(|e| format!("Could not back up keys of {name}: {e}"))?,
+        ),
+        None => None,
+    };
+
+    let private_key = generate().map_err(|e| e.to_string())?;
+    let new_public_key = derive(private_key.clone()).map_err(|e| e.to_string())?;
+    if interface.public_key.is_some() {
+        interface.public_key = Some(new_public_key.clone());
+    }
+    interface.private_key = Some(private_key);
+
+    Ok(KeyRotation {
+        old_public_key,
+        new_public_key,
+    })
+}
+
+/// Rotates keys of every inactive interface, given with whether its tunnel is active, using
+/// `rotate`. Returns report line for every interface with its old and new public key, or why
+/// it was skipped, and whether any keys were rotated.
+pub fn rotate_inactive_keys<'a>(
+    interfaces: impl IntoIterator<Item = (&'a mut Interface, bool)>,
+    mut rotate: impl FnMut(&mut Interface) -> std::result::Result<KeyRotation, String>,
+) -> (Vec<String>, bool) {
+    let mut report = vec![];
+    let mut rotated = false;
+
+    for (interface, active) in interfaces {
+        let name = interface.name.clone().unwrap_or_else(|| "unknown".into());
+        if active {
+            report.push(format!("{name}: skipped, tunnel is active"));
+            continue;
+        }
+        match rotate(interface) {
+            Ok(rotation) => {
+                rotated = true;
+                report.push(format!(
+                    "{name}: {} -> {}",
+                    rotation.old_public_key.as_deref().unwrap_or("(none)"),
+                    rotation.new_public_key
+                ));
+            }
+            Err(e) => report.push(format!("{name}: failed, {e}")),
+        }
+    }
+
+    (report, rotated)
+}
+
+/// Opens the default mail composer with file attached.
+pub fn open_mail_composer(subject: &str, attachment: &Path) -> Result<()> {
+    let mut child = Command::new("xdg-email")
+        .arg("--subject")
+        .arg(subject)
+        .arg("--attach")
+        .arg(attachment)
+        .spawn()?;
+    // Reap the handler once it exits without blocking the UI.
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+
+    #[test]
+    fn fix_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf = dir.path().join("wg0.conf");
+        let other = dir.path().join("notes.txt");
+        fs::write(&conf, "[Interface]\n").unwrap();
+        fs::write(&other, "").unwrap();
+        fs::set_permissions(&conf, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(&other, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let meta = fs::metadata(&conf).unwrap();
+        let results =
+            fix_configs_permissions(dir.path(), Some(meta.uid()), Some(meta.gid())).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, conf);
+        assert!(results[0].1.is_ok());
+
+        assert_eq!(fs::metadata(&conf).unwrap().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(&other).unwrap().mode() & 0o777, 0o644);
+    }
+
+    #[test]
+    fn wrong_ownership() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf = dir.path().join("wg0.conf");
+        fs::write(&conf, "[Interface]\n").unwrap();
+        let meta = fs::metadata(&conf).unwrap();
+
+        assert!(ownership_matches(&conf, Some(meta.uid()), Some(meta.gid())).unwrap());
+        assert!(ownership_matches(&conf, None, None).unwrap());
+        assert!(!ownership_matches(&conf, Some(meta.uid() + 1), None).unwrap());
+        assert!(!ownership_matches(&conf, None, Some(meta.gid() + 1)).unwrap());
+
+        assert!(find_wrong_ownership(dir.path(), Some(meta.uid()), None)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            find_wrong_ownership(dir.path(), Some(meta.uid() + 1), None).unwrap(),
+            vec![conf]
+        );
+    }
+
+    #[test]
+    fn unknown_owner() {
+        assert!(resolve_ownership(Some("no-such-user-wireguard-gui"), None).is_err());
+        assert_eq!(resolve_ownership(None, None).unwrap(), (None, None));
+    }
+
+    #[test]
+    fn endpoints() {
+        assert_eq!(
+            parse_endpoint("vpn.example.com:51820"),
+            Ok(("vpn.example.com".into(), 51820))
+        );
+        assert_eq!(
+            parse_endpoint("192.0.2.1:51820"),
+            Ok(("192.0.2.1".into(), 51820))
+        );
+        assert_eq!(
+            parse_endpoint("[2001:db8::1]:51820"),
+            Ok(("2001:db8::1".into(), 51820))
+        );
+        assert!(parse_endpoint("2001:db8::1:51820").is_err());
+        assert!(parse_endpoint("vpn.example.com").is_err());
+        assert!(parse_endpoint("vpn.example.com:0").is_err());
+        assert!(parse_endpoint("vpn.example.com:65536").is_err());
+        assert!(parse_endpoint(":51820").is_err());
+
+        assert_eq!(validate_endpoint("vpn.example.com:51820"), Ok(()));
+        assert_eq!(validate_endpoint("vpn.example.com.:51820"), Ok(()));
+        assert_eq!(validate_endpoint("192.0.2.1:51820"), Ok(()));
+        assert_eq!(validate_endpoint("[::1]:51820"), Ok(()));
+        assert!(validate_endpoint("example.com").is_err());
+        assert!(validate_endpoint("1.2.3.4:notaport").is_err());
+        assert!(validate_endpoint("[vpn.example.com]:51820").is_err());
+        assert!(validate_endpoint("1.2.3.999:51820").is_err());
+        assert!(validate_endpoint("vpn_example.com:51820").is_err());
+        assert!(validate_endpoint("-vpn.example.com:51820").is_err());
+        assert!(validate_endpoint("vpn..example.com:51820").is_err());
+        assert_eq!(validate_endpoint_host("[2001:db8::1]"), Ok(()));
+        assert!(validate_endpoint_host("[192.0.2.1]").is_err());
+
+        assert_eq!(format_endpoint("192.0.2.1", 51820), "192.0.2.1:51820");
+        assert_eq!(format_endpoint("2001:db8::1", 51820), "[2001:db8::1]:51820");
+        assert_eq!(
+            format_endpoint("[2001:db8::1]", 51820),
+            "[2001:db8::1]:51820"
+        );
+    }
+
+    #[test]
+    fn cmd_timeout() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let status = wait_cmd_with_timeout(&mut child, Duration::from_secs(5)).unwrap();
+        assert!(status.success());
+
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        let err = wait_cmd_with_timeout(&mut child, Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn retry_policy() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.next_delay(0), None);
+
+        let policy = RetryPolicy {
+            attempts: 4,
+            interval: Duration::from_millis(10),
+        };
+        assert_eq!(policy.next_delay(0), Some(Duration::from_millis(10)));
+        assert_eq!(policy.next_delay(1), Some(Duration::from_millis(20)));
+        assert_eq!(policy.next_delay(2), Some(Duration::from_millis(40)));
+        assert_eq!(policy.next_delay(3), None);
+
+        let policy = RetryPolicy {
+            attempts: 3,
+            interval: Duration::ZERO,
+        };
+        let mut calls = 0;
+        assert!(!policy.retry(|| {
+            calls += 1;
+            false
+        }));
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        assert!(policy.retry(|| {
+            calls += 1;
+            calls == 2
+        }));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn iface_kind() {
+        let wg = "DEVTYPE=wireguard\nINTERFACE=wg0\nIFINDEX=7\n";
+        let bridge = "DEVTYPE=bridge\nINTERFACE=wg0\nIFINDEX=3\n";
+        let plain = "INTERFACE=wg0\nIFINDEX=2\n";
+
+        assert_eq!(classify_iface(Some(wg), true), IfaceState::WireguardUp);
+        assert_eq!(
+            classify_iface(Some("DEVTYPE=tun\n"), true),
+            IfaceState::WireguardUp
+        );
+        assert_eq!(
+            classify_iface(Some(bridge), false),
+            IfaceState::NotWireguard
+        );
+        assert_eq!(classify_iface(Some(plain), false), IfaceState::NotWireguard);
+        // Not accessible by `wg show`, but still a WireGuard device.
+        assert_eq!(classify_iface(Some(wg), false), IfaceState::Down);
+        assert_eq!(classify_iface(None, false), IfaceState::Down);
+    }
+
+    #[test]
+    fn invoking_user_resolution() {
+        let resolve = |is_root: bool, vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            resolve_invoking_user(
+                is_root,
+                |key| vars.get(key).cloned(),
+                |uid| (uid == 1000).then(|| String::from("/home/alice")),
+                |name| (name == "bob").then(|| String::from("/home/bob")),
+            )
+        };
+
+        assert_eq!(
+            resolve(true, &[("PKEXEC_UID", "1000")]).as_deref(),
+            Some("/home/alice")
+        );
+        assert_eq!(
+            resolve(true, &[("SUDO_USER", "bob")]).as_deref(),
+            Some("/home/bob")
+        );
+        // pkexec takes precedence, e.g. when it was run from sudo.
+        assert_eq!(
+            resolve(true, &[("PKEXEC_UID", "1000"), ("SUDO_USER", "bob")]).as_deref(),
+            Some("/home/alice")
+        );
+        assert_eq!(
+            resolve(true, &[("PKEXEC_UID", "0"), ("SUDO_USER", "bob")]).as_deref(),
+            Some("/home/bob")
+        );
+        assert_eq!(resolve(true, &[("PKEXEC_UID", "1001")]), None);
+        assert_eq!(resolve(true, &[("PKEXEC_UID", "alice")]), None);
+        assert_eq!(resolve(true, &[("SUDO_USER", "root")]), None);
+        assert_eq!(resolve(true, &[]), None);
+        assert_eq!(resolve(false, &[("PKEXEC_UID", "1000")]), None);
+        assert_eq!(resolve(false, &[("SUDO_USER", "bob")]), None);
+    }
+
+    #[test]
+    fn interfaces_without_sysfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let sysfs = dir.path().join("net");
+        let dev = dir.path().join("dev");
+        fs::write(
+            &dev,
+            "Inter-|   Receive                |  Transmit
+ face |bytes    packets errs drop|bytes    packets errs drop
+    lo:    1000      10    0    0     1000      10    0    0
+  eth0: 2000000    1500    0    0   300000    1200    0    0
+   wg0:    5000      40    0    0     6000      50    0    0
+",
+        )
+        .unwrap();
+
+        let (names, warning) = list_non_wireguard_interfaces(&sysfs, &dev).unwrap();
+        assert_eq!(names, ["eth0", "wg0"]);
+        assert!(warning.unwrap().contains("non-loopback"));
+
+        // Directory exists, but types of its interfaces can't be read.
+        for name in ["lo", "eth0"] {
+            fs::create_dir_all(sysfs.join(name)).unwrap();
+        }
+        let (mut names, warning) = list_non_wireguard_interfaces(&sysfs, &dev).unwrap();
+        names.sort();
+        assert_eq!(names, ["eth0"]);
+        assert!(warning.is_some());
+
+        fs::write(sysfs.join("lo/uevent"), "INTERFACE=lo\nIFINDEX=1\n").unwrap();
+        fs::write(sysfs.join("eth0/uevent"), "INTERFACE=eth0\nIFINDEX=2\n").unwrap();
+        fs::create_dir(sysfs.join("wg0")).unwrap();
+        fs::write(
+            sysfs.join("wg0/uevent"),
+            "DEVTYPE=wireguard\nINTERFACE=wg0\n",
+        )
+        .unwrap();
+        let (mut names, warning) = list_non_wireguard_interfaces(&sysfs, &dev).unwrap();
+        names.sort();
+        assert_eq!(names, ["eth0", "lo"]);
+        assert_eq!(warning, None);
+
+        let missing = dir.path().join("missing");
+        assert!(list_non_wireguard_interfaces(&missing, &missing).is_err());
+    }
+
+    #[test]
+    fn configs_in_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["wg1.conf", "wg0.conf", "notes.txt", "conf"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::create_dir(dir.path().join("old.conf")).unwrap();
+
+        assert_eq!(
+            list_configs(dir.path()).unwrap(),
+            [dir.path().join("wg0.conf"), dir.path().join("wg1.conf")]
+        );
+        assert!(list_configs(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn key_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let derive = |k: String| Ok(format!("pub-{k}"));
+
+        let mut interface = Interface {
+            name: Some("wg0".into()),
+            private_key: Some("old=".into()),
+            public_key: Some("pub-old=".into()),
+            ..Default::default()
+        };
+        let rotation =
+            rotate_keys_with(&mut interface, dir.path(), || Ok("new=".into()), derive).unwrap();
+        assert_eq!(
+            rotation,
+            KeyRotation {
+                old_public_key: Some("pub-old=".into()),
+                new_public_key: "pub-new=".into()
+            }
+        );
+        assert_eq!(interface.private_key.as_deref(), Some("new="));
+        assert_eq!(interface.public_key.as_deref(), Some("pub-new="));
+        assert_eq!(
+            latest_key_backup(dir.path(), "wg0").unwrap().as_deref(),
+            Some("old=")
+        );
+
+        let mut keyless = Interface {
+            name: Some("wg1".into()),
+            ..Default::default()
+        };
+        let rotation =
+            rotate_keys_with(&mut keyless, dir.path(), || Ok("new=".into()), derive).unwrap();
+        assert_eq!(rotation.old_public_key, None);
+        assert_eq!(keyless.public_key, None);
+    }
+
+    #[test]
+    fn batch_key_rotation() {
+        let mut interfaces: Vec<Interface> = ["wg0", "wg1", "wg2"]
+            .into_iter()
+            .map(|name| Interface {
+                name: Some(name.into()),
+                private_key: Some(format!("{name}-old=")),
+                ..Default::default()
+            })
+            .collect();
+        let active = [false, true, false];
+
+        let mut calls = vec![];
+        let (report, rotated) =
+            rotate_inactive_keys(interfaces.iter_mut().zip(active), |interface| {
+                let name = interface.name.clone().unwrap();
+                calls.push(name.clone());
+                if name == "wg2" {
+                    return Err(String::from("no entropy"));
+                }
+                interface.private_key = Some(format!("{name}-new="));
+                Ok(KeyRotation {
+                    old_public_key: Some(format!("{name}-oldpub=")),
+                    new_public_key: format!("{name}-newpub="),
+                })
+            });
+
+        assert_eq!(calls, ["wg0", "wg2"]);
+        assert!(rotated);
+        assert_eq!(
+            report,
+            [
+                "wg0: wg0-oldpub= -> wg0-newpub=",
+                "wg1: skipped, tunnel is active",
+                "wg2: failed, no entropy"
+            ]
+        );
+        assert_eq!(interfaces[0].private_key.as_deref(), Some("wg0-new="));
+        assert_eq!(interfaces[1].private_key.as_deref(), Some("wg1-old="));
+
+        let (report, rotated) =
+            rotate_inactive_keys(interfaces.iter_mut().map(|i| (i, true)), |_| unreachable!());
+        assert_eq!(report.len(), 3);
+        assert!(!rotated);
+    }
+
+    #[test]
+    fn key_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("key-backups");
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+        assert_eq!(latest_key_backup(&backups, "wg0").unwrap(), None);
+
+        let path = backup_keypair(&backups, "wg0", "oldPriv=", "oldPub=", at(100)).unwrap();
+        assert_eq!(path, backups.join("wg0-100000.key"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "PrivateKey = oldPriv=\nPublicKey = oldPub=\n"
+        );
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(&backups).unwrap().mode() & 0o777, 0o700);
+
+        backup_keypair(&backups, "wg0", "newerPriv=", "newerPub=", at(200)).unwrap();
+        backup_keypair(&backups, "wg0-office", "officePriv=", "officePub=", at(300)).unwrap();
+        fs::write(backups.join("wg0-notes.key"), "PrivateKey = bogus").unwrap();
+
+        assert_eq!(
+            latest_key_backup(&backups, "wg0").unwrap().as_deref(),
+            Some("newerPriv=")
+        );
+        assert_eq!(
+            latest_key_backup(&backups, "wg0-office")
+                .unwrap()
+                .as_deref(),
+            Some("officePriv=")
+        );
+        assert_eq!(latest_key_backup(&backups, "wg1").unwrap(), None);
+    }
+
+    #[cfg(feature = "native-keys")]
+    #[test]
+    fn native_keys() {
+        // Key pairs from RFC 7748, section 6.1, as printed by `wg pubkey`.
+        assert_eq!(
+            generate_public_key("dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=".into()).unwrap(),
+            "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo="
+        );
+        assert_eq!(
+            generate_public_key("XasIfmJKikt54X+Lg4AO5m87sSkmGLb9HC+LJ/+I4Os=\n".into()).unwrap(),
+            "3p7bfXt9wbTTW2HC7OQ1Nz+DQ8hbeGdNrfx+FG+IK08="
+        );
+        assert!(generate_public_key("not a key".into()).is_err());
+        assert!(generate_public_key("AAAA".into()).is_err());
+
+        use base64::prelude::*;
+        let private = BASE64_STANDARD
+            .decode(generate_private_key().unwrap())
+            .unwrap();
+        assert_eq!(private.len(), 32);
+        assert_eq!(private[0] & 7, 0);
+        assert_eq!(private[31] & 192, 64);
+    }
+
+    #[test]
+    fn peer_keypair() {
+        let mut cfg = WireguardConfig {
+            peers: vec![Peer::default()],
+            ..Default::default()
+        };
+        let private_key = set_generated_keypair(
+            &mut cfg.peers[0],
+            || Ok(String::from("clientPrivateKey=")),
+            |private_key| Ok(format!("derived from {private_key}")),
+        )
+        .unwrap();
+
+        assert_eq!(private_key, "clientPrivateKey=");
+        assert_eq!(
+            cfg.peers[0].public_key.as_deref(),
+            Some("derived from clientPrivateKey=")
+        );
+        let written = crate::config::write_config(&cfg);
+        assert!(written.contains("PublicKey = derived from clientPrivateKey=\n"));
+        assert_eq!(written.matches("clientPrivateKey=").count(), 1);
+
+        let mut peer = Peer {
+            public_key: Some(String::from("old=")),
+            ..Default::default()
+        };
+        assert!(set_generated_keypair(&mut peer, || Err(Error::other("no wg")), Ok).is_err());
+        assert_eq!(peer.public_key.as_deref(), Some("old="));
+    }
+
+    #[test]
+    fn keypair_check() {
+        let derive = |private_key: String| match private_key.as_str() {
+            "priv=" => Ok(String::from("pub=")),
+            _ => Err(Error::other("invalid key")),
+        };
+        let interface = |private_key: Option<&str>, public_key: Option<&str>| Interface {
+            name: Some("wg0".into()),
+            private_key: private_key.map(String::from),
+            public_key: public_key.map(String::from),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            check_keypair(&interface(Some("priv="), Some("pub=")), derive),
+            Ok(())
+        );
+        assert_eq!(
+            check_keypair(&interface(Some("priv="), None), derive),
+            Ok(())
+        );
+        assert_eq!(
+            check_keypair(&interface(None, Some("pub=")), derive),
+            Ok(())
+        );
+        assert_eq!(
+            check_keypair(&interface(Some("priv="), Some("other=")), derive),
+            Err(String::from(
+                "PublicKey of wg0 doesn't match its PrivateKey, the key derived from it is pub="
+            ))
+        );
+        assert!(check_keypair(&interface(Some("bad"), Some("pub=")), derive).is_err());
+
+        assert_eq!(
+            saved_keys_warning_with(&interface(Some("priv="), Some("pub=")), derive),
+            None
+        );
+        assert_eq!(
+            saved_keys_warning_with(&interface(Some("priv="), Some("other=")), derive),
+            Some(String::from(
+                "Saved, but PrivateKey and # PublicKey do not match: PublicKey of wg0 doesn't match its PrivateKey, the key derived from it is pub="
+            ))
+        );
+    }
+
+    #[cfg(not(feature = "native-keys"))]
+    #[test]
+    fn key_output_conversion() {
+        assert_eq!(
+            key_from_output(
+                "wg genkey",
+                b"dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=\n".to_vec()
+            )
+            .unwrap(),
+            "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo="
+        );
+
+        let err = key_from_output("wg pubkey", b"abc\xff\xfe".to_vec()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "Output of `wg pubkey` is not a utf-8 string: \"abc\u{fffd}\u{fffd}\""
+        );
+    }
+
+    #[test]
+    fn strict_import() {
+        let fields = |f: &[&str]| f.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        let interface_fields = fields(&["PrivateKey", "Address"]);
+        let peer_fields = fields(&["PublicKey", "allowedips"]);
+
+        let compliant = crate::config::parse_config(
+            "[Interface]
+Address = 10.0.0.1/24, fd00::1/64
+ListenPort = 51820
+PrivateKey = dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=
+[Peer]
+PublicKey = hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=
+AllowedIPs = 10.0.0.2/24
+Endpoint = vpn.example.tld:51820
+PersistentKeepalive = 25
+",
+        )
+        .unwrap();
+        assert_eq!(
+            strict_check(&compliant, &interface_fields, &peer_fields),
+            Ok(())
+        );
+
+        let non_compliant = crate::config::parse_config(
+            "[Interface]
+Address = 10.0.0.1
+ListenPort = 70000
+[Peer]
+# Name = Laptop
+PublicKey = notAKey
+Endpoint = vpn.example.tld
+",
+        )
+        .unwrap();
+        assert_eq!(
+            strict_check(&non_compliant, &interface_fields, &peer_fields),
+            Err(vec![
+                String::from("Interface: PrivateKey is missing"),
+                String::from("Interface: invalid ListenPort `70000`"),
+                String::from("Peer Laptop: AllowedIPs is missing"),
+                String::from("Peer Laptop: invalid Endpoint `vpn.example.tld`"),
+                String::from("Peer Laptop: invalid PublicKey `notAKey`"),
+            ])
+        );
+
+        assert_eq!(
+            strict_check(&compliant, &fields(&["Mtu", "Bogus"]), &[]),
+            Err(vec![
+                String::from("Interface: MTU is missing"),
+                String::from("Interface: unknown required field Bogus")
+            ])
+        );
+    }
+
+    #[test]
+    fn private_key_presence() {
+        let mut interface = Interface {
+            name: Some("wg0".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            check_private_key(&interface),
+            Err(String::from("wg0 has no PrivateKey"))
+        );
+
+        interface.public_key = Some("hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=".into());
+        interface.private_key = Some(" ".into());
+        assert!(check_private_key(&interface).is_err());
+
+        // Key managed outside of the config.
+        interface.post_up = Some("wg set %i private-key /run/keys/wg0".into());
+        assert!(check_private_key(&interface).unwrap().is_some());
+
+        interface.private_key = Some("dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=".into());
+        assert_eq!(check_private_key(&interface), Ok(None));
+    }
+
+    #[test]
+    fn peer_allowed_ips() {
+        let peer = |allowed_ips: Option<&str>| Peer {
+            public_key: Some("hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=".into()),
+            allowed_ips: allowed_ips.map(Into::into),
+            ..Default::default()
+        };
+
+        assert_eq!(check_peers_allowed_ips(&[]), Ok(()));
+        assert_eq!(
+            check_peers_allowed_ips(&[peer(Some("10.0.0.2/32, fd00::2/128"))]),
+            Ok(())
+        );
+        assert!(check_peers_allowed_ips(&[peer(None)]).is_err());
+        assert!(check_peers_allowed_ips(&[peer(Some(""))]).is_err());
+        assert!(check_peers_allowed_ips(&[peer(Some(" \t"))]).is_err());
+        assert!(check_peers_allowed_ips(&[peer(Some("10.0.0.2/32,"))]).is_err());
+        assert!(check_peers_allowed_ips(&[peer(Some("10.0.0.300/32"))]).is_err());
+
+        // Peers just added in the editor and disabled peers aren't checked.
+        let blank = Peer {
+            name: Some(" ".into()),
+            ..Default::default()
+        };
+        let disabled = Peer {
+            disabled: true,
+            ..peer(None)
+        };
+        assert_eq!(check_peers_allowed_ips(&[blank, disabled]), Ok(()));
+    }
+
+    #[cfg(feature = "native-keys")]
+    #[test]
+    fn interface_keys() {
+        let mut interface = Interface {
+            private_key: Some("dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=".into()),
+            public_key: Some("hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=".into()),
+            ..Default::default()
+        };
+        assert_eq!(check_interface_keys(&interface), Ok(()));
+
+        interface.public_key = Some("3p7bfXt9wbTTW2HC7OQ1Nz+DQ8hbeGdNrfx+FG+IK08=".into());
+        assert!(check_interface_keys(&interface).is_err());
+    }
+
+    #[test]
+    fn config_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("wg0.conf");
+        let large = dir.path().join("wg1.conf");
+        fs::write(&small, "[Interface]\n").unwrap();
+        fs::write(&large, "# Name = x\n".repeat(1000)).unwrap();
+
+        assert_eq!(read_config_file(&small, 12).unwrap(), "[Interface]\n");
+        assert_eq!(
+            read_config_file(&small, 11).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(
+            read_config_file(&large, 1024).unwrap_err().to_string(),
+            format!("{} is larger than 1024 bytes", large.display())
+        );
+    }
+
+    #[test]
+    fn duplicate_tunnel_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let named = dir.path().join("office.conf");
+        let unnamed = dir.path().join("wg0.conf");
+        let other = dir.path().join("wg1.conf");
+        fs::write(&named, "[Interface]\n# Name = wg0\nAddress = 10.0.0.1/24\n").unwrap();
+        fs::write(&unnamed, "[Interface]\nAddress = 10.0.1.1/24\n").unwrap();
+        fs::write(&other, "[Interface]\nAddress = 10.0.2.1/24\n").unwrap();
+
+        let mut names = HashMap::new();
+        for path in [&named, &other] {
+            let cfg = load_configuration(path).unwrap();
+            assert_eq!(claim_tunnel_name(&cfg, path, &mut names), Ok(()));
+        }
+        let cfg = load_configuration(&unnamed).unwrap();
+        assert_eq!(cfg.interface.name.as_deref(), Some("wg0"));
+        assert_eq!(
+            claim_tunnel_name(&cfg, &unnamed, &mut names),
+            Err(format!(
+                "{}: tunnel wg0 is already loaded from {}, set a different # Name to load both",
+                unnamed.display(),
+                named.display()
+            ))
+        );
+        assert_eq!(names.get("wg0"), Some(&named));
+    }
+
+    #[test]
+    fn tunnels_dir() {
+        let root = tempfile::tempdir().unwrap();
+
+        let missing = root.path().join("etc/wireguard");
+        assert_eq!(ensure_tunnels_dir(&missing), Ok(()));
+        assert_eq!(
+            fs::metadata(&missing).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+        assert_eq!(ensure_tunnels_dir(&missing), Ok(()));
+
+        let file = root.path().join("wireguard");
+        fs::write(&file, "").unwrap();
+        assert_eq!(
+            ensure_tunnels_dir(&file),
+            Err(format!(
+                "Tunnel directory {} is not a directory",
+                file.display()
+            ))
+        );
+        assert!(ensure_tunnels_dir(&file.join("nested"))
+            .unwrap_err()
+            .contains("could not be created"));
+
+        // Root may write anywhere.
+        if !getuid().is_root() {
+            fs::set_permissions(&missing, fs::Permissions::from_mode(0o500)).unwrap();
+            assert!(ensure_tunnels_dir(&missing)
+                .unwrap_err()
+                .contains("is not writable"));
+        }
+    }
+
+    #[test]
+    fn cross_tunnel_conflicts() {
+        let office = crate::config::parse_config(
+            "[Interface]
+# Name = office
+[Peer]
+PublicKey = a
+AllowedIPs = 10.1.0.0/16, fd00::/64
+[Peer]
+PublicKey = b
+AllowedIPs = 192.168.1.0/24
+",
+        )
+        .unwrap();
+        let home = crate::config::parse_config(
+            "[Interface]
+# Name = home
+[Peer]
+PublicKey = c
+AllowedIPs = 10.1.5.1/24, fd01::/64
+#[Peer]
+#PublicKey = d
+#AllowedIPs = 192.168.1.1/32
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            allowed_ips_conflicts(&home, [("office", &office)]),
+            ["AllowedIPs 10.1.5.0/24 overlap with 10.1.0.0/16 of active tunnel office"]
+        );
+        assert_eq!(
+            allowed_ips_conflicts(&office, [("home", &home)]),
+            ["AllowedIPs 10.1.0.0/16 overlap with 10.1.5.0/24 of active tunnel home"]
+        );
+        assert_eq!(allowed_ips_conflicts(&office, []), Vec::<String>::new());
+
+        let v4 = "0.0.0.0/0".parse().unwrap();
+        assert!(!cidrs_overlap(&v4, &"::/0".parse().unwrap()));
+        assert!(cidrs_overlap(&v4, &"10.0.0.1/32".parse().unwrap()));
+        assert!(!cidrs_overlap(
+            &"10.0.0.0/24".parse().unwrap(),
+            &"10.0.1.0/24".parse().unwrap()
+        ));
+    }
+
+    #[test]
+    fn validation_reports() {
+        let valid = crate::config::parse_config(
+            "[Interface]
+# Name = valid
+PrivateKey = aGVsbG8gd29ybGQgaGVsbG8gd29ybGQgaGVsbG8gd28=
+Address = 10.0.0.1/24
+DNS = 10.0.0.53
+[Peer]
+PublicKey = aGVsbG8gd29ybGQgaGVsbG8gd29ybGQgaGVsbG8gd28=
+AllowedIPs = 0.0.0.0/0
+",
+        )
+        .unwrap();
+        let broken = crate::config::parse_config(
+            "[Interface]
+# Name = broken
+Address = 10.0.0.1/24
+ListenPort = 51820x
+[Peer]
+# Name = Laptop
+PublicKey = aGVsbG8gd29ybGQgaGVsbG8gd29ybGQgaGVsbG8gd28=
+Endpoint = vpn.example.tld
+",
+        )
+        .unwrap();
+        let leaky = crate::config::parse_config(
+            "[Interface]
+# Name = leaky
+PrivateKey = aGVsbG8gd29ybGQgaGVsbG8gd29ybGQgaGVsbG8gd28=
+[Peer]
+PublicKey = aGVsbG8gd29ybGQgaGVsbG8gd29ybGQgaGVsbG8gd28=
+AllowedIPs = 0.0.0.0/0
+",
+        )
+        .unwrap();
+
+        let report = validation_report([&valid, &broken, &leaky]);
+        assert_eq!(
+            report
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["broken", "leaky"]
+        );
+        assert_eq!(
+            report[0].1,
+            [
+                "broken has no PrivateKey",
+                "Laptop has no AllowedIPs, it wouldn't route any traffic",
+                "Interface: invalid ListenPort `51820x`",
+                "Peer Laptop: invalid Endpoint `vpn.example.tld`",
+            ]
+        );
+        assert_eq!(report[1].1, lint_config(&leaky));
+        assert_eq!(report[1].1.len(), 1);
+        assert_eq!(validation_report([&valid]), []);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wg0.conf");
+        let content = b"[Interface]\n# Name = caf\xe9\nAddress = 10.0.0.1/24\n";
+        fs::write(&path, content).unwrap();
+
+        let err = read_config_file(&path, 1024).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{} is not valid UTF-8: byte 0xe9 on line 2, column 13",
+                path.display()
+            )
+        );
+
+        let lossy = decode_config(&path, content.to_vec(), true).unwrap();
+        assert_eq!(
+            lossy,
+            "[Interface]\n# Name = caf\u{fffd}\nAddress = 10.0.0.1/24\n"
+        );
+        assert_eq!(decode_config(&path, b"ok".to_vec(), false).unwrap(), "ok");
+    }
+
+    #[test]
+    fn allowed_ips_validation() {
+        assert_eq!(
+            validate_allowed_ips("10.0.0.2/32, fd00::/64,0.0.0.0/0"),
+            Ok(())
+        );
+        assert_eq!(validate_allowed_ips("10.0.0.2"), Ok(()));
+        // Host bits are allowed, as in addresses written by the generator.
+        assert_eq!(validate_allowed_ips("10.0.0.2/24, fd00::2/64"), Ok(()));
+        assert_eq!(parse_allowed_ip("10.0.0.2/24"), "10.0.0.0/24".parse().ok());
+        assert_eq!(validate_allowed_ips(""), Ok(()));
+        assert_eq!(validate_allowed_ips("  "), Ok(()));
+        assert_eq!(
+            validate_allowed_ips("10.0.0.2/32, 10.0.0.0/33"),
+            Err(String::from("Invalid AllowedIPs entry `10.0.0.0/33`"))
+        );
+        assert_eq!(
+            validate_allowed_ips("10.0.0, 10.0.0.0/33"),
+            Err(String::from("Invalid AllowedIPs entry `10.0.0`"))
+        );
+        assert_eq!(
+            validate_allowed_ips("10.0.0.2/32,"),
+            Err(String::from("Invalid AllowedIPs entry ``"))
+        );
+    }
+
+    #[test]
+    fn tunnel_limit() {
+        let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            limit_configuration_paths(paths(&["wg2.conf", "wg0.conf", "wg1.conf"]), 2),
+            (paths(&["wg0.conf", "wg1.conf"]), 1)
+        );
+        assert_eq!(
+            limit_configuration_paths(paths(&["wg1.conf", "wg0.conf"]), 2),
+            (paths(&["wg0.conf", "wg1.conf"]), 0)
+        );
+        assert_eq!(
+            limit_configuration_paths(paths(&["wg0.conf"]), 0),
+            (vec![], 1)
+        );
+    }
+
+    #[test]
+    fn name_collision() {
+        let existing = [String::from("lo"), String::from("eth0")];
+
+        assert_eq!(check_name_collision("wg0", &existing), Ok(()));
+        assert_eq!(check_name_collision("eth", &existing), Ok(()));
+        assert_eq!(
+            check_name_collision("eth0", &existing),
+            Err(String::from(
+                "Interface eth0 already exists and isn't a WireGuard interface"
+            ))
+        );
+        assert_eq!(check_name_collision("eth0", &[]), Ok(()));
+    }
+
+    #[test]
+    fn allowed_ips_normalization() {
+        assert_eq!(
+            normalize_allowed_ips("10.0.0.1/32, 10.0.0.1/32,fd00::1/128, 10.0.0.1", false),
+            "10.0.0.1/32, fd00::1/128"
+        );
+        assert_eq!(
+            normalize_allowed_ips("10.0.0.0/24, 10.0.0.5/32, 10.1.0.0/16", false),
+            "10.0.0.0/24, 10.0.0.5/32, 10.1.0.0/16"
+        );
+        assert_eq!(
+            normalize_allowed_ips("10.0.0.5/32, 10.0.0.0/24, 10.1.0.0/16, 10.0.0.0/24", true),
+            "10.0.0.0/24, 10.1.0.0/16"
+        );
+        assert_eq!(
+            normalize_allowed_ips("0.0.0.0/0, ::/0, 192.168.1.0/24, fd00::/64", true),
+            "0.0.0.0/0, ::/0"
+        );
+        // Host bits are set, so it's kept as it is.
+        assert_eq!(
+            normalize_allowed_ips("10.0.0.5/24, 10.0.0.5/24, , 10.0.0.0/8", true),
+            "10.0.0.5/24, 10.0.0.0/8"
+        );
+    }
+
+    #[test]
+    fn binaries_lookup() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let executable = |path: PathBuf, mode| {
+            fs::write(&path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+        executable(first.path().join("wg"), 0o755);
+        executable(second.path().join("wg-quick"), 0o644);
+        fs::create_dir(second.path().join("ping")).unwrap();
+
+        let path = std::env::join_paths([first.path(), second.path()]).unwrap();
+        assert_eq!(
+            missing_binaries(&["wg", "wg-quick", "ping"], &path),
+            ["wg-quick", "ping"]
+        );
+
+        executable(second.path().join("wg-quick"), 0o755);
+        assert_eq!(
+            missing_binaries(&["wg", "wg-quick"], &path),
+            Vec::<&str>::new()
+        );
+        assert_eq!(missing_binaries(&["wg"], "".as_ref()), ["wg"]);
+    }
+
+    #[test]
+    fn port() {
+        assert_eq!(parse_port(" 51820 "), Ok(51820));
+        assert_eq!(parse_port("65535"), Ok(65535));
+        assert!(parse_port("0").is_err());
+        assert!(parse_port("51820x").is_err());
+        assert!(parse_port("99999").is_err());
+    }
+
+    #[test]
+    fn keepalive() {
+        assert_eq!(parse_keepalive(" 25 "), Ok(Some(25)));
+        assert_eq!(parse_keepalive("0"), Ok(Some(0)));
+        assert_eq!(parse_keepalive("off"), Ok(None));
+        assert_eq!(parse_keepalive(""), Ok(None));
+        assert!(parse_keepalive("65536").is_err());
+        assert!(parse_keepalive("-1").is_err());
+
+        let mut peers = vec![
+            Peer::default(),
+            Peer {
+                persistent_keepalive: Some("10".into()),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(set_keepalive_on_all(&mut peers, "25", 3600), Ok(None));
+        assert!(peers
+            .iter()
+            .all(|p| p.persistent_keepalive.as_deref() == Some("25")));
+
+        assert!(set_keepalive_on_all(&mut peers, "soon", 3600).is_err());
+        assert!(peers
+            .iter()
+            .all(|p| p.persistent_keepalive.as_deref() == Some("25")));
+
+        assert!(set_keepalive_on_all(&mut peers, "7200", 3600)
+            .unwrap()
+            .is_some());
+        assert!(peers
+            .iter()
+            .all(|p| p.persistent_keepalive.as_deref() == Some("7200")));
+
+        set_keepalive_on_all(&mut peers, "off", 3600).unwrap();
+        assert!(peers.iter().all(|p| p.persistent_keepalive.is_none()));
+    }
+
+    #[test]
+    fn fwmark_values() {
+        assert_eq!(parse_fwmark("51820"), Ok(Some(String::from("51820"))));
+        assert_eq!(parse_fwmark(" 0xca6c "), Ok(Some(String::from("0xca6c"))));
+        assert_eq!(parse_fwmark("off"), Ok(Some(String::from("off"))));
+        assert_eq!(parse_fwmark(""), Ok(None));
+        assert_eq!(parse_fwmark("  "), Ok(None));
+
+        assert!(parse_fwmark("4294967296").is_err());
+        assert!(parse_fwmark("0x").is_err());
+        assert!(parse_fwmark("0xfffffffff").is_err());
+        assert!(parse_fwmark("-1").is_err());
+        assert!(parse_fwmark("mark").is_err());
+    }
+
+    #[test]
+    fn keepalive_bounds() {
+        assert_eq!(keepalive_warning(Some(25), 3600), None);
+        assert_eq!(keepalive_warning(Some(3600), 3600), None);
+        assert_eq!(keepalive_warning(None, 0), None);
+        assert_eq!(
+            keepalive_warning(Some(3601), 3600).as_deref(),
+            Some("PersistentKeepalive of 3601 seconds is longer than 3600 seconds, which is usually a mistake. NAT mappings typically need a keepalive of 25 seconds.")
+        );
+        assert!(keepalive_warning(Some(65535), 60).is_some());
+    }
+
+    #[test]
+    fn ignored_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wireguard-gui").join("ignored-configs");
+
+        assert_eq!(read_ignored_configs(&path).unwrap(), Vec::<String>::new());
+
+        let wg0 = Path::new("/etc/wireguard/wg0.conf");
+        add_ignored_config(&path, wg0).unwrap();
+        add_ignored_config(&path, Path::new("/etc/wireguard/office.conf")).unwrap();
+        add_ignored_config(&path, wg0).unwrap();
+
+        assert_eq!(
+            read_ignored_configs(&path).unwrap(),
+            ["/etc/wireguard/wg0.conf", "/etc/wireguard/office.conf"]
+        );
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "/etc/wireguard/wg0.conf\n/etc/wireguard/office.conf\n"
+        );
+    }
+
+    #[test]
+    fn ignored_configs_in_home() {
+        let home = tempfile::tempdir().unwrap();
+        let mut user = User::from_uid(getuid()).unwrap().unwrap();
+        user.dir = home.path().to_path_buf();
+        let path = home.path().join(".config/wireguard-gui/ignored-configs");
+
+        open_ignored_configs(&path, Some(&user))
+            .unwrap()
+            .write_all(b"wg0\n")
+            .unwrap();
+        assert_eq!(read_ignored_configs(&path).unwrap(), ["wg0"]);
+        assert!(open_ignored_configs(&path, Some(&user)).is_ok());
+        assert!(open_ignored_configs(Path::new("/etc/ignored-configs"), Some(&user)).is_err());
+
+        // Symlinks and hard links could point the write at files the user can't change.
+        let target = home.path().join("target");
+        fs::write(&target, "").unwrap();
+        fs::remove_file(&path).unwrap();
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+        assert!(open_ignored_configs(&path, Some(&user)).is_err());
+        fs::remove_file(&path).unwrap();
+        fs::hard_link(&target, &path).unwrap();
+        assert!(open_ignored_configs(&path, Some(&user)).is_err());
+
+        fs::remove_file(&path).unwrap();
+        let config_dir = home.path().join(".config/wireguard-gui");
+        fs::remove_dir(&config_dir).unwrap();
+        std::os::unix::fs::symlink(home.path().join("elsewhere"), &config_dir).unwrap();
+        fs::create_dir(home.path().join("elsewhere")).unwrap();
+        assert!(open_ignored_configs(&path, Some(&user)).is_err());
+        assert!(!home.path().join("elsewhere/ignored-configs").exists());
+    }
+
+    #[test]
+    fn shared_config() {
+        let base = tempfile::tempdir().unwrap();
+
+        let shared = write_shared_config(base.path(), "laptop/alice", "[Interface]\n").unwrap();
+        let path = shared.path().to_path_buf();
+        let dir = path.parent().unwrap().to_path_buf();
+        assert_eq!(path.file_name().unwrap(), "laptop_alice.conf");
+        assert_eq!(dir.parent().unwrap(), base.path());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[Interface]\n");
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+
+        // Every config gets a directory of its own.
+        let other = write_shared_config(base.path(), "laptop/alice", "[Peer]\n").unwrap();
+        assert_ne!(other.path(), path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[Interface]\n");
+
+        drop(shared);
+        assert!(!path.exists());
+        assert!(!dir.exists());
+
+        assert!(write_shared_config(base.path(), "..", "").is_err());
+    }
 }